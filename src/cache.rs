@@ -0,0 +1,177 @@
+use std::cmp::Reverse;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use typst::syntax::package::PackageSpec;
+use typst::syntax::{FileId, VirtualPath};
+use typst::World;
+
+use crate::hash::Fnv;
+use crate::metadata::style_name;
+use crate::TypstWrapperWorld;
+
+/// An on-disk cache of extraction outputs, so that unchanged documents don't
+/// need to be compiled again.
+///
+/// Entries are keyed by everything known before compiling: the main source,
+/// the fonts, the current date and the options. The other files a document
+/// depends on are only known once it is compiled, so an entry records them
+/// with the hash of their contents and is only used while they are unchanged.
+pub struct Cache {
+    dir: PathBuf,
+}
+
+/// A cached output, together with what it was produced from.
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    /// The version of the tool that produced the output. Entries of other
+    /// versions are ignored.
+    version: String,
+    /// The files the compilation read, besides the main source.
+    dependencies: Vec<Dependency>,
+    /// The serialized word boxes.
+    output: String,
+}
+
+/// A file a compilation read, and the hash of its contents at the time.
+#[derive(Serialize, Deserialize)]
+struct Dependency {
+    /// The package the file is in, e.g. `@preview/cetz:0.3.4`.
+    package: Option<String>,
+    /// The path of the file within the root or the package.
+    path: String,
+    hash: u64,
+}
+
+impl Entry {
+    /// Records the output of a compilation in the given world, with the
+    /// files that were read for it. Returns `None` if the output is not text.
+    pub fn new(world: &TypstWrapperWorld, output: &[u8]) -> Option<Entry> {
+        let mut dependencies = Vec::new();
+        for id in world.loaded_files() {
+            let bytes = World::file(world, id).ok()?;
+            dependencies.push(Dependency {
+                package: id.package().map(|package| package.to_string()),
+                path: id.vpath().as_rooted_path().to_string_lossy().into_owned(),
+                hash: hash_bytes(&bytes),
+            });
+        }
+        Some(Entry {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            dependencies,
+            output: String::from_utf8(output.to_vec()).ok()?,
+        })
+    }
+
+    /// Whether the entry was made by this version of the tool, and all the
+    /// files it depends on still have the same contents in the given world.
+    fn is_fresh(&self, world: &TypstWrapperWorld) -> bool {
+        self.version == env!("CARGO_PKG_VERSION")
+            && self.dependencies.iter().all(|dependency| {
+                let package = match &dependency.package {
+                    Some(package) => match package.parse::<PackageSpec>() {
+                        Ok(package) => Some(package),
+                        Err(_) => return false,
+                    },
+                    None => None,
+                };
+                let id = FileId::new(package, VirtualPath::new(&dependency.path));
+                World::file(world, id).is_ok_and(|bytes| hash_bytes(&bytes) == dependency.hash)
+            })
+    }
+}
+
+impl Cache {
+    /// Opens the cache in the given directory, creating it if needed.
+    pub fn new(dir: PathBuf) -> io::Result<Cache> {
+        fs::create_dir_all(&dir)?;
+        Ok(Cache { dir })
+    }
+
+    /// Computes the key of the output of the world's document with the given
+    /// options, which should describe everything that changes the output.
+    ///
+    /// The fonts are identified by the family, variant and flags of each.
+    pub fn key(world: &TypstWrapperWorld, options: &str) -> u64 {
+        let mut hasher = Fnv::new();
+        hasher.part(env!("CARGO_PKG_VERSION").as_bytes());
+        hasher.part(options.as_bytes());
+        hasher.part(world.main_text().as_bytes());
+        let book = world.book();
+        for info in (0..).map_while(|index| book.info(index)) {
+            hasher.part(info.family.as_bytes());
+            hasher.part(style_name(info.variant.style).as_bytes());
+            hasher.part(&info.variant.weight.to_number().to_le_bytes());
+            hasher.part(&info.variant.stretch.to_ratio().get().to_le_bytes());
+            hasher.part(&info.flags.bits().to_le_bytes());
+        }
+        let today = world.today(None);
+        let date = today.map(|today| (today.year(), today.month(), today.day()));
+        hasher.part(format!("{date:?}").as_bytes());
+        hasher.finish()
+    }
+
+    /// Returns the cached output for the key, if there is one and the files
+    /// it was compiled from are unchanged.
+    pub fn get(&self, key: u64, world: &TypstWrapperWorld) -> Option<Vec<u8>> {
+        let json = fs::read_to_string(self.path(key)).ok()?;
+        let entry: Entry = serde_json::from_str(&json).ok()?;
+        entry.is_fresh(world).then(|| entry.output.into_bytes())
+    }
+
+    /// Stores an entry under the key, replacing any previous one.
+    pub fn put(&self, key: u64, entry: &Entry) -> io::Result<()> {
+        // Write to a temporary file first, so that concurrent runs never read
+        // a partial entry.
+        let path = self.path(key);
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, serde_json::to_vec(entry)?)?;
+        fs::rename(temporary, path)
+    }
+
+    /// Removes entries that were last written longer than `max_age` ago, and
+    /// then the oldest entries until all of them take up at most `max_size`
+    /// bytes.
+    pub fn evict(&self, max_age: Duration, max_size: u64) -> io::Result<()> {
+        let now = SystemTime::now();
+        let mut entries = Vec::new();
+        for dir_entry in fs::read_dir(&self.dir)? {
+            let path = dir_entry?.path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            let metadata = fs::metadata(&path)?;
+            let modified = metadata.modified()?;
+            if now.duration_since(modified).unwrap_or_default() > max_age {
+                fs::remove_file(&path)?;
+            } else {
+                entries.push((modified, metadata.len(), path));
+            }
+        }
+
+        // Newest first, so that the oldest entries are removed.
+        entries.sort_by_key(|&(modified, _, _)| Reverse(modified));
+        let mut total = 0;
+        for (_, size, path) in entries {
+            total += size;
+            if total > max_size {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{key:016x}.json"))
+    }
+}
+
+/// Hashes the contents of a file. Like the keys, these hashes are stored, so
+/// they must not change between platforms and Rust versions, as those of
+/// the std hasher may.
+fn hash_bytes(bytes: &[u8]) -> u64 {
+    Fnv::hash(bytes)
+}
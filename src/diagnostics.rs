@@ -0,0 +1,70 @@
+use std::fmt::Write;
+
+use typst::World;
+use typst::diag::{Severity, SourceDiagnostic};
+use typst::syntax::Span;
+
+/// Formats a diagnostic in the style of rustc: the message, followed by the
+/// location and the offending source line with the span underlined, and one
+/// line per trace point and hint.
+///
+/// ```text
+/// error: unknown variable: foo
+///  --> main.typ:3:2
+///   |
+/// 3 | #foo
+///   |  ^^^
+///   = hint: ...
+/// ```
+pub fn format_diagnostic(world: &dyn World, diagnostic: &SourceDiagnostic) -> String {
+    let severity = match diagnostic.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    };
+
+    let mut output = format!("{severity}: {}", diagnostic.message);
+    if let Some(snippet) = format_snippet(world, diagnostic.span) {
+        write!(output, "\n{snippet}").unwrap();
+    }
+    for point in &diagnostic.trace {
+        write!(output, "\n  = note: {}", point.v).unwrap();
+    }
+    for hint in &diagnostic.hints {
+        write!(output, "\n  = hint: {hint}").unwrap();
+    }
+    output
+}
+
+/// Prints diagnostics to stderr.
+pub fn print_diagnostics(world: &dyn World, diagnostics: &[SourceDiagnostic]) {
+    for diagnostic in diagnostics {
+        eprintln!("{}\n", format_diagnostic(world, diagnostic));
+    }
+}
+
+/// Formats the location of a span and the source line it starts on, with the
+/// span underlined. Returns `None` if the span doesn't point into a source file.
+fn format_snippet(world: &dyn World, span: Span) -> Option<String> {
+    let id = span.id()?;
+    let source = world.source(id).ok()?;
+    let range = source.range(span)?;
+    let line = source.byte_to_line(range.start)?;
+    let column = source.byte_to_column(range.start)?;
+    let line_range = source.line_to_range(line)?;
+    let line_text = source.text()[line_range.clone()].trim_end_matches(['\r', '\n']);
+
+    // Underline the span up to the end of its first line, with at least one caret.
+    let underlined_end = range.end.min(line_range.start + line_text.len()).max(range.start);
+    let carets = source.text()[range.start..underlined_end].chars().count().max(1);
+
+    let line_number = (line + 1).to_string();
+    let gutter = " ".repeat(line_number.len());
+    Some(format!(
+        "{gutter}--> {}:{}:{}\n{gutter} |\n{line_number} | {line_text}\n{gutter} | {}{}",
+        id.vpath().as_rootless_path().display(),
+        line + 1,
+        column + 1,
+        " ".repeat(column),
+        "^".repeat(carets),
+    ))
+}
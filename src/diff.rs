@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::WordBox;
+
+/// How the boxes of two versions of a document differ.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BoxDiff {
+    /// The boxes of the second version that have no counterpart in the first.
+    pub added: Vec<WordBox>,
+    /// The boxes of the first version that have no counterpart in the second.
+    pub removed: Vec<WordBox>,
+    /// The boxes that are in both versions, but not in the same place.
+    pub moved: Vec<Move>,
+    /// How many boxes are in the same place in both versions.
+    pub unchanged: usize,
+}
+
+/// A box that is in both versions of a document, but not in the same place.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Move {
+    /// The box in the first version.
+    pub from: WordBox,
+    /// The box in the second version.
+    pub to: WordBox,
+    /// How far the box moved to the right, in pt. Its page may differ.
+    pub dx: f64,
+    /// How far the box moved down, in pt.
+    pub dy: f64,
+    /// How much wider the box became, in pt.
+    pub dwidth: f64,
+    /// How much taller the box became, in pt.
+    pub dheight: f64,
+}
+
+/// Matches the boxes of two versions of a document by their text and kind.
+///
+/// A box is unchanged if the other version has a box with the same text and
+/// kind on the same page whose coordinates are all within `tolerance` pt of
+/// its own. The remaining boxes with the same text and kind are then paired
+/// in the order they are in, so that boxes that moved because the text
+/// before them changed (e.g. words that reflowed onto the next line) are
+/// still matched. Boxes that are left over were added or removed.
+pub fn diff_boxes(before: &[WordBox], after: &[WordBox], tolerance: f64) -> BoxDiff {
+    let key = |word_box: &WordBox| (word_box.word.clone(), word_box.kind.clone());
+    let mut unmatched: HashMap<(String, String), Vec<usize>> = HashMap::new();
+    for (index, word_box) in before.iter().enumerate() {
+        unmatched.entry(key(word_box)).or_default().push(index);
+    }
+
+    // Boxes that stayed where they were.
+    let mut unchanged = 0;
+    let mut moved_candidates = Vec::new();
+    for (index, word_box) in after.iter().enumerate() {
+        let candidates = unmatched.entry(key(word_box)).or_default();
+        match candidates.iter().position(|&candidate| is_same_place(&before[candidate], word_box, tolerance)) {
+            Some(position) => {
+                candidates.remove(position);
+                unchanged += 1;
+            }
+            None => moved_candidates.push(index),
+        }
+    }
+
+    // The others are paired in order.
+    let mut added = Vec::new();
+    let mut moved = Vec::new();
+    for index in moved_candidates {
+        let to = &after[index];
+        let candidates = unmatched.entry(key(to)).or_default();
+        if candidates.is_empty() {
+            added.push(to.clone());
+            continue;
+        }
+        let from = &before[candidates.remove(0)];
+        moved.push(Move {
+            dx: to.x - from.x,
+            dy: to.y - from.y,
+            dwidth: to.width - from.width,
+            dheight: to.height - from.height,
+            from: from.clone(),
+            to: to.clone(),
+        });
+    }
+    let mut removed: Vec<usize> = unmatched.into_values().flatten().collect();
+    removed.sort_unstable();
+
+    BoxDiff { added, removed: removed.into_iter().map(|index| before[index].clone()).collect(), moved, unchanged }
+}
+
+fn is_same_place(a: &WordBox, b: &WordBox, tolerance: f64) -> bool {
+    a.page == b.page
+        && [(a.x, b.x), (a.y, b.y), (a.width, b.width), (a.height, b.height)]
+            .iter()
+            .all(|(a, b)| (a - b).abs() <= tolerance)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{extract_word_boxes, ExtractOptions};
+
+    fn boxes(source: &str) -> Vec<WordBox> {
+        extract_word_boxes(source, Path::new("."), &ExtractOptions::default()).unwrap()
+    }
+
+    fn words(boxes: &[WordBox]) -> Vec<&str> {
+        boxes.iter().map(|word_box| word_box.word.as_str()).collect()
+    }
+
+    #[test]
+    fn boxes_in_the_same_place_are_unchanged() {
+        let before = boxes("Hello world");
+        let diff = diff_boxes(&before, &boxes("Hello world"), 0.5);
+        assert_eq!(diff.unchanged, 2);
+        assert!(diff.added.is_empty() && diff.removed.is_empty() && diff.moved.is_empty());
+    }
+
+    #[test]
+    fn boxes_pushed_down_have_moved() {
+        let diff = diff_boxes(&boxes("Hello world"), &boxes("#v(20pt) Hello world"), 0.5);
+        assert_eq!(diff.unchanged, 0);
+        let moves: Vec<(&str, f64, f64)> =
+            diff.moved.iter().map(|moved| (moved.to.word.as_str(), moved.dx, moved.dy.round())).collect();
+        assert_eq!(moves, [("Hello", 0.0, 20.0), ("world", 0.0, 20.0)]);
+    }
+
+    #[test]
+    fn a_word_that_is_inserted_is_added_and_moves_the_rest() {
+        let diff = diff_boxes(&boxes("Hello world"), &boxes("Hello brave world"), 0.5);
+        assert_eq!(words(&diff.added), ["brave"]);
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.moved.len(), 1);
+        assert!(diff.moved[0].dx > 0.0 && diff.moved[0].dy == 0.0);
+    }
+
+    #[test]
+    fn a_word_that_is_deleted_is_removed() {
+        let diff = diff_boxes(&boxes("Hello brave world"), &boxes("Hello world"), 0.5);
+        assert_eq!(words(&diff.removed), ["brave"]);
+        assert!(diff.added.is_empty());
+        assert_eq!(diff.moved[0].to.word, "world");
+        assert!(diff.moved[0].dx < 0.0);
+    }
+}
@@ -0,0 +1,74 @@
+use std::fmt;
+
+/// The text encoding of a source file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Encoding {
+    /// UTF-8, as Typst expects.
+    #[default]
+    Utf8,
+    /// ISO 8859-1, where every byte is the character with the same code.
+    Latin1,
+    /// UTF-16 in little-endian byte order.
+    Utf16le,
+    /// UTF-16 in big-endian byte order.
+    Utf16be,
+}
+
+/// The error when bytes are not valid text in the expected encoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeError {
+    /// The encoding the bytes were decoded as.
+    pub encoding: Encoding,
+    /// The offset of the first byte that is not part of a valid character.
+    pub offset: usize,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let encoding = match self.encoding {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Latin1 => "Latin-1",
+            Encoding::Utf16le => "UTF-16LE",
+            Encoding::Utf16be => "UTF-16BE",
+        };
+        write!(f, "not valid {encoding}: invalid byte sequence at byte offset {}", self.offset)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Decodes a source file, removing a leading byte order mark.
+pub fn decode(bytes: &[u8], encoding: Encoding) -> Result<String, DecodeError> {
+    let error = |offset| DecodeError { encoding, offset };
+    match encoding {
+        Encoding::Utf8 => {
+            let (bom_len, bytes) = match bytes.strip_prefix(b"\xef\xbb\xbf") {
+                Some(rest) => (3, rest),
+                None => (0, bytes),
+            };
+            match std::str::from_utf8(bytes) {
+                Ok(text) => Ok(text.to_owned()),
+                Err(utf8_error) => Err(error(bom_len + utf8_error.valid_up_to())),
+            }
+        }
+        Encoding::Latin1 => Ok(bytes.iter().map(|&byte| char::from(byte)).collect()),
+        Encoding::Utf16le | Encoding::Utf16be => {
+            let pairs = bytes.chunks_exact(2);
+            let remainder = pairs.remainder();
+            let units = pairs.map(|pair| match encoding {
+                Encoding::Utf16le => u16::from_le_bytes([pair[0], pair[1]]),
+                _ => u16::from_be_bytes([pair[0], pair[1]]),
+            });
+            let mut text = String::with_capacity(bytes.len() / 2);
+            for decoded in char::decode_utf16(units) {
+                let c = decoded.map_err(|_| error(2 * text.encode_utf16().count()))?;
+                text.push(c);
+            }
+            // A trailing byte that is only half of a code unit.
+            if !remainder.is_empty() {
+                return Err(error(bytes.len() - 1));
+            }
+            Ok(text.strip_prefix('\u{feff}').map(str::to_owned).unwrap_or(text))
+        }
+    }
+}
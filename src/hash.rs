@@ -0,0 +1,35 @@
+/// An incremental 64-bit FNV-1a hash. Unlike the std hasher, it gives the
+/// same hash on every platform and Rust version, so its hashes can be stored
+/// and shown to users.
+pub(crate) struct Fnv(u64);
+
+impl Fnv {
+    pub(crate) fn new() -> Self {
+        Fnv(0xcbf29ce484222325)
+    }
+
+    /// Adds bytes to the hash.
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x100000001b3);
+        }
+    }
+
+    /// Adds a part, preceded by its length so that the end of one part and
+    /// the start of the next can't be mistaken for each other.
+    pub(crate) fn part(&mut self, bytes: &[u8]) {
+        self.write(&(bytes.len() as u64).to_le_bytes());
+        self.write(bytes);
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+
+    /// The hash of a single sequence of bytes.
+    pub(crate) fn hash(bytes: &[u8]) -> u64 {
+        let mut hasher = Fnv::new();
+        hasher.write(bytes);
+        hasher.finish()
+    }
+}
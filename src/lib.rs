@@ -0,0 +1,996 @@
+pub mod cache;
+pub mod diagnostics;
+pub mod diff;
+pub mod encoding;
+mod hash;
+pub mod metadata;
+pub mod output;
+pub mod pdf;
+pub mod query;
+pub mod reading_order;
+pub mod render;
+pub mod tables;
+pub mod vfs;
+pub mod word_analysis;
+pub mod world;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+
+use regex::Regex;
+use serde::Serialize;
+use typst::diag::{SourceDiagnostic, Warned};
+use typst::layout::{Dir, PagedDocument};
+use typst::syntax::{FileId, Source, Span, SyntaxKind};
+
+pub use pdf::render_to_pdf_with_boxes;
+pub use render::{
+    kind_color, merged_page_offsets, render_pages_to_png, render_pages_to_png_with_boxes, render_to_png,
+    render_to_png_with_boxes, render_to_svg_with_boxes, RenderError, RenderOptions, HIGHLIGHT_COLOR,
+};
+pub use word_analysis::{
+    blocks_with_boxes, chars_with_boxes, glyphs_with_boxes, lines_with_boxes, sentences_with_boxes,
+    unhyphenated_words_with_boxes, word_trees_with_boxes, words_with_boxes, Element, TextOptions, Word,
+};
+pub use metadata::Metadata;
+pub use world::{TypstWrapperWorld, WorldBuilder};
+
+/// A word (or group of words) together with its bounding box in pt.
+///
+/// Coordinates are relative to the top-left corner of the page the word is on.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct WordBox {
+    /// Index of the page the word is on, starting at 0.
+    pub page: usize,
+    /// The position of the box in reading order among all boxes, starting at
+    /// 0, for boxes that are not the children of another box.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order: Option<usize>,
+    pub word: String,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    /// The font family, if the box covers a single text run. A run has a
+    /// single font, so a word that falls back to another font for some of
+    /// its characters is split where the font changes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font: Option<String>,
+    /// The subfamily name of the font, e.g. `Bold Italic`, if the box covers
+    /// a single text run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subfamily: Option<String>,
+    /// The weight of the font, from 100 (thin) to 900 (black), if the box
+    /// covers a single text run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u16>,
+    /// The style of the font, `normal`, `italic` or `oblique`, if the box
+    /// covers a single text run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+    /// The stretch of the font as a ratio of the normal width, if the box
+    /// covers a single text run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stretch: Option<f64>,
+    /// The font size in pt, if the box covers a single text run. For a word
+    /// joined from parts on different lines, that of its first part.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_size: Option<f64>,
+    /// Whether the word was joined from parts in different font sizes, only
+    /// written if it was.
+    #[serde(skip_serializing_if = "is_false")]
+    pub mixed_sizes: bool,
+    /// The fill color as a hex string, or `non-solid` for gradients and tilings.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    /// The rotation in degrees (clockwise), if the word is rotated. The box
+    /// is then the axis-aligned bounding box of the rotated word.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<f64>,
+    /// The corners of the word as `[x, y]` if it is rotated, scaled or
+    /// skewed, clockwise from its top-left corner (before the transform), so
+    /// that its exact outline can be drawn, as in the overlays. The order
+    /// is the same with `--origin bottom-left` (see [`WordBox::flipped`]).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quad: Option<[(f64, f64); 4]>,
+    /// What the box covers: `word`, `char`, `glyph`, `line`, `sentence`, `cell`, or for groups the name
+    /// of the innermost element they were laid out for, e.g. `heading`,
+    /// `link`, or `group` for plain frame groups.
+    pub kind: String,
+    /// The URL of the link the word is part of, or for links within the
+    /// document `#label` or `#page=N&x=X&y=Y` (in pt).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+    /// The label of the element, for boxes of elements found by a query.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// For a cell of a table or grid, the index of the table among those
+    /// whose cells were found, in the order they were laid out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub table: Option<usize>,
+    /// For a cell, the index of the first row it is in, from 0.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub row: Option<usize>,
+    /// For a cell, the index of the first column it is in, from 0.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub col: Option<usize>,
+    /// For a cell, how many rows it spans.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rowspan: Option<usize>,
+    /// For a cell, how many columns it spans.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub colspan: Option<usize>,
+    /// How many characters of the word are missing from all available fonts,
+    /// so that they are shown as the `.notdef` glyph (usually a box) and the
+    /// box's width is not that of the actual text. For lines, blocks,
+    /// sentences and groups, the total of the words they are made of.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub missing_glyph_count: usize,
+    /// How reliable the box is, from 0 (e.g. only missing glyphs) to 1, if
+    /// the box covers a single text run. It is lowered by missing glyphs,
+    /// glyphs without an advance and characters that are not letters or
+    /// digits.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    /// The y-coordinate of the baseline, if the box covers a single text run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<f64>,
+    /// How far the font reaches above the baseline, if the box covers a
+    /// single text run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ascender: Option<f64>,
+    /// How far the font reaches below the baseline (negative), if the box
+    /// covers a single text run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub descender: Option<f64>,
+    /// For a character or glyph, the index of the word it is part of, counting only
+    /// the words (not groups) of the document, so that characters can be
+    /// joined back into words.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub word_index: Option<usize>,
+    /// The language the word was set in, like `de` or `en-GB`, if the box
+    /// covers a single text run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    /// The script most characters of the word are in, like `Latin`,
+    /// `Cyrillic` or `Han`, if the box covers a single text run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+    /// The direction the text was set in, `ltr` or `rtl`, if the box covers
+    /// a single text run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    /// For a glyph, its index in the font.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub glyph_id: Option<u16>,
+    /// For a glyph, the byte range `[start, end)` in the text of its run
+    /// that it was shaped from. It covers several characters for ligatures.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cluster: Option<(usize, usize)>,
+    /// For a glyph, how far it moves the pen.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x_advance: Option<f64>,
+    /// For a glyph, how far it is moved right from the pen position. There is
+    /// no `y_offset`, as glyphs in Typst 0.13 have none: Typst starts a new
+    /// text item where the shaper moves glyphs up or down, so such a glyph's
+    /// offset is in its `baseline` and box instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x_offset: Option<f64>,
+    /// The byte offset in the main source where the text of the word
+    /// starts, if it comes from there and the options ask for it. Text made
+    /// by the document, like page numbers, has none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub src_start: Option<usize>,
+    /// The byte offset in the main source where the text of the word ends.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub src_end: Option<usize>,
+    /// The file the text of the word comes from, if the options ask for it.
+    /// Text made by the document has the file of the code that made it, if
+    /// it is known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// The line in `file` where the text of the word starts, from 1.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    /// The column in `line` where the text of the word starts, in
+    /// characters from 1.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+    /// For a sentence that wraps or a word that is hyphenated across lines,
+    /// its box on each line it is on, which together cover much less than
+    /// the box around all of them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rects: Vec<Rect>,
+    /// The boxes this box is made of, e.g. the words of a line.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<WordBox>,
+}
+
+/// A rectangle in pt, relative to the top-left corner of its page.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl From<word_analysis::BBox> for Rect {
+    fn from((x, y, width, height): word_analysis::BBox) -> Self {
+        Rect { x, y, width, height }
+    }
+}
+
+impl WordBox {
+    /// Creates the box of a word found on the given page.
+    pub fn new(page: usize, word: Word) -> WordBox {
+        let (x, y, width, height) = word.bbox;
+        WordBox {
+            page,
+            order: None,
+            word: word.text,
+            x,
+            y,
+            width,
+            height,
+            font: word.font,
+            subfamily: word.subfamily,
+            weight: word.variant.map(|variant| variant.weight.to_number()),
+            style: word.variant.map(|variant| metadata::style_name(variant.style).to_string()),
+            stretch: word.variant.map(|variant| variant.stretch.to_ratio().get()),
+            font_size: word.font_size,
+            mixed_sizes: word.mixed_sizes,
+            color: word.color,
+            rotation: word.rotation,
+            quad: word.quad,
+            kind: word.kind,
+            link: word.link,
+            label: None,
+            table: None,
+            row: None,
+            col: None,
+            rowspan: None,
+            colspan: None,
+            missing_glyph_count: word.missing_glyphs,
+            score: word.score,
+            baseline: word.baseline,
+            ascender: word.metrics.map(|(ascender, _)| ascender),
+            descender: word.metrics.map(|(_, descender)| descender),
+            word_index: word.word_index,
+            lang: word.lang,
+            script: word.script,
+            dir: word.dir.map(|dir| if dir == Dir::RTL { "rtl" } else { "ltr" }.to_string()),
+            glyph_id: word.shaping.as_ref().map(|shaping| shaping.glyph_id),
+            cluster: word.shaping.as_ref().map(|shaping| (shaping.cluster.start, shaping.cluster.end)),
+            x_advance: word.shaping.as_ref().map(|shaping| shaping.x_advance),
+            x_offset: word.shaping.as_ref().map(|shaping| shaping.x_offset),
+            src_start: None,
+            src_end: None,
+            file: None,
+            line: None,
+            column: None,
+            rects: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Returns a copy of the box moved down by `offset`, e.g. to the position
+    /// of its page in the merged image of all pages.
+    pub fn moved_down(&self, offset: f64) -> WordBox {
+        WordBox {
+            y: self.y + offset,
+            baseline: self.baseline.map(|baseline| baseline + offset),
+            quad: self.quad.map(|quad| quad.map(|(x, y)| (x, y + offset))),
+            rects: self.rects.iter().map(|rect| Rect { y: rect.y + offset, ..*rect }).collect(),
+            children: self.children.iter().map(|child| child.moved_down(offset)).collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a copy of the box with its y-coordinates measured upwards from
+    /// `height`, e.g. from the bottom edge of its page of that height, as in
+    /// PDF. Each y-coordinate becomes `height - y`, so the box's `y` becomes
+    /// `height - (y + height of the box)`, that of its bottom edge, and its
+    /// baseline `height - baseline`. The ascender and descender are
+    /// distances from the baseline and stay the same.
+    ///
+    /// The corners of `quad` stay in the same order, from the word's
+    /// top-left corner, so they still go clockwise as seen on the page. With
+    /// y pointing up that is the reverse winding in the coordinates, e.g.
+    /// the shoelace formula gives a negative area where it was positive.
+    pub fn flipped(&self, height: f64) -> WordBox {
+        WordBox {
+            y: height - self.y - self.height,
+            baseline: self.baseline.map(|baseline| height - baseline),
+            quad: self.quad.map(|quad| quad.map(|(x, y)| (x, height - y))),
+            rects: self.rects.iter().map(|rect| Rect { y: height - rect.y - rect.height, ..*rect }).collect(),
+            children: self.children.iter().map(|child| child.flipped(height)).collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a copy of the box with its coordinates multiplied by `factor`,
+    /// e.g. to convert from pt to pixels.
+    pub fn scaled(&self, factor: f64) -> WordBox {
+        WordBox {
+            x: self.x * factor,
+            y: self.y * factor,
+            width: self.width * factor,
+            height: self.height * factor,
+            baseline: self.baseline.map(|baseline| baseline * factor),
+            ascender: self.ascender.map(|ascender| ascender * factor),
+            descender: self.descender.map(|descender| descender * factor),
+            x_advance: self.x_advance.map(|x_advance| x_advance * factor),
+            x_offset: self.x_offset.map(|x_offset| x_offset * factor),
+            quad: self.quad.map(|quad| quad.map(|(x, y)| (x * factor, y * factor))),
+            rects: self
+                .rects
+                .iter()
+                .map(|rect| Rect {
+                    x: rect.x * factor,
+                    y: rect.y * factor,
+                    width: rect.width * factor,
+                    height: rect.height * factor,
+                })
+                .collect(),
+            children: self.children.iter().map(|child| child.scaled(factor)).collect(),
+            ..self.clone()
+        }
+    }
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// The unit of text that each extracted box covers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Granularity {
+    /// One box per glyph, including whitespace and delimiters, with how it
+    /// was shaped and the index of its word.
+    Glyph,
+    /// One box per character (grapheme cluster), with the index of its word.
+    Char,
+    /// One box per word, plus boxes for the groups words are laid out in.
+    #[default]
+    Word,
+    /// One box per line of text, with the boxes of its words as children.
+    Line,
+    /// One box per paragraph, heading or list item, with the boxes of its
+    /// lines as children.
+    Block,
+    /// One box per sentence, with the boxes of its words as children, and
+    /// its box on each line under `rects` if it wraps.
+    Sentence,
+}
+
+/// The order in which boxes are numbered and returned.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReadingOrder {
+    /// Page by page, in the order Typst laid the text out, which is usually
+    /// the order of the source, but e.g. places floating figures where they
+    /// are in the source.
+    #[default]
+    Layout,
+    /// Page by page, columns from left to right and within them from top to
+    /// bottom, as found from the gaps between the boxes. Boxes that overlap
+    /// are ordered by y, then x. Groups follow the last of the words within
+    /// them.
+    Visual,
+}
+
+/// Which boxes word granularity yields, as words are laid out within groups
+/// like headings, links and figures.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputLevel {
+    /// Only the boxes of words, whether they are in a group or not.
+    Words,
+    /// The boxes of the outermost groups and of the words that are not in
+    /// any group, so that each word is covered exactly once. Groups nested
+    /// in other groups are left out.
+    Groups,
+    /// The boxes of all words, each outermost group after its words.
+    #[default]
+    Both,
+}
+
+/// How far up the boxes of text reach from the baseline. They always reach
+/// down to the font's descender.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum BoxMode {
+    /// To the font's ascender, above the tallest letters.
+    #[default]
+    Ascent,
+    /// To the height of the font's capital letters, which fits uppercase
+    /// Latin text much more tightly.
+    CapHeight,
+    /// To the height of the font's lowercase `x`.
+    XHeight,
+}
+
+/// Where text is split into words. In all modes, a glyph for characters of
+/// several words, like a ligature, is split among them in proportion to their
+/// characters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SplitMode {
+    /// Only at whitespace, so `e.g.` and `3.14` stay whole.
+    Whitespace,
+    /// At whitespace and ASCII punctuation.
+    #[default]
+    #[value(alias = "simple")]
+    Punctuation,
+    /// At the Unicode word boundaries (UAX #29), which keep `don't`, `e.g`
+    /// and `3.14` together and also split text without spaces, like Chinese.
+    #[value(alias = "uax29")]
+    Unicode,
+    /// Like `punctuation`, but each Chinese, Japanese or Korean character is
+    /// a word of its own, and full-width punctuation like `、` and `。` is a
+    /// delimiter too.
+    CjkChars,
+}
+
+/// Options that control how words are extracted from a document.
+#[derive(Debug, Clone)]
+pub struct ExtractOptions {
+    /// The unit of text that each box covers.
+    pub granularity: Granularity,
+    /// With word granularity, which of the boxes of words and groups to
+    /// return.
+    pub output_level: OutputLevel,
+    /// The order in which boxes are numbered and returned.
+    pub reading_order: ReadingOrder,
+    /// Whether to include boxes of whitespace.
+    pub include_whitespace: bool,
+    /// Whether to include boxes of delimiters.
+    pub include_delimiters: bool,
+    /// Where text is split into words.
+    pub split_mode: SplitMode,
+    /// With word granularity, whether to join the parts of a word that is
+    /// hyphenated at the end of a line into one box, with the box of each
+    /// part under `rects`. Not done for trees or when only groups are
+    /// returned.
+    pub merge_hyphenation: bool,
+    /// With word granularity, whether the symbols of equations are boxes of
+    /// their own within a group of kind `equation`, instead of each equation
+    /// being a single box of that kind with all of its text.
+    pub split_math: bool,
+    /// Whether all Unicode punctuation, like curly quotes, dashes and `…`,
+    /// separates words, instead of only ASCII punctuation.
+    pub unicode_delimiters: bool,
+    /// More characters that separate words like ASCII punctuation does, e.g.
+    /// `“”–…` for some of the Unicode punctuation only.
+    pub delimiters: String,
+    /// Whether boxes only reach as far up and down as the glyph outlines.
+    pub tight_boxes: bool,
+    /// How far up boxes reach, unless they are tight.
+    pub box_mode: BoxMode,
+    /// Whether to report the font family, style, weight, stretch and size of
+    /// each word.
+    pub include_font_info: bool,
+    /// Whether to report the fill color of each word.
+    pub include_color: bool,
+    /// Whether to report the baseline of each word and the ascender and
+    /// descender of its font.
+    pub include_baseline: bool,
+    /// Whether to report the language and script of each word.
+    pub include_lang: bool,
+    /// The main source, to report where the text of words, characters and
+    /// glyphs comes from in it, or `None` not to report it.
+    pub source: Option<Source>,
+    /// The sources of the document, to report the file, line and column the
+    /// text of words, characters and glyphs comes from in them. Empty not to
+    /// report them.
+    pub sources: Vec<Source>,
+    /// The indices of the pages to extract from, or `None` for all pages.
+    pub pages: Option<Vec<usize>>,
+    /// With word granularity, whether to only return the words and groups
+    /// that are not in a group, with the words and groups in each group as
+    /// its children.
+    pub tree: bool,
+    /// Only keep the boxes whose text matches this pattern. The boxes of
+    /// groups are dropped unless `match_groups` is set.
+    pub matches: Option<Regex>,
+    /// Whether to keep the boxes of groups whose text matches `matches`,
+    /// instead of dropping all of them.
+    pub match_groups: bool,
+    /// Only keep the boxes of text at least this large, in pt. Boxes of
+    /// several runs, like lines, are kept if any of their words is large
+    /// enough. Boxes without any font size, like groups, are always kept.
+    pub min_font_size: Option<f64>,
+    /// Only keep the boxes with at least this score. Boxes of several runs,
+    /// like lines, are kept if all of their words are. Boxes without any
+    /// score, like groups, are always kept.
+    pub min_score: Option<f64>,
+    /// Only keep the boxes of text filled with this RGBA color. Boxes of
+    /// several runs, like lines, are kept if any of their words is.
+    pub filter_color: Option<[u8; 4]>,
+    /// Drop boxes that have the same text and kind as an earlier box of the
+    /// same page, and whose coordinates are all within this many pt of it,
+    /// e.g. of text that is drawn twice. `None` keeps all boxes.
+    pub dedup_epsilon: Option<f64>,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self {
+            granularity: Granularity::default(),
+            output_level: OutputLevel::default(),
+            reading_order: ReadingOrder::default(),
+            include_whitespace: false,
+            include_delimiters: false,
+            split_mode: SplitMode::default(),
+            merge_hyphenation: false,
+            split_math: false,
+            unicode_delimiters: false,
+            delimiters: String::new(),
+            tight_boxes: false,
+            box_mode: BoxMode::default(),
+            include_font_info: true,
+            include_color: false,
+            include_baseline: false,
+            include_lang: false,
+            source: None,
+            sources: Vec::new(),
+            pages: None,
+            tree: false,
+            matches: None,
+            match_groups: false,
+            min_font_size: None,
+            min_score: None,
+            filter_color: None,
+            dedup_epsilon: None,
+        }
+    }
+}
+
+/// Errors that can occur while extracting word boxes.
+#[derive(Debug)]
+pub enum ExtractError {
+    /// The document failed to compile.
+    Compile(Vec<SourceDiagnostic>),
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtractError::Compile(diagnostics) => {
+                write!(f, "failed to compile document")?;
+                for diagnostic in diagnostics {
+                    write!(f, "\n  {}", diagnostic.message)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExtractError {}
+
+/// Lays out the main source of the given world.
+///
+/// Warnings are returned alongside the result, whether compilation succeeded
+/// or not.
+pub fn compile(world: &TypstWrapperWorld) -> Warned<Result<PagedDocument, ExtractError>> {
+    let compiled = typst::compile(world);
+    Warned {
+        output: compiled.output.map_err(|errors| ExtractError::Compile(errors.to_vec())),
+        warnings: compiled.warnings,
+    }
+}
+
+/// Collects all words (or glyphs) of a compiled document into `WordBox`es.
+pub fn collect_word_boxes(document: &PagedDocument, opts: &ExtractOptions) -> Vec<WordBox> {
+    word_boxes(document, opts).collect()
+}
+
+/// Returns the boxes of all words (or glyphs) of a compiled document, page
+/// by page as they are found, so that they can be written out without
+/// holding all of them in memory.
+pub fn word_boxes<'a>(
+    document: &'a PagedDocument,
+    opts: &'a ExtractOptions,
+) -> Box<dyn Iterator<Item = WordBox> + 'a> {
+    let to_word_box = |(page, word): (usize, Word)| word_box(page, word, opts);
+    let pages = opts.pages.as_deref();
+    let text_options = TextOptions {
+        include_whitespace: opts.include_whitespace,
+        include_delimiters: opts.include_delimiters,
+        split_mode: opts.split_mode,
+        tight_boxes: opts.tight_boxes,
+        box_mode: opts.box_mode,
+        unicode_delimiters: opts.unicode_delimiters,
+        delimiters: &opts.delimiters,
+        split_math: opts.split_math,
+    };
+    let boxes: Box<dyn Iterator<Item = WordBox>> = match opts.granularity {
+        Granularity::Glyph => Box::new(glyphs_with_boxes(document, text_options, pages).map(to_word_box)),
+        Granularity::Char => Box::new(chars_with_boxes(document, text_options, pages).map(to_word_box)),
+        Granularity::Word if opts.tree => Box::new(
+            word_trees_with_boxes(document, text_options, pages)
+                .map(|(page, element)| tree_word_box(page, element, opts)),
+        ),
+        Granularity::Word if opts.merge_hyphenation && opts.output_level != OutputLevel::Groups => Box::new(
+            unhyphenated_words_with_boxes(document, text_options, pages)
+                .filter(|(_, word, _)| opts.output_level == OutputLevel::Both || !is_group_kind(&word.kind, opts))
+                .map(move |(page, word, parts)| WordBox {
+                    rects: match parts.len() {
+                        1 => Vec::new(),
+                        _ => parts.into_iter().map(Rect::from).collect(),
+                    },
+                    ..to_word_box((page, word))
+                }),
+        ),
+        Granularity::Word => match opts.output_level {
+            OutputLevel::Words => Box::new(
+                words_with_boxes(document, text_options, pages)
+                    .filter(|(_, word)| !is_group_kind(&word.kind, opts))
+                    .map(to_word_box),
+            ),
+            OutputLevel::Groups => Box::new(word_trees_with_boxes(document, text_options, pages).map(
+                move |(page, element)| match element {
+                    Element::Word(word) | Element::Group(word, _) => to_word_box((page, word)),
+                },
+            )),
+            OutputLevel::Both => Box::new(words_with_boxes(document, text_options, pages).map(to_word_box)),
+        },
+        Granularity::Line => Box::new(lines_with_boxes(document, text_options, pages).map(
+            move |(page, line, words)| WordBox {
+                children: words.into_iter().map(|word| to_word_box((page, word))).collect(),
+                ..to_word_box((page, line))
+            },
+        )),
+        Granularity::Block => Box::new(blocks_with_boxes(document, text_options, pages).map(
+            move |(page, block, lines)| WordBox {
+                children: lines
+                    .into_iter()
+                    .map(|(line, words)| WordBox {
+                        children: words.into_iter().map(|word| to_word_box((page, word))).collect(),
+                        ..to_word_box((page, line))
+                    })
+                    .collect(),
+                ..to_word_box((page, block))
+            },
+        )),
+        Granularity::Sentence => Box::new(sentences_with_boxes(document, text_options, pages).map(
+            move |(page, sentence, line_boxes, words)| WordBox {
+                rects: match line_boxes.len() {
+                    1 => Vec::new(),
+                    _ => line_boxes.into_iter().map(Rect::from).collect(),
+                },
+                children: words.into_iter().map(|word| to_word_box((page, word))).collect(),
+                ..to_word_box((page, sentence))
+            },
+        )),
+    };
+    let boxes: Box<dyn Iterator<Item = WordBox>> = match &opts.matches {
+        // Only words have groups around them; lines and blocks are units.
+        Some(pattern) => Box::new(boxes.filter(move |word_box| {
+            let is_group = opts.granularity == Granularity::Word && is_group_kind(&word_box.kind, opts);
+            (opts.match_groups || !is_group) && pattern.is_match(&word_box.word)
+        })),
+        None => boxes,
+    };
+    let boxes: Box<dyn Iterator<Item = WordBox>> = match opts.min_font_size {
+        Some(min_font_size) => Box::new(
+            boxes.filter(move |word_box| largest_font_size(word_box).is_none_or(|size| size >= min_font_size)),
+        ),
+        None => boxes,
+    };
+    let boxes: Box<dyn Iterator<Item = WordBox>> = match opts.min_score {
+        Some(min_score) => {
+            Box::new(boxes.filter(move |word_box| lowest_score(word_box).is_none_or(|score| score >= min_score)))
+        }
+        None => boxes,
+    };
+    let boxes: Box<dyn Iterator<Item = WordBox>> = match opts.filter_color {
+        Some([r, g, b, a]) => {
+            // The color as `Word::color` has it.
+            let mut hex = format!("#{r:02x}{g:02x}{b:02x}");
+            if a != 255 {
+                hex.push_str(&format!("{a:02x}"));
+            }
+            Box::new(boxes.filter(move |word_box| has_color(word_box, &hex)))
+        }
+        None => boxes,
+    };
+    let boxes: Box<dyn Iterator<Item = WordBox>> = match opts.dedup_epsilon {
+        Some(epsilon) => {
+            // The boxes kept so far on the current page, by their text.
+            let mut page = None;
+            let mut kept: HashMap<(String, String), Vec<word_analysis::BBox>> = HashMap::new();
+            Box::new(boxes.filter(move |word_box| {
+                if page != Some(word_box.page) {
+                    page = Some(word_box.page);
+                    kept.clear();
+                }
+                let bbox = (word_box.x, word_box.y, word_box.width, word_box.height);
+                let same = kept.entry((word_box.word.clone(), word_box.kind.clone())).or_default();
+                let close = |(x, y, width, height): &word_analysis::BBox| {
+                    [x - bbox.0, y - bbox.1, width - bbox.2, height - bbox.3].iter().all(|d| d.abs() <= epsilon)
+                };
+                if same.iter().any(close) {
+                    return false;
+                }
+                same.push(bbox);
+                true
+            }))
+        }
+        None => boxes,
+    };
+    // Fonts and colors are only left out now, as they are needed to filter.
+    let boxes = boxes.map(|word_box| without_unrequested(word_box, opts));
+    Box::new(in_reading_order(boxes, opts))
+}
+
+/// Numbers the boxes in reading order, and sorts the boxes of each page by
+/// it, one page at a time.
+fn in_reading_order<'a>(
+    boxes: impl Iterator<Item = WordBox> + 'a,
+    opts: &'a ExtractOptions,
+) -> impl Iterator<Item = WordBox> + 'a {
+    let mut boxes = boxes.peekable();
+    let mut next_order = 0;
+    std::iter::from_fn(move || {
+        let first = boxes.next()?;
+        let page = first.page;
+        let mut page_boxes = vec![first];
+        while let Some(word_box) = boxes.next_if(|word_box| word_box.page == page) {
+            page_boxes.push(word_box);
+        }
+        if opts.reading_order == ReadingOrder::Visual {
+            page_boxes = visually_ordered(page_boxes, opts);
+        }
+        in_logical_order(&mut page_boxes);
+        for word_box in &mut page_boxes {
+            word_box.order = Some(next_order);
+            next_order += 1;
+        }
+        Some(page_boxes)
+    })
+    .flatten()
+}
+
+/// Puts each run of consecutive right-to-left boxes on the same line into
+/// the order they are read in, from right to left. Both the layout and the
+/// visual order have the runs of right-to-left text that follow one another
+/// from left to right, e.g. where a word in it is bold.
+fn in_logical_order(page_boxes: &mut [WordBox]) {
+    let is_rtl = |word_box: &WordBox| word_box.dir.as_deref() == Some("rtl");
+    let same_line = |a: &WordBox, b: &WordBox| {
+        let (a_center, b_center) = (a.y + a.height / 2.0, b.y + b.height / 2.0);
+        (a_center - b_center).abs() < a.height.min(b.height) / 2.0
+    };
+    let mut start = 0;
+    while start < page_boxes.len() {
+        let mut end = start + 1;
+        if is_rtl(&page_boxes[start]) {
+            while let Some(next) = page_boxes.get(end)
+                && is_rtl(next)
+                && same_line(&page_boxes[end - 1], next)
+            {
+                end += 1;
+            }
+            page_boxes[start..end].sort_by(|a, b| (b.x + b.width).total_cmp(&(a.x + a.width)));
+        }
+        start = end;
+    }
+}
+
+/// Sorts the boxes of a page in visual reading order. Groups are left out
+/// when finding it, and then follow the last box within them.
+fn visually_ordered(page_boxes: Vec<WordBox>, opts: &ExtractOptions) -> Vec<WordBox> {
+    let bbox = |word_box: &WordBox| (word_box.x, word_box.y, word_box.width, word_box.height);
+    let is_group =
+        |word_box: &WordBox| opts.granularity == Granularity::Word && !opts.tree && is_group_kind(&word_box.kind, opts);
+    let (groups, units): (Vec<WordBox>, Vec<WordBox>) = page_boxes.into_iter().partition(is_group);
+
+    // Columns are told apart by gaps wider than a typical line is high.
+    let mut heights: Vec<f64> = units.iter().map(|word_box| word_box.height).collect();
+    heights.sort_by(f64::total_cmp);
+    let column_gap = heights.get(heights.len() / 2).copied().unwrap_or_default();
+    let bboxes: Vec<word_analysis::BBox> = units.iter().map(bbox).collect();
+    let order = reading_order::visual_order(&bboxes, column_gap);
+
+    // Each group goes after the last box whose center is within it.
+    let mut rank = vec![0; units.len()];
+    for (position, &index) in order.iter().enumerate() {
+        rank[index] = position;
+    }
+    let mut keyed: Vec<(usize, WordBox)> = Vec::with_capacity(units.len() + groups.len());
+    for group in groups {
+        let (x, y, width, height) = bbox(&group);
+        let last = bboxes
+            .iter()
+            .enumerate()
+            .filter(|&(_, &(ux, uy, uwidth, uheight))| {
+                let (cx, cy) = (ux + uwidth / 2.0, uy + uheight / 2.0);
+                x <= cx && cx <= x + width && y <= cy && cy <= y + height
+            })
+            .map(|(index, _)| 2 * rank[index] + 1)
+            .max();
+        keyed.push((last.unwrap_or(2 * units.len()), group));
+    }
+    keyed.extend(units.into_iter().enumerate().map(|(index, unit)| (2 * rank[index], unit)));
+    // Groups that follow the same box stay in layout order.
+    keyed.sort_by_key(|(key, _)| *key);
+    keyed.into_iter().map(|(_, word_box)| word_box).collect()
+}
+
+/// Whether a box of word granularity with the given kind is that of a group of
+/// words, rather than of a word or an equation that is one box.
+fn is_group_kind(kind: &str, opts: &ExtractOptions) -> bool {
+    kind != "word" && (opts.split_math || kind != "equation")
+}
+
+/// The box of a word found on the given page, with where it comes from in
+/// the main source if the options ask for it.
+fn word_box(page: usize, word: Word, opts: &ExtractOptions) -> WordBox {
+    let source_range = opts.source.as_ref().and_then(|source| source_range(source, &word.spans));
+    let location = source_location(&opts.sources, &word.spans);
+    WordBox {
+        src_start: source_range.as_ref().map(|range| range.start),
+        src_end: source_range.map(|range| range.end),
+        file: location.as_ref().map(|(file, _, _)| file.clone()),
+        line: location.as_ref().map(|&(_, line, _)| line),
+        column: location.map(|(_, _, column)| column),
+        ..WordBox::new(page, word)
+    }
+}
+
+/// The byte range in the source that covers the text of all the given spans
+/// in it, or `None` if none of them is text in the source, e.g. for page
+/// numbers or the title of an outline, whose span is the call that made them.
+fn source_range(source: &Source, spans: &[(Span, Range<usize>)]) -> Option<Range<usize>> {
+    spans
+        .iter()
+        .filter_map(|(span, range)| span_text_range(source, *span, range))
+        .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end))
+}
+
+/// The byte range in the source of the part of the text of a span given by
+/// `range`, or `None` if the span is not text in the source.
+fn span_text_range(source: &Source, span: Span, range: &Range<usize>) -> Option<Range<usize>> {
+    if span.id() != Some(source.id()) {
+        return None;
+    }
+    let node = source.find(span)?;
+    if node.get().children().len() > 0 {
+        return None;
+    }
+    // The text of a string literal starts after its quote.
+    let start = node.offset() + usize::from(node.kind() == SyntaxKind::Str);
+    // The text of a span can be longer than its source, e.g. for a smart
+    // quote or an escape.
+    let end = node.range().end;
+    Some((start + range.start).min(end)..(start + range.end).min(end))
+}
+
+/// The file, line and column (both starting at 1, the column in characters)
+/// where the text of the first of the given spans that is in one of the
+/// sources starts. Text that isn't in any source, like a figure's supplement,
+/// is placed at the start of the code that made it, e.g. the figure call.
+fn source_location(sources: &[Source], spans: &[(Span, Range<usize>)]) -> Option<(String, usize, usize)> {
+    spans.iter().find_map(|(span, range)| {
+        let source = sources.iter().find(|source| span.id() == Some(source.id()))?;
+        let start = match span_text_range(source, *span, range) {
+            Some(text_range) => text_range.start,
+            None => source.range(*span)?.start,
+        };
+        let line = source.byte_to_line(start)?;
+        let column = source.byte_to_column(start)?;
+        Some((file_name(source.id()), line + 1, column + 1))
+    })
+}
+
+/// The name of a file: its path within the root, e.g. `/chapters/intro.typ`,
+/// prefixed by its package if it is in one.
+pub fn file_name(id: FileId) -> String {
+    let path = id.vpath().as_rooted_path().to_string_lossy();
+    match id.package() {
+        Some(package) => format!("{package}{path}"),
+        None => path.into_owned(),
+    }
+}
+
+/// The box of a word, or of a group with the boxes of its words and groups as
+/// children.
+fn tree_word_box(page: usize, element: Element, opts: &ExtractOptions) -> WordBox {
+    match element {
+        Element::Word(word) => word_box(page, word, opts),
+        Element::Group(group, elements) => WordBox {
+            children: elements.into_iter().map(|element| tree_word_box(page, element, opts)).collect(),
+            ..word_box(page, group, opts)
+        },
+    }
+}
+
+/// The font size of a box, or for boxes of several runs (e.g. lines) the
+/// largest font size of the boxes they are made of.
+fn largest_font_size(word_box: &WordBox) -> Option<f64> {
+    word_box
+        .font_size
+        .or_else(|| word_box.children.iter().filter_map(largest_font_size).reduce(f64::max))
+}
+
+/// The score of a box, or the lowest score of the boxes it is made of.
+fn lowest_score(word_box: &WordBox) -> Option<f64> {
+    word_box.score.or_else(|| word_box.children.iter().filter_map(lowest_score).reduce(f64::min))
+}
+
+/// Whether a box, or one of the boxes it is made of, has the given color.
+fn has_color(word_box: &WordBox, hex: &str) -> bool {
+    word_box.color.as_deref() == Some(hex) || word_box.children.iter().any(|child| has_color(child, hex))
+}
+
+/// Removes the information from a box and its children that the options
+/// don't ask for.
+fn without_unrequested(mut word_box: WordBox, opts: &ExtractOptions) -> WordBox {
+    if !opts.include_font_info {
+        word_box.font = None;
+        word_box.subfamily = None;
+        word_box.weight = None;
+        word_box.style = None;
+        word_box.stretch = None;
+        word_box.font_size = None;
+        word_box.mixed_sizes = false;
+    }
+    if !opts.include_color {
+        word_box.color = None;
+    }
+    if !opts.include_baseline {
+        word_box.baseline = None;
+        word_box.ascender = None;
+        word_box.descender = None;
+    }
+    if !opts.include_lang {
+        word_box.lang = None;
+        word_box.script = None;
+    }
+    word_box.children = word_box.children.into_iter().map(|child| without_unrequested(child, opts)).collect();
+    word_box
+}
+
+/// Returns the `(width, height)` of each page of a document in pt.
+pub fn page_sizes(document: &PagedDocument) -> Vec<(f64, f64)> {
+    document
+        .pages
+        .iter()
+        .map(|page| (page.frame.width().to_pt(), page.frame.height().to_pt()))
+        .collect()
+}
+
+/// Compiles a Typst source and returns the boxes of all its words.
+///
+/// Files referenced by the source are resolved relative to `root`. This
+/// searches for fonts on every call; use [`extract_word_boxes_with`] to
+/// extract from many sources.
+pub fn extract_word_boxes(
+    source: &str,
+    root: &Path,
+    opts: &ExtractOptions,
+) -> Result<Vec<WordBox>, ExtractError> {
+    extract_word_boxes_with(&WorldBuilder::new(&[]), source, root, opts)
+}
+
+/// Like [`extract_word_boxes`], but reuses the fonts and library of the
+/// given builder, so that they are only loaded once for a batch of sources.
+///
+/// Afterwards, cached compilation results that haven't been used for a few
+/// compilations are evicted, so memory use stays bounded over a long batch.
+pub fn extract_word_boxes_with(
+    builder: &WorldBuilder,
+    source: &str,
+    root: &Path,
+    opts: &ExtractOptions,
+) -> Result<Vec<WordBox>, ExtractError> {
+    let world = builder.build(root.to_string_lossy().into_owned(), source.to_owned());
+    let document = compile(&world).output;
+    comemo::evict(10);
+    Ok(collect_word_boxes(&document?, opts))
+}
@@ -1,35 +1,143 @@
-mod render;
-mod word_analysis;
-mod world;
-
-use std::fs;
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::num::NonZeroUsize;
+use std::ops::RangeInclusive;
 use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use clap::Parser;
-use serde::Serialize;
-use typst::layout::PagedDocument;
-
-use render::{render_to_png,render_to_png_with_boxes};
-use word_analysis::words_with_boxes;
-use world::TypstWrapperWorld;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use notify::{RecursiveMode, Watcher};
+use regex::Regex;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use typst::layout::{Abs, PagedDocument};
+use typst::syntax::VirtualPath;
 
-#[derive(Serialize)]
-struct WordBox {
-    word: String,
-    x: f64,
-    y: f64,
-    width: f64,
-    height: f64,
-}
+use box_extractor::cache::{Cache, Entry};
+use box_extractor::diagnostics::print_diagnostics;
+use box_extractor::diff::diff_boxes;
+use box_extractor::encoding::{Encoding, decode};
+use box_extractor::metadata::fonts_used;
+use box_extractor::output::{
+    CoordinateSpace, Coords, Envelope, Format, ImageSize, Origin, first_difference, json_schema, output_hash,
+    write_boxes, write_diff, write_json, write_json_envelope, write_json_object, write_ndjson,
+};
+use box_extractor::query::{find_phrase, parse_selector, query_boxes};
+use box_extractor::tables::table_cells;
+use box_extractor::vfs::parse_vfs;
+use box_extractor::word_analysis::selected_pages;
+use box_extractor::world::check_font_paths;
+use box_extractor::{
+    BoxMode, ExtractError, ExtractOptions, Granularity, Metadata, OutputLevel, ReadingOrder, RenderError,
+    RenderOptions, SplitMode, TypstWrapperWorld, WordBox, WorldBuilder, collect_word_boxes, compile,
+    merged_page_offsets, page_sizes, render_pages_to_png, render_pages_to_png_with_boxes, render_to_pdf_with_boxes,
+    render_to_png, render_to_png_with_boxes, render_to_svg_with_boxes,
+};
 
-#[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[derive(Parser, Clone, Debug)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    subcommand_negates_reqs = true,
+    override_usage = "box_extractor [OPTIONS] <INPUT> <OUTPUT>\n       \
+                      box_extractor [OPTIONS] <--vfs <FILE>|--code <STRING>> <OUTPUT>\n       \
+                      box_extractor [OPTIONS] <COMMAND>"
+)]
 struct Cli {
-    /// The path to the input Typst file.
-    input: PathBuf,
+    /// The path to the input Typst file (or `-` to read it from stdin),
+    /// then the path for the output file (JSON unless `--format` says
+    /// otherwise). Only the output path is given with `--vfs` and `--code`,
+    /// which take the place of the input file.
+    #[arg(value_names = ["INPUT", "OUTPUT"], num_args = 1..=2, required_unless_present = "schema")]
+    paths: Vec<PathBuf>,
+
+    /// The input file of `paths`, if there is one.
+    #[arg(skip)]
+    input: Option<PathBuf>,
+
+    /// The output file of `paths`.
+    #[arg(skip)]
+    output: Option<PathBuf>,
+
+    /// Compile from an in-memory file system instead of the disk: a JSON file
+    /// (or `-` for stdin) mapping paths to contents, given as strings for text
+    /// files and as `{"base64": "..."}` for binary files.
+    #[arg(long, value_name = "FILE")]
+    vfs: Option<PathBuf>,
+
+    /// Compile this Typst markup instead of an input file. `\n` in it
+    /// stands for a line break. Files are resolved against the current
+    /// directory, or `--root`.
+    #[arg(long, value_name = "STRING", conflicts_with = "vfs")]
+    code: Option<String>,
+
+    /// The main file within `--vfs`.
+    #[arg(long, value_name = "PATH", requires = "vfs", default_value = "main.typ")]
+    main: String,
+
+    /// The encoding of the input file. Typst sources are UTF-8, so other
+    /// encodings are converted first.
+    #[arg(long, value_enum, default_value_t = Encoding::Utf8)]
+    assume_encoding: Encoding,
+
+    /// The format of the output file. `hocr` and `alto` always group words
+    /// into lines, so they reject any other `--granularity`, and `hocr`
+    /// always uses pixel coordinates, so it rejects `--coords points`. The
+    /// output is written to the file while it is serialized, instead of as a
+    /// whole once it is done, and `ndjson` box by box as the boxes are found,
+    /// before rendering, unless `--timeout`, `--print-hash`, `--compare` or
+    /// `--cache-dir` need the whole output.
+    #[arg(long, value_enum, default_value_t = Format::Json)]
+    format: Format,
+
+    /// Also write the document metadata (title, author, keywords and the
+    /// fonts used) under `document`. With `--legacy-json`, the output is then
+    /// an object with the words under `words` and the metadata next to them.
+    /// Only for `--format json`.
+    #[arg(long, action)]
+    with_metadata: bool,
+
+    /// Also find the elements matching this selector, like `typst query`
+    /// does, e.g. a label (`<intro>`) or an element function (`figure`).
+    /// Their boxes are written under `labels` of each page (with
+    /// `--legacy-json`, next to the words under `words`), and drawn onto the
+    /// rendered boxes. Only for `--format json`.
+    #[arg(long, value_name = "SELECTOR")]
+    query: Option<String>,
+
+    /// Also find the occurrences of this phrase in the text, ignoring case
+    /// and how much whitespace separates its words, even across lines. The
+    /// box around the words each occurrence covers is written under `matches`
+    /// of each page (with `--legacy-json`, next to the words), and drawn
+    /// onto the rendered boxes. Only for `--format json`.
+    #[arg(long, value_name = "PHRASE")]
+    find: Option<String>,
+
+    /// Also find the cells of tables and grids whose content is written out
+    /// in the source, with the index of their `table`, their `row` and
+    /// `col` and how many rows and columns they span. The box around the text
+    /// of each cell, with its words as children, is written under `cells` of
+    /// each page (with `--legacy-json`, next to the words), and drawn onto
+    /// the rendered boxes. Only for `--format json`.
+    #[arg(long, action)]
+    tables: bool,
+
+    /// Write the JSON output as it was before it had a version: a plain
+    /// array of boxes, or an object with them under `words`.
+    #[arg(long, action)]
+    legacy_json: bool,
 
-    /// The path for the output JSON file.
-    output: PathBuf,
+    /// Print the JSON Schema of the JSON output and exit.
+    #[arg(long, action)]
+    schema: bool,
 
     /// Optional: The path for the rendered PNG file.
     #[arg(short, long, default_value = "output.png")]
@@ -39,6 +147,41 @@ struct Cli {
     #[arg(long, default_value = "output_boxes.png")]
     render_boxes: PathBuf,
 
+    /// Also write the pages as an SVG with the boxes drawn on top as
+    /// `<rect>` elements, which carry their text in `data-word` and their
+    /// kind as a class, so that they can be styled and made interactive.
+    #[arg(long, value_name = "FILE")]
+    svg_out: Option<PathBuf>,
+
+    /// Also write the pages as a PDF with the boxes drawn over them as
+    /// vector shapes. The text of the pages stays selectable.
+    #[arg(long, value_name = "FILE")]
+    pdf_out: Option<PathBuf>,
+
+    /// The unit of text that each box covers.
+    #[arg(long, value_enum, default_value_t = Granularity::Word)]
+    granularity: Granularity,
+
+    /// Which boxes to write with word granularity: `words` for only the
+    /// words, `groups` for the outermost groups (headings, figures, ...) and
+    /// the words outside of any group, or `both` for all words and the
+    /// outermost groups.
+    #[arg(long, value_enum, default_value_t = OutputLevel::Both)]
+    output_level: OutputLevel,
+
+    /// The order in which boxes are numbered (as `order`) and written:
+    /// `layout` for the order Typst laid them out in, or `visual` for columns
+    /// from left to right and each from top to bottom, as found from the
+    /// gaps between the boxes.
+    #[arg(long, value_enum, default_value_t = ReadingOrder::Layout)]
+    reading_order: ReadingOrder,
+
+    /// Write only the words and groups that are not in a group, with the
+    /// words and groups in each group nested under its `children`, instead
+    /// of all of them in a flat list. Only for word granularity and JSON.
+    #[arg(long, action)]
+    tree: bool,
+
     // Whether to include boxes of whitespace.
     #[arg(long, action)]
     include_whitespace: bool,
@@ -47,56 +190,1512 @@ struct Cli {
     #[arg(long, action)]
     include_delimiters: bool,
 
+    /// Where text is split into words. Also accepted as `--segmentation`,
+    /// with `simple` for `punctuation` and `uax29` for `unicode`.
+    #[arg(long, alias = "segmentation", value_enum, default_value_t = SplitMode::Punctuation)]
+    split_mode: SplitMode,
+
+    /// Treat all Unicode punctuation, like curly quotes, dashes, `…` and
+    /// `«»`, as delimiters, instead of only ASCII punctuation, so that
+    /// smart quotes are not part of the words they enclose.
+    #[arg(long, action)]
+    unicode_delimiters: bool,
+
+    /// More characters to treat as delimiters like ASCII punctuation, e.g.
+    /// `--delimiters "“”–…"` to split only at these of the Unicode
+    /// punctuation.
+    #[arg(long, value_name = "CHARS", default_value = "")]
+    delimiters: String,
+
+    /// Join the parts of a word that is hyphenated at the end of a line into
+    /// one box around both, with the box of each part under `rects`. The
+    /// hyphen is left out of the text unless it is in the source. Only for
+    /// word granularity without `--tree` or `--output-level groups`.
+    #[arg(long, action)]
+    merge_hyphenation: bool,
+
+    /// Split equations into their symbols, each a word within a group of
+    /// kind `equation`, instead of writing each equation as a single box of
+    /// that kind with all of its text, operators included.
+    #[arg(long, action)]
+    split_math: bool,
+
+    /// Only keep the boxes whose text matches this regular expression, e.g.
+    /// `^\d{6}$` for six-digit numbers. The boxes of groups (headings, links,
+    /// ...) are dropped, unless `--match-group` is given. Only the kept boxes
+    /// are written and drawn.
+    #[arg(long = "match", value_name = "REGEX", value_parser = parse_regex)]
+    matches: Option<Regex>,
+
+    /// With `--match`, keep the boxes of groups whose text matches as well.
+    #[arg(long, action, requires = "matches")]
+    match_group: bool,
+
+    /// Only keep the boxes of text at least this large, in pt, e.g. to leave
+    /// out footnotes. Lines and blocks are kept if any of their words is.
+    #[arg(long, value_name = "PT")]
+    min_font_size: Option<f64>,
+
+    /// Only keep the boxes whose score (from 0 to 1, lowered by missing
+    /// glyphs, glyphs without an advance and punctuation) is at least this.
+    /// Lines and blocks are kept if all of their words are.
+    #[arg(long, value_name = "SCORE")]
+    min_score: Option<f64>,
+
+    /// Only keep the boxes of text filled with this color, given as
+    /// `RRGGBB` (or `RRGGBBAA`) hex like `--include-color` reports it.
+    #[arg(long, value_name = "RRGGBB", value_parser = parse_color)]
+    filter_color: Option<[u8; 4]>,
+
+    /// Merge boxes of the same text at (nearly) the same place on a page into
+    /// one, e.g. of text that is drawn twice on top of itself.
+    #[arg(long, action)]
+    dedup: bool,
+
+    /// How far apart in pt the coordinates of boxes may be for `--dedup` to
+    /// merge them.
+    #[arg(long, value_name = "PT", default_value_t = 0.1, requires = "dedup")]
+    dedup_epsilon: f64,
+
+    /// Make boxes only as tall as the outlines of their glyphs, instead of
+    /// spanning the font's ascender and descender. This looks up the bounds
+    /// of every glyph's outline, which makes extraction slower.
+    #[arg(long, action)]
+    tight_boxes: bool,
+
+    /// How far up boxes reach from the baseline: to the font's ascender, its
+    /// cap height or its x-height. Boxes always reach down to the font's
+    /// descender. With `--tight-boxes`, only applies to words without
+    /// outlines, like spaces.
+    #[arg(long, value_enum, default_value_t = BoxMode::Ascent)]
+    box_mode: BoxMode,
+
+    /// Omit the font family, style, weight, stretch and size of each word
+    /// from the output.
+    #[arg(long, action)]
+    no_font_info: bool,
+
+    /// Report the fill color of each word as a hex string.
+    #[arg(long, action)]
+    include_color: bool,
+
+    /// Report the y-coordinate of the baseline of each word, and the
+    /// ascender and descender of its font.
+    #[arg(long, action)]
+    include_baseline: bool,
+
+    /// Report the language each word was set in (as set with
+    /// `#set text(lang: ..)`), and the script of its characters.
+    #[arg(long, action)]
+    include_lang: bool,
+
+    /// Report the byte offsets in the input file where the text of each word
+    /// starts and ends, as `src_start` and `src_end`. Text that is not in
+    /// the input file, like page numbers, has none.
+    #[arg(long, action)]
+    include_source: bool,
+
+    /// Report the file, line and column where the text of each word starts,
+    /// including in files the input includes. Text made by the document,
+    /// like a figure's supplement, is placed at the code that made it if it
+    /// is known.
+    #[arg(long, action)]
+    with_source_locations: bool,
+
     // Number of pixels per pt for rendering.
     #[arg(short, long, default_value_t = 1.0)]
     pixel_per_pt: f32,
 
-    // Number of pixels for the bbox stroke rendering.
-    #[arg(short, long, default_value_t = 1.0)]
-    bbox_stroke: f32
+    /// Resolution for rendering in dots per inch. Overrides `--pixel-per-pt`
+    /// with `dpi / 72`.
+    #[arg(long, conflicts_with = "pixel_per_pt")]
+    dpi: Option<f32>,
+
+    /// Unit of the coordinates in the JSON output. Coordinates stay in points
+    /// by default; `pixels` scales them to match the rendered PNGs.
+    #[arg(long, value_enum, default_value_t = Coords::Points)]
+    coords: Coords,
+
+    /// Whether coordinates in the output are relative to the page each box
+    /// is on, or to the merged image of all pages (`--render`), where later
+    /// pages are further down. Boxes are drawn in the right place either way.
+    #[arg(long, value_enum, default_value_t = CoordinateSpace::Page)]
+    coordinate_space: CoordinateSpace,
+
+    /// Where the y-axis of the output coordinates starts. With
+    /// `bottom-left`, as in PDF, y increases upwards from the bottom edge of
+    /// the page (or of the merged image), and each box's `y` is that of its
+    /// bottom edge: `page height - (y + height)` of the default `top-left`.
+    /// Boxes are drawn in the right place either way.
+    #[arg(long, value_enum, default_value_t = Origin::TopLeft)]
+    origin: Origin,
+
+    /// The color of the box outlines as `RRGGBBAA` (or `RRGGBB`) hex.
+    #[arg(long, value_name = "RRGGBBAA", value_parser = parse_color, default_value = "ff0000b4")]
+    box_color: [u8; 4],
+
+    /// The width of the box outlines in pt, so that it scales with the
+    /// resolution.
+    #[arg(short = 'b', long, alias = "bbox-stroke", default_value_t = 1.0)]
+    box_width: f32,
+
+    /// Interpret `--box-width` in pixels, regardless of the resolution.
+    #[arg(long, action)]
+    absolute_box_width: bool,
+
+    /// Fill the boxes with a color, given as `--box-fill=RRGGBBAA` (or
+    /// `RRGGBB`, which is mostly transparent) hex, or `ff000033` if none is
+    /// given. Boxes of groups are filled in the color of their kind.
+    #[arg(
+        long,
+        value_name = "RRGGBBAA",
+        value_parser = parse_fill_color,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "ff000033"
+    )]
+    box_fill: Option<[u8; 4]>,
+
+    /// Don't draw the outlines of the boxes, e.g. to only fill them with
+    /// `--box-fill`.
+    #[arg(long, action)]
+    no_box_stroke: bool,
+
+    /// Highlight the boxes with these indices in the output (their `order`),
+    /// e.g. `137` or `10-12,40`, in magenta and three times as wide, and
+    /// draw all other boxes faintly.
+    #[arg(long, value_name = "INDICES", value_parser = parse_indices)]
+    highlight: Option<Indices>,
+
+    /// Stamp a legend of the box colors, which depend on the kind of each
+    /// group, onto the rendered boxes.
+    #[arg(long, action)]
+    legend: bool,
+
+    /// The largest width and height of a page that is rendered, in cm.
+    /// Documents with larger pages fail to render. `0` disables the limit.
+    #[arg(long, value_name = "CM", default_value_t = 100.0)]
+    max_page_size: f64,
+
+    /// Only extract and render these pages, e.g. `1,3,5-9`. Pages are
+    /// numbered from 1; the `page` of each box stays the index in the whole
+    /// document.
+    #[arg(long, value_name = "RANGES", value_parser = parse_pages)]
+    pages: Option<PageRanges>,
+
+    /// Render each page to its own PNG file (e.g. `output-01.png`) instead of
+    /// one merged image.
+    #[arg(long, action)]
+    per_page: bool,
+
+    /// Render at most this many pages at once with `--per-page`. Defaults to
+    /// the number of CPU cores. Without `--per-page` the merged image is drawn
+    /// on one thread, so this has no effect.
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+
+    /// Treat compile warnings as errors.
+    #[arg(long, action)]
+    deny_warnings: bool,
+
+    /// The project root that files are resolved against. Defaults to the
+    /// directory of the input file, or the current directory for stdin.
+    #[arg(long, value_name = "DIR")]
+    root: Option<PathBuf>,
+
+    /// Allow reading files from this directory outside the root, through
+    /// symlinks within the root (repeatable). Other files outside the root
+    /// are refused.
+    #[arg(long = "allow-path", value_name = "DIR")]
+    allowed_paths: Vec<PathBuf>,
+
+    /// Additional directories to load fonts from (repeatable). Their fonts
+    /// take precedence over the system fonts.
+    #[arg(long = "font-path", value_name = "DIR")]
+    font_paths: Vec<PathBuf>,
+
+    /// Also use the fonts installed on the system. By default, only the
+    /// fonts from `--font-path` and those embedded in Typst are used, so
+    /// that the boxes are the same on every machine.
+    #[arg(long, action)]
+    use_system_fonts: bool,
+
+    /// Resolve packages from `<DIR>/<namespace>/<name>/<version>/`, and
+    /// download missing ones there, instead of using the default package cache.
+    #[arg(long, value_name = "DIR")]
+    package_path: Option<PathBuf>,
+
+    /// Never download packages; fail if a package is not already available.
+    #[arg(long, action)]
+    offline: bool,
+
+    /// Compile this Typst code before the document (repeatable), e.g.
+    /// `#set page(width: 210mm, margin: 1cm)` to override its page geometry.
+    #[arg(long, value_name = "TYPST")]
+    prepend: Vec<String>,
+
+    /// Add a string key-value pair visible through `sys.inputs` (repeatable).
+    #[arg(long = "input", value_name = "KEY=VALUE", value_parser = parse_input)]
+    inputs: Vec<(String, String)>,
+
+    /// Give up after this many seconds of compiling and rendering, exiting
+    /// with code 124 without writing any output.
+    #[arg(long, value_name = "SECONDS", value_parser = parse_timeout)]
+    timeout: Option<Duration>,
+
+    /// Keep running after writing the outputs, and write them again whenever
+    /// the input file or a file it reads changes, until interrupted. Only
+    /// what the changes affect is laid out again, so this is much faster
+    /// than running again.
+    #[arg(long, action, conflicts_with_all = ["timeout", "compare", "cache_dir", "code", "vfs"])]
+    watch: bool,
+
+    /// Pin the current time (RFC 3339, e.g. `2024-01-31T12:00:00Z`) used by
+    /// `datetime.today()`, for reproducible extraction.
+    #[arg(long, value_name = "DATETIME", value_parser = parse_now)]
+    now: Option<OffsetDateTime>,
+
+    /// Make the output reproducible: unless `--now` is given, the current
+    /// time is pinned to 1970-01-01T00:00:00Z.
+    #[arg(long, action)]
+    deterministic: bool,
+
+    /// Print a hash of the output file, to check that runs agree.
+    #[arg(long, action)]
+    print_hash: bool,
+
+    /// Compare the output with an earlier output file, e.g. of the same
+    /// document, and fail with the first box that differs. Nothing is
+    /// written in that case.
+    #[arg(long, value_name = "FILE")]
+    compare: Option<PathBuf>,
+
+    /// Cache the word boxes in this directory, and reuse them without
+    /// compiling when the document, the files it reads, the fonts and the
+    /// options are unchanged. Nothing is rendered when they are reused.
+    #[arg(long, value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// Remove cache entries that were written more than this many days ago.
+    #[arg(long, value_name = "DAYS", default_value = "7", value_parser = parse_max_age, requires = "cache_dir")]
+    cache_max_age: Duration,
+
+    /// Remove the oldest cache entries while the cache takes up more than
+    /// this many megabytes.
+    #[arg(long, value_name = "MB", default_value_t = 100, requires = "cache_dir")]
+    cache_max_size: u64,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum Command {
+    /// Extract the boxes of two versions of a document and write which boxes
+    /// were added, removed or moved as JSON. The options for extracting
+    /// them go before `diff`, e.g. `--granularity line diff a.typ b.typ`.
+    Diff {
+        /// The earlier version of the document.
+        before: PathBuf,
+        /// The later version of the document.
+        after: PathBuf,
+        /// The path for the JSON output, or none to print it.
+        output: Option<PathBuf>,
+        /// How far in pt the coordinates of a box may change for it to still
+        /// be in the same place.
+        #[arg(long, value_name = "PT", default_value_t = 0.5)]
+        tolerance: f64,
+    },
+    /// Extract the boxes of many documents, loading the fonts only once for
+    /// all of them, and write those of each to `<name>.<format>` in the
+    /// output directory. Nothing is rendered. The options for extracting
+    /// them go before `batch`, e.g. `--format csv batch *.typ --out-dir boxes`.
+    Batch {
+        /// The Typst files.
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        /// The directory to write the outputs to. It is created if needed.
+        #[arg(long, value_name = "DIR")]
+        out_dir: PathBuf,
+    },
 }
 
-fn main() {
-    let cli = Cli::parse();
-    
-    let content = fs::read_to_string(&cli.input)
-        .expect("Error: Could not read the input file.");
-    let root_path = cli.input.parent().unwrap_or_else(|| Path::new(""));
-    let world = TypstWrapperWorld::new(root_path.to_str().unwrap().to_owned(), content);
+fn main() -> ExitCode {
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+    let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    if cli.schema {
+        println!("{:#}", json_schema());
+        return ExitCode::SUCCESS;
+    }
+    if let Some(dpi) = cli.dpi {
+        cli.pixel_per_pt = dpi / 72.0;
+    }
+    if cli.with_metadata && cli.format != Format::Json {
+        eprintln!("error: --with-metadata is only supported with --format json");
+        return ExitCode::FAILURE;
+    }
+    if cli.query.is_some() && cli.format != Format::Json {
+        eprintln!("error: --query is only supported with --format json");
+        return ExitCode::FAILURE;
+    }
+    if cli.find.is_some() && cli.format != Format::Json {
+        eprintln!("error: --find is only supported with --format json");
+        return ExitCode::FAILURE;
+    }
+    if cli.tables && cli.format != Format::Json {
+        eprintln!("error: --tables is only supported with --format json");
+        return ExitCode::FAILURE;
+    }
+    if cli.legacy_json && cli.format != Format::Json {
+        eprintln!("error: --legacy-json is only supported with --format json");
+        return ExitCode::FAILURE;
+    }
+    if cli.tree && (cli.granularity != Granularity::Word || cli.format != Format::Json) {
+        eprintln!("error: --tree is only supported with --granularity word and --format json");
+        return ExitCode::FAILURE;
+    }
+    if cli.output_level != OutputLevel::Both && cli.granularity != Granularity::Word {
+        eprintln!("error: --output-level is only supported with --granularity word");
+        return ExitCode::FAILURE;
+    }
+    if cli.tree && cli.output_level != OutputLevel::Both {
+        eprintln!("error: --tree can't be used with --output-level, as it writes the words within their groups");
+        return ExitCode::FAILURE;
+    }
+    if cli.merge_hyphenation && (cli.granularity != Granularity::Word || cli.tree || cli.output_level == OutputLevel::Groups) {
+        eprintln!("error: --merge-hyphenation is only supported with --granularity word, without --tree or --output-level groups");
+        return ExitCode::FAILURE;
+    }
+    if cli.origin == Origin::BottomLeft && matches!(cli.format, Format::Hocr | Format::Alto) {
+        eprintln!("error: --origin bottom-left can't be used with --format hocr and alto, whose coordinates start at the top");
+        return ExitCode::FAILURE;
+    }
+    if cli.coordinate_space == CoordinateSpace::Merged {
+        let conflict = if cli.per_page {
+            Some("--per-page")
+        } else if matches!(cli.format, Format::Hocr | Format::Alto) {
+            Some("--format hocr and alto, which describe each page,")
+        } else {
+            None
+        };
+        if let Some(conflict) = conflict {
+            eprintln!("error: --coordinate-space merged can't be used with {conflict}");
+            return ExitCode::FAILURE;
+        }
+    }
+    if matches!(cli.format, Format::Hocr | Format::Alto) && given("granularity") && cli.granularity != Granularity::Line {
+        eprintln!("error: --format hocr and alto only support --granularity line, as they describe words within lines");
+        return ExitCode::FAILURE;
+    }
+    if cli.format == Format::Hocr && given("coords") && cli.coords != Coords::Pixels {
+        eprintln!("error: --format hocr only supports --coords pixels, as its coordinates are whole pixels");
+        return ExitCode::FAILURE;
+    }
+    if cli.format == Format::Hocr {
+        // hOCR describes words within lines, in whole pixels.
+        cli.granularity = Granularity::Line;
+        cli.coords = Coords::Pixels;
+    }
+    if cli.format == Format::Alto {
+        // ALTO describes words within lines within blocks.
+        cli.granularity = Granularity::Line;
+    }
+
+    match &cli.command {
+        Some(Command::Diff { before, after, output, tolerance }) => {
+            return run_diff(&cli, before, after, output.as_deref(), *tolerance);
+        }
+        Some(Command::Batch { inputs, out_dir }) => return run_batch(&cli, inputs, out_dir),
+        None => {}
+    }
+
+    // With `--vfs` or `--code`, the only path is that of the output.
+    let source_flag = if cli.vfs.is_some() {
+        Some("--vfs")
+    } else if cli.code.is_some() {
+        Some("--code")
+    } else {
+        None
+    };
+    match (source_flag, cli.paths.as_slice()) {
+        (Some(_), [output]) => cli.output = Some(output.clone()),
+        (None, [input, output]) => {
+            cli.input = Some(input.clone());
+            cli.output = Some(output.clone());
+        }
+        (Some(flag), _) => {
+            let message = format!("an input file can't be used with {flag}");
+            Cli::command().error(ErrorKind::ArgumentConflict, message).exit();
+        }
+        (None, _) => {
+            Cli::command()
+                .error(ErrorKind::MissingRequiredArgument, "the output path is required")
+                .exit();
+        }
+    }
+    let output = cli.output.clone().expect("the output path is set");
+
+    if let Err(error) = check_font_paths(&cli.font_paths) {
+        eprintln!("error: {error}");
+        return ExitCode::FAILURE;
+    }
+    // Nothing is written unless all outputs can be, so that an unwritable
+    // path doesn't leave the others behind. Pages rendered one by one are
+    // written next to `--render` and `--render-boxes`.
+    let images = [Some(&cli.render), Some(&cli.render_boxes), cli.svg_out.as_ref(), cli.pdf_out.as_ref()];
+    for path in [Some(&output)].into_iter().chain(images).flatten() {
+        if let Err(error) = check_writable(path) {
+            eprintln!("error: could not write {}: {error}", path.display());
+            return ExitCode::FAILURE;
+        }
+    }
+    let (world, input_name) = match load_world(&cli, &world_builder(&cli)) {
+        Ok(loaded) => loaded,
+        Err(LoadError::Encoding(message)) => {
+            eprintln!("error: {message}");
+            return ExitCode::from(ENCODING_EXIT_CODE);
+        }
+        Err(LoadError::Other(message)) => {
+            eprintln!("error: {message}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let world = configure_world(&cli, world);
+    if cli.watch {
+        return watch(&cli, world, &output);
+    }
+
+    let cache = match &cli.cache_dir {
+        Some(dir) => match Cache::new(dir.clone()) {
+            Ok(cache) => Some((Cache::key(&world, &cache_options(&cli)), cache)),
+            Err(error) => {
+                eprintln!("error: could not create the cache in {}: {error}", dir.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+    let cached = cache.as_ref().and_then(|(key, cache)| cache.get(*key, &world));
+    let reused = cached.is_some();
+
+    // Compile and render in memory first, so that nothing is written if
+    // either fails or takes too long.
+    let outputs = match (cached, cli.timeout) {
+        (Some(boxes), _) => Ok(Outputs { boxes: Some(boxes), images: Vec::new(), rendered_pages: 0, cache_entry: None }),
+        (None, None) => extract_and_render(&cli, &world),
+        (None, Some(timeout)) => {
+            let (sender, receiver) = mpsc::channel();
+            let worker_cli = cli.clone();
+            // Typst recurses deeply on nested content, so give the worker as
+            // much stack as a main thread usually gets.
+            thread::Builder::new()
+                .stack_size(8 * 1024 * 1024)
+                .spawn(move || _ = sender.send(extract_and_render(&worker_cli, &world)))
+                .expect("Failed to spawn the compilation thread.");
+            match receiver.recv_timeout(timeout) {
+                Ok(outputs) => outputs,
+                Err(RecvTimeoutError::Timeout) => {
+                    eprintln!("error: {input_name} timed out after {} seconds", timeout.as_secs_f64());
+                    return ExitCode::from(TIMEOUT_EXIT_CODE);
+                }
+                // The worker panicked and already reported why.
+                Err(RecvTimeoutError::Disconnected) => return ExitCode::FAILURE,
+            }
+        }
+    };
+    let Ok(outputs) = outputs else {
+        return ExitCode::FAILURE;
+    };
+
+    // Without streaming, the whole output is in memory at this point.
+    let boxes = outputs.boxes.as_deref().unwrap_or_default();
+    if let Some(compare) = &cli.compare {
+        let previous = match fs::read(compare) {
+            Ok(previous) => previous,
+            Err(error) => {
+                eprintln!("error: could not read {}: {error}", compare.display());
+                return ExitCode::FAILURE;
+            }
+        };
+        if let Some(difference) = first_difference(&previous, boxes) {
+            eprintln!("error: the output differs from {}: {difference}", compare.display());
+            return ExitCode::FAILURE;
+        }
+        println!("✅ Output matches {}", compare.display());
+    }
+
+    if outputs.boxes.is_some()
+        && let Err(error) = fs::write(&output, boxes)
+    {
+        eprintln!("error: could not write {}: {error}", output.display());
+        return ExitCode::FAILURE;
+    }
+    if cli.print_hash {
+        println!("hash: {:016x}", output_hash(boxes));
+    }
+    println!("✅ Successfully wrote word analysis to {}", output.display());
+
+    if let Some((key, cache)) = &cache {
+        let stored = match &outputs.cache_entry {
+            Some(entry) => cache.put(*key, entry),
+            None => Ok(()),
+        };
+        if let Err(error) = stored.and_then(|()| cache.evict(cli.cache_max_age, cli.cache_max_size * 1_000_000)) {
+            eprintln!("warning: could not update the cache: {error}");
+        }
+    }
 
+    for (path, data) in &outputs.images {
+        if let Err(error) = fs::write(path, data) {
+            eprintln!("error: could not write {}: {error}", path.display());
+            return ExitCode::FAILURE;
+        }
+    }
+    if reused {
+        println!("✅ Reused the cached word boxes, skipped compiling and rendering");
+    } else {
+        if cli.per_page {
+            println!(
+                "✅ Rendered {} pages to {} and {}",
+                outputs.rendered_pages,
+                cli.render.display(),
+                cli.render_boxes.display()
+            );
+        } else {
+            println!("✅ Rendered PNG to {}", cli.render.display());
+            println!("✅ Rendered PNG to {}", cli.render_boxes.display());
+        }
+        if let Some(path) = &cli.svg_out {
+            println!("✅ Rendered SVG to {}", path.display());
+        }
+        if let Some(path) = &cli.pdf_out {
+            println!("✅ Rendered PDF to {}", path.display());
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// Extracts the boxes of two versions of a document with the options of the
+/// command line, and writes how they differ to the output file, or stdout.
+fn run_diff(cli: &Cli, before: &Path, after: &Path, output: Option<&Path>, tolerance: f64) -> ExitCode {
+    if let Err(error) = check_font_paths(&cli.font_paths) {
+        eprintln!("error: {error}");
+        return ExitCode::FAILURE;
+    }
+    let builder = world_builder(cli);
+    let Ok(before_boxes) = extract_boxes(cli, &builder, before) else {
+        return ExitCode::FAILURE;
+    };
+    let Ok(after_boxes) = extract_boxes(cli, &builder, after) else {
+        return ExitCode::FAILURE;
+    };
+    let diff = diff_boxes(&before_boxes, &after_boxes, tolerance);
+    match output {
+        Some(path) => {
+            let written = File::create(path).and_then(|file| write_diff(BufWriter::new(file), &diff));
+            if let Err(error) = written {
+                eprintln!("error: could not write {}: {error}", path.display());
+                return ExitCode::FAILURE;
+            }
+            println!(
+                "✅ Wrote the differences to {}: {} added, {} removed, {} moved, {} unchanged",
+                path.display(),
+                diff.added.len(),
+                diff.removed.len(),
+                diff.moved.len(),
+                diff.unchanged
+            );
+        }
+        None => {
+            if let Err(error) = write_diff(io::stdout().lock(), &diff) {
+                eprintln!("error: could not write the differences: {error}");
+                return ExitCode::FAILURE;
+            }
+            println!();
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Writes the outputs, and then again whenever the input file or a file the
+/// last compilation read changes, until interrupted. The world is reused, so
+/// that the fonts stay loaded and comemo's memoization lets Typst lay out
+/// again only what the changes affect.
+fn watch(cli: &Cli, mut world: TypstWrapperWorld, output: &Path) -> ExitCode {
+    let Some(input) = cli.input.as_deref().filter(|input| input.as_os_str() != "-") else {
+        eprintln!("error: --watch needs an input file");
+        return ExitCode::FAILURE;
+    };
+    let input = input.canonicalize().unwrap_or_else(|_| input.to_path_buf());
+    let (sender, receiver) = mpsc::channel();
+    // Directories are watched instead of the files in them, since editors
+    // often save a file by renaming a new one over it, which ends a watch
+    // on the file itself. The watcher stops when it is dropped.
+    let mut watched_dirs = BTreeSet::new();
+    let input_dir = input.parent().unwrap_or(&input).to_path_buf();
+    let watcher = notify::recommended_watcher(sender).and_then(|mut watcher| {
+        watcher.watch(&input_dir, RecursiveMode::NonRecursive)?;
+        Ok(watcher)
+    });
+    let mut watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(error) => {
+            eprintln!("error: could not watch {}: {error}", input_dir.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    watched_dirs.insert(input_dir);
+
+    let rebuild = |world: &TypstWrapperWorld| {
+        let start = Instant::now();
+        let written = extract_and_render(cli, world).and_then(|outputs| {
+            let files = outputs.boxes.iter().map(|boxes| (output, boxes)).chain(
+                outputs.images.iter().map(|(path, data)| (path.as_path(), data)),
+            );
+            for (path, data) in files {
+                fs::write(path, data).map_err(|error| eprintln!("error: could not write {}: {error}", path.display()))?;
+            }
+            Ok(())
+        });
+        // Results that no compilation used for a while are dropped, so that
+        // memory use stays bounded.
+        comemo::evict(10);
+        match written {
+            Ok(()) => println!("[{}] ✅ Wrote {} in {:.2?}", timestamp(), output.display(), start.elapsed()),
+            Err(()) => println!("[{}] ❌ Failed, waiting for the next change", timestamp()),
+        }
+    };
+    rebuild(&world);
+    let mut files = watched_files(&world, &input);
+    watch_dirs(&mut watcher, &mut watched_dirs, &files);
+    println!("👀 Watching {} for changes, press Ctrl+C to stop", input.display());
+
+    loop {
+        let Ok(event) = receiver.recv() else {
+            return ExitCode::FAILURE;
+        };
+        if !changes_document(&files, event) {
+            continue;
+        }
+        // Editors often write a file in several steps, which should only
+        // cause one rebuild.
+        while receiver.recv_timeout(Duration::from_millis(50)).is_ok() {}
+
+        let text = fs::read(&input).map_err(|error| error.to_string()).and_then(|bytes| {
+            decode(&bytes, cli.assume_encoding).map_err(|error| format!("is {error}"))
+        });
+        match text {
+            Ok(text) => world.set_main_text(&text),
+            Err(error) => {
+                eprintln!("error: could not read {}: {error}", input.display());
+                continue;
+            }
+        }
+        world.reset();
+        rebuild(&world);
+        files = watched_files(&world, &input);
+        watch_dirs(&mut watcher, &mut watched_dirs, &files);
+    }
+}
+
+/// The input file and the files of the project that the last compilation
+/// read. Their paths are within the canonical root, as are those of the
+/// events in the directories they are in, so they can be compared as they
+/// are.
+fn watched_files(world: &TypstWrapperWorld, input: &Path) -> BTreeSet<PathBuf> {
+    let mut files: BTreeSet<PathBuf> = world
+        .loaded_files()
+        .into_iter()
+        .filter(|id| id.package().is_none())
+        .filter_map(|id| id.vpath().resolve(world.root()))
+        .collect();
+    files.insert(input.to_path_buf());
+    files
+}
+
+/// Watches the directories of the files, and stops watching those that no
+/// longer have any of them in it.
+fn watch_dirs(watcher: &mut impl Watcher, watched_dirs: &mut BTreeSet<PathBuf>, files: &BTreeSet<PathBuf>) {
+    let dirs: BTreeSet<PathBuf> = files.iter().filter_map(|file| file.parent()).map(Path::to_path_buf).collect();
+    for dir in watched_dirs.difference(&dirs) {
+        _ = watcher.unwatch(dir);
+    }
+    watched_dirs.retain(|dir| dirs.contains(dir));
+    for dir in dirs {
+        if watched_dirs.contains(&dir) {
+            continue;
+        }
+        match watcher.watch(&dir, RecursiveMode::NonRecursive) {
+            Ok(()) => _ = watched_dirs.insert(dir),
+            Err(error) => eprintln!("warning: could not watch {}: {error}", dir.display()),
+        }
+    }
+}
+
+/// Whether a file system event is a change to one of the watched files, as
+/// opposed to e.g. the outputs being written next to them.
+fn changes_document(files: &BTreeSet<PathBuf>, event: notify::Result<notify::Event>) -> bool {
+    let Ok(event) = event else {
+        return false;
+    };
+    !event.kind.is_access() && event.paths.iter().any(|path| files.contains(path))
+}
+
+/// The current local time as `HH:MM:SS`, for messages.
+fn timestamp() -> String {
+    let now = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+    format!("{:02}:{:02}:{:02}", now.hour(), now.minute(), now.second())
+}
+
+/// Extracts the boxes of each input file with the options of the command
+/// line, and writes them to a file of the same name in the output directory.
+/// A document that fails doesn't stop the others.
+fn run_batch(cli: &Cli, inputs: &[PathBuf], out_dir: &Path) -> ExitCode {
+    if let Err(error) = check_font_paths(&cli.font_paths) {
+        eprintln!("error: {error}");
+        return ExitCode::FAILURE;
+    }
+    if let Err(error) = fs::create_dir_all(out_dir) {
+        eprintln!("error: could not create {}: {error}", out_dir.display());
+        return ExitCode::FAILURE;
+    }
+    let extension = match cli.format {
+        Format::Json => "json",
+        Format::Csv => "csv",
+        Format::Ndjson => "ndjson",
+        Format::Hocr => "html",
+        Format::Alto => "xml",
+    };
+    let scale = match cli.coords {
+        Coords::Points => 1.0,
+        Coords::Pixels => f64::from(cli.pixel_per_pt),
+    };
+
+    // All documents are compiled with the same fonts, which are only loaded
+    // once.
+    let builder = world_builder(cli);
+    let mut failed = 0;
+    for input in inputs {
+        let mut path = out_dir.join(input.file_stem().unwrap_or(input.as_os_str()));
+        path.set_extension(extension);
+        let written = extract_boxes_and_sizes(cli, &builder, input).and_then(|(boxes, page_sizes)| {
+            let boxes: Vec<WordBox> = boxes.iter().map(|word_box| word_box.scaled(scale)).collect();
+            let page_sizes: Vec<(f64, f64)> =
+                page_sizes.into_iter().map(|(width, height)| (width * scale, height * scale)).collect();
+            File::create(&path)
+                .and_then(|file| write_boxes(BufWriter::new(file), &boxes, &page_sizes, cli.coords, cli.format))
+                .map_err(|error| eprintln!("error: could not write {}: {error}", path.display()))
+        });
+        // Results only the previous documents used are dropped, so that
+        // memory use stays bounded.
+        comemo::evict(10);
+        if written.is_err() {
+            failed += 1;
+        }
+    }
+
+    let written = inputs.len() - failed;
+    println!("✅ Wrote the boxes of {written} document(s) to {}", out_dir.display());
+    if failed > 0 {
+        eprintln!("error: {failed} document(s) failed");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+/// Compiles the input file with the options of the command line and
+/// extracts its boxes, without rendering anything.
+fn extract_boxes(cli: &Cli, builder: &WorldBuilder, input: &Path) -> Result<Vec<WordBox>, ()> {
+    extract_boxes_and_sizes(cli, builder, input).map(|(boxes, _)| boxes)
+}
+
+/// The boxes of a document, and the width and height of each of its pages.
+type BoxesAndSizes = (Vec<WordBox>, Vec<(f64, f64)>);
+
+/// Like [`extract_boxes`], but also returns the size of each page.
+fn extract_boxes_and_sizes(cli: &Cli, builder: &WorldBuilder, input: &Path) -> Result<BoxesAndSizes, ()> {
+    let cli = Cli { input: Some(input.to_path_buf()), code: None, vfs: None, ..cli.clone() };
+    let world = match load_world(&cli, builder) {
+        Ok((world, _)) => configure_world(&cli, world),
+        Err(LoadError::Encoding(message) | LoadError::Other(message)) => {
+            eprintln!("error: {message}");
+            return Err(());
+        }
+    };
+    let document = compile_document(&cli, &world)?;
+    let boxes = collect_word_boxes(&document, &extract_options(&cli, &world, &document));
+    Ok((boxes, page_sizes(&document)))
+}
+
+/// Sets up the world with the inputs, packages, time and prepended code of
+/// the command line.
+fn configure_world(cli: &Cli, world: TypstWrapperWorld) -> TypstWrapperWorld {
+    let mut world = world
+        .with_inputs(cli.inputs.clone())
+        .with_offline(cli.offline)
+        .with_allowed_paths(cli.allowed_paths.clone());
+    if let Some(package_path) = &cli.package_path {
+        world = world.with_package_path(package_path.clone());
+    }
+    if let Some(now) = cli.now {
+        world = world.with_now(now);
+    } else if cli.deterministic {
+        world = world.with_now(OffsetDateTime::UNIX_EPOCH);
+    }
+    if !cli.prepend.is_empty() {
+        world = world.with_prepended(cli.prepend.clone());
+    }
+    world
+}
+
+/// Searches for the fonts of the command line.
+fn world_builder(cli: &Cli) -> WorldBuilder {
+    if cli.use_system_fonts {
+        WorldBuilder::with_system_fonts(&cli.font_paths)
+    } else {
+        WorldBuilder::new(&cli.font_paths)
+    }
+}
+
+/// Creates the world for the input file, stdin, `--code` or `--vfs`, with the
+/// main file in place and the fonts of the builder. Also returns a name for
+/// the input in messages.
+fn load_world(cli: &Cli, builder: &WorldBuilder) -> Result<(TypstWrapperWorld, String), LoadError> {
+    if let Some(root) = &cli.root
+        && !root.is_dir()
+    {
+        return Err(format!("the project root {} is not a directory", root.display()).into());
+    }
+
+    if let Some(code) = &cli.code {
+        let root = cli.root.as_deref().unwrap_or(Path::new("."));
+        let content = unescape_newlines(code);
+        let world = builder.build(root.to_str().unwrap().to_owned(), content);
+        return Ok((world, "<code>".into()));
+    }
+
+    if let Some(vfs) = &cli.vfs {
+        let json = if vfs.as_os_str() == "-" {
+            read_stdin()?
+        } else {
+            fs::read(vfs).map_err(|error| format!("could not read {}: {error}", vfs.display()))?
+        };
+        let json = decode(&json, Encoding::Utf8)
+            .map_err(|error| LoadError::Encoding(format!("the file system is {error}")))?;
+        let files = parse_vfs(&json)?;
+        let main = VirtualPath::new(&cli.main);
+        let content = files
+            .iter()
+            .find(|(path, _)| *path == main)
+            .ok_or_else(|| format!("the main file {} is not in the file system", cli.main))?;
+        let content = decode(&content.1, Encoding::Utf8)
+            .map_err(|error| LoadError::Encoding(format!("the main file {} is {error}", cli.main)))?;
+        let world = builder
+            .build(String::new(), content)
+            .with_main(main)
+            .with_vfs(files);
+        return Ok((world, cli.main.clone()));
+    }
+
+    let input = cli.input.as_ref().expect("the input is required without --vfs");
+    let from_stdin = input.as_os_str() == "-";
+    let content = if from_stdin {
+        read_stdin()?
+    } else {
+        fs::read(input).map_err(|error| format!("could not read {}: {error}", input.display()))?
+    };
+    let content = decode(&content, cli.assume_encoding).map_err(|error| {
+        let name = if from_stdin { "the input".to_string() } else { input.display().to_string() };
+        let hint = match cli.assume_encoding {
+            Encoding::Utf8 => " (use --assume-encoding for other encodings)",
+            _ => "",
+        };
+        LoadError::Encoding(format!("{name} is {error}{hint}"))
+    })?;
+    if from_stdin && content.trim().is_empty() {
+        return Err("no input on stdin".to_string().into());
+    }
+    let root_path = match &cli.root {
+        Some(root) => root.as_path(),
+        None if from_stdin => Path::new("."),
+        None => input.parent().unwrap_or_else(|| Path::new("")),
+    };
+    let mut world = builder.build(root_path.to_str().unwrap().to_owned(), content);
+    if from_stdin {
+        return Ok((world, "<stdin>".into()));
+    }
+
+    // Identify the main file by its path within the root.
+    let canonical_input = input.canonicalize().unwrap_or_else(|_| input.clone());
+    match VirtualPath::within_root(&canonical_input, world.root()) {
+        Some(path) => world = world.with_main(path),
+        None => {
+            return Err(format!(
+                "the input file {} is not inside the project root {}",
+                input.display(),
+                world.root().display()
+            )
+            .into());
+        }
+    }
+    Ok((world, input.display().to_string()))
+}
+
+/// Replaces each `\n` with a line break, so that multi-line snippets can be
+/// passed on one line. An escaped backslash (`\\`) is kept as is, so that
+/// Typst still sees it as one.
+fn unescape_newlines(code: &str) -> String {
+    let mut unescaped = String::with_capacity(code.len());
+    let mut chars = code.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some(next) => {
+                unescaped.push(c);
+                unescaped.push(next);
+            }
+            None => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Reads all of stdin, which must not be empty.
+fn read_stdin() -> Result<Vec<u8>, String> {
+    let mut content = Vec::new();
+    io::stdin()
+        .read_to_end(&mut content)
+        .map_err(|error| format!("could not read the input from stdin: {error}"))?;
+    if content.is_empty() {
+        return Err("no input on stdin".into());
+    }
+    Ok(content)
+}
+
+/// Why the input could not be loaded.
+enum LoadError {
+    /// The input is not valid text in the expected encoding.
+    Encoding(String),
+    /// Any other problem, e.g. a missing file.
+    Other(String),
+}
+
+impl From<String> for LoadError {
+    fn from(message: String) -> Self {
+        LoadError::Other(message)
+    }
+}
+
+/// The exit code when the input is not valid text, like `EX_DATAERR`.
+const ENCODING_EXIT_CODE: u8 = 65;
+
+/// The exit code when `--timeout` is exceeded, like the `timeout` utility's.
+const TIMEOUT_EXIT_CODE: u8 = 124;
+
+/// The contents of the files to write once extraction and rendering are done.
+struct Outputs {
+    /// The serialized word boxes, or `None` if they were streamed to the
+    /// output file.
+    boxes: Option<Vec<u8>>,
+    /// The encoded PNG images, with the paths to write them to.
+    images: Vec<(PathBuf, Vec<u8>)>,
+    /// How many pages were rendered.
+    rendered_pages: usize,
+    /// The cache entry for the word boxes, with `--cache-dir`.
+    cache_entry: Option<Entry>,
+}
+
+/// Compiles the document, printing its diagnostics. Fails if it has errors,
+/// or warnings with `--deny-warnings`.
+fn compile_document(cli: &Cli, world: &TypstWrapperWorld) -> Result<PagedDocument, ()> {
+    let compiled = compile(world);
+    print_diagnostics(world, &compiled.warnings);
+    let document = match compiled.output {
+        Ok(document) => document,
+        Err(ExtractError::Compile(diagnostics)) => {
+            print_diagnostics(world, &diagnostics);
+            return Err(());
+        }
+    };
+    if cli.deny_warnings && !compiled.warnings.is_empty() {
+        eprintln!("error: aborting due to {} warning(s)", compiled.warnings.len());
+        return Err(());
+    }
+    Ok(document)
+}
+
+/// The options for extracting the boxes of the document that the command
+/// line asks for.
+fn extract_options(cli: &Cli, world: &TypstWrapperWorld, document: &PagedDocument) -> ExtractOptions {
+    ExtractOptions {
+        granularity: cli.granularity,
+        output_level: cli.output_level,
+        reading_order: cli.reading_order,
+        include_whitespace: cli.include_whitespace,
+        include_delimiters: cli.include_delimiters,
+        split_mode: cli.split_mode,
+        unicode_delimiters: cli.unicode_delimiters,
+        delimiters: cli.delimiters.clone(),
+        merge_hyphenation: cli.merge_hyphenation,
+        split_math: cli.split_math,
+        tight_boxes: cli.tight_boxes,
+        box_mode: cli.box_mode,
+        include_font_info: !cli.no_font_info,
+        include_color: cli.include_color,
+        include_baseline: cli.include_baseline,
+        include_lang: cli.include_lang,
+        source: cli.include_source.then(|| world.main_source().clone()),
+        sources: if cli.with_source_locations { world.sources() } else { Vec::new() },
+        pages: cli.pages.as_ref().map(|ranges| ranges.indices(document.pages.len())),
+        tree: cli.tree,
+        matches: cli.matches.clone(),
+        match_groups: cli.match_group,
+        min_font_size: cli.min_font_size,
+        min_score: cli.min_score,
+        filter_color: cli.filter_color,
+        dedup_epsilon: cli.dedup.then_some(cli.dedup_epsilon),
+    }
+}
+
+/// Compiles the document, extracts its word boxes and renders the images.
+///
+/// Diagnostics are printed as they occur; an error means compilation failed.
+fn extract_and_render(cli: &Cli, world: &TypstWrapperWorld) -> Result<Outputs, ()> {
     // Layout document
-    let document: PagedDocument = typst::compile(&world)
-        .output
-        .expect("Error compiling typst");
+    let document = compile_document(cli, world)?;
 
     // Collect word and box data into our `WordBox` struct.
-    let mut word_boxes: Vec<WordBox> = vec![];
-    for (word, (x, y, w, h)) in words_with_boxes(&document, cli.include_whitespace, cli.include_delimiters) {
-        word_boxes.push(WordBox {
-            word: word.to_string(),
-            x,
-            y,
-            width: w,
-            height: h,
+    let opts = extract_options(cli, world, &document);
+    let pages = opts.pages.as_deref();
+    if pages.is_some_and(|pages| pages.is_empty()) {
+        eprintln!("error: --pages selects none of the {} page(s) of the document", document.pages.len());
+        return Err(());
+    }
+    // How the boxes are placed in the output.
+    let scale = match cli.coords {
+        Coords::Points => 1.0,
+        Coords::Pixels => f64::from(cli.pixel_per_pt),
+    };
+    // Boxes are drawn relative to their pages, whatever the output uses.
+    let offsets = match cli.coordinate_space {
+        CoordinateSpace::Page => None,
+        CoordinateSpace::Merged => Some(merged_page_offsets(&document, pages)),
+    };
+    // The height the y-coordinates are measured up from, if they are: that
+    // of the merged image, whose last page is at the bottom, or of each page.
+    let flip_heights: Option<Vec<f64>> = (cli.origin == Origin::BottomLeft).then(|| match &offsets {
+        Some(offsets) => {
+            let merged_height = selected_pages(&document, pages)
+                .last()
+                .map_or(0.0, |(index, page)| offsets[index] + page.frame.height().to_pt());
+            vec![merged_height; document.pages.len()]
+        }
+        None => page_sizes(&document).into_iter().map(|(_, height)| height).collect(),
+    });
+    let to_output = |word_box: &WordBox| {
+        let word_box = match &offsets {
+            Some(offsets) => word_box.moved_down(offsets[word_box.page]),
+            None => word_box.clone(),
+        };
+        match &flip_heights {
+            Some(heights) => word_box.flipped(heights[word_box.page]).scaled(scale),
+            None => word_box.scaled(scale),
+        }
+    };
+    let word_boxes = if cli.format == Format::Ndjson && streams_output(cli) {
+        // Each box is written as soon as it is found, before anything is
+        // rendered. The boxes are only kept to draw them afterwards.
+        let output = cli.output.as_ref().expect("the output path is set");
+        let mut word_boxes = Vec::new();
+        let boxes = box_extractor::word_boxes(&document, &opts).map(|word_box| {
+            let output_box = to_output(&word_box);
+            word_boxes.push(word_box);
+            output_box
         });
+        if let Err(error) = File::create(output).and_then(|file| write_ndjson(BufWriter::new(file), boxes)) {
+            eprintln!("error: could not write {}: {error}", output.display());
+            return Err(());
+        }
+        word_boxes
+    } else {
+        collect_word_boxes(&document, &opts)
+    };
+    if let Some(pattern) = &cli.matches
+        && word_boxes.is_empty()
+    {
+        eprintln!("warning: no boxes match {pattern}, the output has none");
+    }
+    // Once per family, as its variants (e.g. bold) usually cover the same
+    // characters.
+    let uncovered: BTreeSet<String> = fonts_used(&document, world)
+        .into_iter()
+        .filter(|font| font.coverage.is_some())
+        .map(|font| font.family)
+        .collect();
+    for family in uncovered {
+        eprintln!("warning: some characters are not covered by any font and are shown as boxes in {family}");
+    }
+    let label_boxes = match &cli.query {
+        Some(selector) => match parse_selector(world, selector) {
+            Ok(selector) => {
+                let mut label_boxes = query_boxes(&document, &selector);
+                label_boxes.retain(|label_box| pages.is_none_or(|pages| pages.contains(&label_box.page)));
+                Some(label_boxes)
+            }
+            Err(message) => {
+                eprintln!("error: invalid selector {selector}: {message}");
+                return Err(());
+            }
+        },
+        None => None,
+    };
+    let match_boxes = cli.find.as_ref().map(|phrase| {
+        let mut match_boxes = find_phrase(&document, phrase);
+        match_boxes.retain(|match_box| pages.is_none_or(|pages| pages.contains(&match_box.page)));
+        match_boxes
+    });
+    let cell_boxes = cli.tables.then(|| {
+        let mut cell_boxes = table_cells(&document, &world.sources());
+        cell_boxes.retain(|cell_box| pages.is_none_or(|pages| pages.contains(&cell_box.page)));
+        cell_boxes
+    });
+
+    // The boxes of queried elements, found phrases and table cells are drawn
+    // along with the words.
+    let drawn_boxes = match (&label_boxes, &match_boxes, &cell_boxes) {
+        (None, None, None) => Cow::Borrowed(&word_boxes),
+        (label_boxes, match_boxes, cell_boxes) => Cow::Owned(
+            [
+                word_boxes.as_slice(),
+                label_boxes.as_deref().unwrap_or_default(),
+                match_boxes.as_deref().unwrap_or_default(),
+                cell_boxes.as_deref().unwrap_or_default(),
+            ]
+            .concat(),
+        ),
+    };
+
+    let render_options = RenderOptions {
+        max_page_size: (cli.max_page_size > 0.0).then(|| Abs::cm(cli.max_page_size)),
+        box_color: cli.box_color,
+        box_width: cli.box_width,
+        absolute_box_width: cli.absolute_box_width,
+        box_stroke: !cli.no_box_stroke,
+        box_fill: cli.box_fill,
+        legend: cli.legend.then(|| world.builder()),
+        jobs: cli.jobs,
+        highlight: cli.highlight.as_ref().map(|indices| indices.0.clone()),
+    };
+    let report = |error: RenderError| eprintln!("error: {error}");
+    let rendered_pages = selected_pages(&document, pages).count();
+    let mut images = Vec::new();
+    // The size of each image without boxes, for the output.
+    let mut image_sizes = Vec::new();
+    if cli.per_page {
+        // Render each page separately, drawing only the boxes of that page.
+        let pixmaps = render_pages_to_png(&document, cli.pixel_per_pt, &render_options, pages).map_err(report)?;
+        let pixmaps_boxes =
+            render_pages_to_png_with_boxes(&document, cli.pixel_per_pt, &drawn_boxes, &render_options, pages)
+                .map_err(report)?;
+        // Pages keep their number in the document in the file names.
+        let page_count = document.pages.len();
+        let page_indices = selected_pages(&document, pages).map(|(index, _)| index);
+        for (index, (pixmap, pixmap_boxes)) in page_indices.zip(pixmaps.iter().zip(&pixmaps_boxes)) {
+            image_sizes.push(ImageSize { page: Some(index), width: pixmap.width(), height: pixmap.height() });
+            images.push((page_path(&cli.render, index, page_count), pixmap.encode_png().unwrap()));
+            images.push((page_path(&cli.render_boxes, index, page_count), pixmap_boxes.encode_png().unwrap()));
+        }
+    } else {
+        // Render a PNG as before, using the path from the CLI args.
+        let pixmap = render_to_png(&document, cli.pixel_per_pt, &render_options, pages).map_err(report)?;
+        image_sizes.push(ImageSize { page: None, width: pixmap.width(), height: pixmap.height() });
+        images.push((cli.render.clone(), pixmap.encode_png().unwrap()));
+
+        // Render a PNG, now passing the word_boxes to draw them.
+        let pixmap_boxes = render_to_png_with_boxes(&document, cli.pixel_per_pt, &drawn_boxes, &render_options, pages)
+            .map_err(report)?;
+        images.push((cli.render_boxes.clone(), pixmap_boxes.encode_png().unwrap()));
+    }
+    if let Some(path) = &cli.svg_out {
+        let svg = render_to_svg_with_boxes(&document, &drawn_boxes, &render_options, pages);
+        images.push((path.clone(), svg.into_bytes()));
+    }
+    if let Some(path) = &cli.pdf_out {
+        let pdf = render_to_pdf_with_boxes(&document, &drawn_boxes, &render_options, pages).map_err(report)?;
+        images.push((path.clone(), pdf));
     }
 
-    // Serialize the vector of WordBox structs into a pretty JSON string.
-    let json_output = serde_json::to_string_pretty(&word_boxes)
-        .expect("Failed to serialize data to JSON.");    
-    fs::write(&cli.output, json_output)
-        .expect("Failed to write JSON output file.");
-    println!("✅ Successfully wrote word analysis to {}", cli.output.display());
-    
-    // Render a PNG as before, using the path from the CLI args.
-    let pixmap = render_to_png(&document, cli.pixel_per_pt);
-    let data: Vec<u8> = pixmap.encode_png().unwrap();
-    fs::write(&cli.render, data).unwrap();
-    println!("✅ Rendered PNG to {}", cli.render.display());
-
-    // Render a PNG, now passing the word_boxes to draw them.
-    let pixmap_boxes = render_to_png_with_boxes(&document, cli.pixel_per_pt, &word_boxes, cli.bbox_stroke);
-    let data: Vec<u8> = pixmap_boxes.encode_png().unwrap();
-    fs::write(&cli.render_boxes, data).unwrap();
-    println!("✅ Rendered PNG to {}", cli.render.display());
-}
\ No newline at end of file
+    // Serialize the vector of WordBox structs in the chosen format.
+    let scaled = |boxes: &[WordBox]| -> Vec<WordBox> { boxes.iter().map(to_output).collect() };
+    let output_boxes = match cli.format {
+        // NDJSON boxes are scaled one at a time when they are written.
+        Format::Ndjson => Vec::new(),
+        _ => scaled(&word_boxes),
+    };
+    let output_page_sizes: Vec<(f64, f64)> = page_sizes(&document)
+        .into_iter()
+        .map(|(width, height)| (width * scale, height * scale))
+        .collect();
+    let metadata = cli.with_metadata.then(|| Metadata::collect(&document, world));
+    let output_labels = label_boxes.as_deref().map(scaled);
+    let output_matches = match_boxes.as_deref().map(scaled);
+    let output_cells = cell_boxes.as_deref().map(scaled);
+    let serialize = |writer: &mut dyn Write| match cli.format {
+        // Each box is scaled just before it is written, so that no copy of
+        // all of them is made.
+        Format::Ndjson => write_ndjson(writer, word_boxes.iter().map(to_output)),
+        Format::Json if cli.legacy_json => {
+            if metadata.is_some() || output_labels.is_some() || output_matches.is_some() || output_cells.is_some() {
+                write_json_object(
+                    writer,
+                    &output_boxes,
+                    output_labels.as_deref(),
+                    output_matches.as_deref(),
+                    output_cells.as_deref(),
+                    metadata.as_ref(),
+                )
+            } else {
+                write_json(writer, &output_boxes)
+            }
+        }
+        Format::Json => {
+            let mut envelope =
+                Envelope::new(&output_boxes, &output_page_sizes, cli.coords).with_pages(pages).with_origin(cli.origin);
+            if let Some(labels) = &output_labels {
+                envelope = envelope.with_labels(labels);
+            }
+            if let Some(matches) = &output_matches {
+                envelope = envelope.with_matches(matches);
+            }
+            if let Some(cells) = &output_cells {
+                envelope = envelope.with_cells(cells);
+            }
+            if let Some(metadata) = &metadata {
+                envelope = envelope.with_metadata(metadata);
+            }
+            envelope = envelope.with_images(f64::from(cli.pixel_per_pt), image_sizes);
+            write_json_envelope(writer, &envelope)
+        }
+        _ => write_boxes(writer, &output_boxes, &output_page_sizes, cli.coords, cli.format),
+    };
+
+    if streams_output(cli) {
+        // Nothing needs the whole output, so it goes straight to the file,
+        // where NDJSON already is.
+        let output = cli.output.as_ref().expect("the output path is set");
+        if cli.format != Format::Ndjson
+            && let Err(error) = File::create(output).and_then(|file| serialize(&mut BufWriter::new(file)))
+        {
+            eprintln!("error: could not write {}: {error}", output.display());
+            return Err(());
+        }
+        return Ok(Outputs { boxes: None, images, rendered_pages, cache_entry: None });
+    }
+    let mut boxes = Vec::new();
+    serialize(&mut boxes).expect("Failed to serialize the word boxes.");
+
+    let cache_entry = cli.cache_dir.as_ref().and_then(|_| Entry::new(world, &boxes));
+    Ok(Outputs { boxes: Some(boxes), images, rendered_pages, cache_entry })
+}
+
+/// Checks that a file can be written, i.e. that its directory exists and
+/// isn't read-only, and that the path isn't a directory itself.
+fn check_writable(path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let metadata = fs::metadata(dir).map_err(|error| match error.kind() {
+        io::ErrorKind::NotFound => io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", dir.display())),
+        _ => error,
+    })?;
+    if !metadata.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::NotADirectory, format!("{} is not a directory", dir.display())));
+    }
+    if metadata.permissions().readonly() {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("{} is read-only", dir.display())));
+    }
+    if path.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::IsADirectory, "it is a directory"));
+    }
+    Ok(())
+}
+
+/// Whether the output can be written while it is serialized, because no
+/// option needs all of it first, and the compilation can't be cut short.
+fn streams_output(cli: &Cli) -> bool {
+    cli.timeout.is_none() && !cli.print_hash && cli.compare.is_none() && cli.cache_dir.is_none()
+}
+
+/// Describes the options that change the serialized word boxes, for the
+/// cache key. The document, the files it reads and the fonts are part of the
+/// key anyway, and options that only affect the images, where files are
+/// written or whether the run fails are left out, since nothing is rendered
+/// when cached boxes are used. New options that change the output must be
+/// added here.
+fn cache_options(cli: &Cli) -> String {
+    macro_rules! describe {
+        ($($option:ident),* $(,)?) => {
+            [$(format!(concat!(stringify!($option), "={:?}"), cli.$option)),*].join("\n")
+        };
+    }
+    describe!(
+        input, main, root, prepend, inputs, now, deterministic,
+        format, with_metadata, query, find, tables, legacy_json,
+        granularity, output_level, reading_order, tree, include_whitespace, include_delimiters, split_mode,
+        unicode_delimiters, delimiters, merge_hyphenation, split_math, matches, match_group, min_font_size,
+        min_score, filter_color, dedup, dedup_epsilon, tight_boxes, box_mode, no_font_info, include_color,
+        include_baseline, include_lang, include_source, with_source_locations,
+        pixel_per_pt, coords, coordinate_space, origin, pages, per_page,
+    )
+}
+
+/// Parses a `KEY=VALUE` pair for `sys.inputs`.
+fn parse_input(raw: &str) -> Result<(String, String), String> {
+    let (key, value) = raw
+        .split_once('=')
+        .ok_or("input must be a key and a value separated by an equal sign")?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err("input key must not be empty".into());
+    }
+    Ok((key.to_owned(), value.to_owned()))
+}
+
+/// Inclusive ranges of page numbers, starting at 1, as given to `--pages`.
+#[derive(Debug, Clone)]
+struct PageRanges(Vec<(usize, usize)>);
+
+impl PageRanges {
+    /// Returns the sorted indices of the selected pages of a document with
+    /// the given number of pages. Pages beyond its end are skipped with a
+    /// warning.
+    fn indices(&self, page_count: usize) -> Vec<usize> {
+        let mut indices = Vec::new();
+        for &(start, end) in &self.0 {
+            if end > page_count {
+                let range = if start == end { format!("page {start}") } else { format!("pages {start}-{end}") };
+                eprintln!("warning: {range} out of range, the document has {page_count} page(s)");
+            }
+            indices.extend((start..=end.min(page_count)).map(|page| page - 1));
+        }
+        indices.sort_unstable();
+        indices.dedup();
+        indices
+    }
+}
+
+/// Parses page ranges like `1,3,5-9` for `--pages`.
+fn parse_pages(raw: &str) -> Result<PageRanges, String> {
+    let number = |part: &str| match part.trim().parse::<usize>() {
+        Ok(0) => Err("pages are numbered from 1".to_string()),
+        Ok(number) => Ok(number),
+        Err(_) => Err(format!("invalid page number `{}`", part.trim())),
+    };
+    let mut ranges = Vec::new();
+    for part in raw.split(',') {
+        let range = match part.split_once('-') {
+            Some((start, end)) => (number(start)?, number(end)?),
+            None => (number(part)?, number(part)?),
+        };
+        if range.0 > range.1 {
+            return Err(format!("invalid page range `{}`", part.trim()));
+        }
+        ranges.push(range);
+    }
+    Ok(PageRanges(ranges))
+}
+
+/// Indices of boxes in the output, as given to `--highlight`.
+#[derive(Debug, Clone)]
+struct Indices(Vec<RangeInclusive<usize>>);
+
+/// Parses indices and inclusive ranges of them like `3,10-12` for
+/// `--highlight`. Ranges are kept as they are, so that a range like
+/// `0-999999999` doesn't take any memory.
+fn parse_indices(raw: &str) -> Result<Indices, String> {
+    let index = |part: &str| part.trim().parse::<usize>().map_err(|_| format!("invalid index `{}`", part.trim()));
+    let mut indices = Vec::new();
+    for part in raw.split(',') {
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => (index(start)?, index(end)?),
+            None => (index(part)?, index(part)?),
+        };
+        if start > end {
+            return Err(format!("invalid range `{}`", part.trim()));
+        }
+        indices.push(start..=end);
+    }
+    Ok(Indices(indices))
+}
+
+/// Parses the color for `--box-fill` like [`parse_color`], except that
+/// without an alpha channel the fill is mostly transparent, so that the text
+/// below stays readable.
+fn parse_fill_color(raw: &str) -> Result<[u8; 4], String> {
+    let [r, g, b, a] = parse_color(raw)?;
+    let has_alpha = raw.strip_prefix('#').unwrap_or(raw).len() == 8;
+    Ok([r, g, b, if has_alpha { a } else { 0x33 }])
+}
+
+/// Parses a regular expression for `--match`.
+fn parse_regex(raw: &str) -> Result<Regex, String> {
+    Regex::new(raw).map_err(|error| error.to_string())
+}
+
+/// Parses an RFC 3339 datetime for `--now`.
+fn parse_now(raw: &str) -> Result<OffsetDateTime, String> {
+    OffsetDateTime::parse(raw, &Rfc3339).map_err(|error| error.to_string())
+}
+
+/// Parses an `RRGGBBAA` or `RRGGBB` hex color, optionally prefixed with `#`.
+fn parse_color(raw: &str) -> Result<[u8; 4], String> {
+    let hex = raw.strip_prefix('#').unwrap_or(raw);
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return Err("color must have the form RRGGBBAA or RRGGBB".into());
+    }
+    let channel = |index: usize| {
+        hex.get(index * 2..index * 2 + 2)
+            .map_or(Ok(255), |digits| u8::from_str_radix(digits, 16))
+            .map_err(|error| error.to_string())
+    };
+    Ok([channel(0)?, channel(1)?, channel(2)?, channel(3)?])
+}
+
+/// Parses a number of days for `--cache-max-age`, small enough to be a
+/// `Duration`.
+fn parse_max_age(raw: &str) -> Result<Duration, String> {
+    let days = raw.parse::<f64>().map_err(|error| error.to_string())?;
+    Duration::try_from_secs_f64(days * 24.0 * 60.0 * 60.0)
+        .map_err(|_| "the age must be a number of days that isn't negative or too large".into())
+}
+
+/// Parses a positive number of seconds for `--timeout`, small enough to be
+/// a `Duration`.
+fn parse_timeout(raw: &str) -> Result<Duration, String> {
+    let seconds = raw.parse::<f64>().map_err(|error| error.to_string())?;
+    match Duration::try_from_secs_f64(seconds) {
+        Ok(timeout) if !timeout.is_zero() => Ok(timeout),
+        _ => Err("timeout must be a positive number of seconds".into()),
+    }
+}
+
+/// Returns the path of a single page's image, e.g. `output-01.png` for the
+/// first of ten pages. The page number is zero-padded so the files sort in
+/// page order.
+fn page_path(path: &Path, index: usize, page_count: usize) -> PathBuf {
+    let width = page_count.to_string().len();
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut file_name = format!("{stem}-{:0width$}", index + 1);
+    if let Some(extension) = path.extension() {
+        file_name.push('.');
+        file_name.push_str(&extension.to_string_lossy());
+    }
+    path.with_file_name(file_name)
+}
@@ -0,0 +1,112 @@
+use serde::Serialize;
+use typst::layout::{Frame, FrameItem, PagedDocument};
+use typst::text::{Font, FontStyle};
+
+use crate::TypstWrapperWorld;
+
+/// Information about a compiled document beyond its word boxes.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Metadata {
+    /// The title set with `#set document(title: ..)`, or `null`.
+    pub title: Option<String>,
+    /// The authors set with `#set document(author: ..)`.
+    pub author: Vec<String>,
+    /// The keywords set with `#set document(keywords: ..)`.
+    pub keywords: Vec<String>,
+    /// The fonts text was set in, in the order they were first used.
+    pub fonts: Vec<FontUsage>,
+}
+
+impl Metadata {
+    /// Collects the metadata of a document compiled in the given world.
+    pub fn collect(document: &PagedDocument, world: &TypstWrapperWorld) -> Metadata {
+        let info = &document.info;
+        Metadata {
+            title: info.title.as_ref().map(|title| title.to_string()),
+            author: info.author.iter().map(|author| author.to_string()).collect(),
+            keywords: info.keywords.iter().map(|keyword| keyword.to_string()).collect(),
+            fonts: fonts_used(document, world),
+        }
+    }
+}
+
+/// A font face that some text of the document was set in.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FontUsage {
+    /// The font family, e.g. `Libertinus Serif`.
+    pub family: String,
+    /// The style: `normal`, `italic` or `oblique`.
+    pub style: String,
+    /// The weight, from 100 (thin) to 900 (black).
+    pub weight: u16,
+    /// The stretch as a ratio of the normal width.
+    pub stretch: f64,
+    /// The path of the font file, or `embedded` for fonts built into Typst.
+    pub source: String,
+    /// A note if some characters are missing from the font, which then shows
+    /// its `.notdef` glyph (usually a box) for them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coverage: Option<String>,
+}
+
+/// Returns the distinct font faces that text of the document was set in,
+/// which may include fallback fonts besides the requested ones.
+pub fn fonts_used(document: &PagedDocument, world: &TypstWrapperWorld) -> Vec<FontUsage> {
+    // The fonts in order of first use, and whether they lack a glyph.
+    // Documents use few fonts, so a list is fast enough to look them up.
+    let mut fonts: Vec<(Font, bool)> = Vec::new();
+    for page in &document.pages {
+        collect_fonts(&page.frame, &mut fonts);
+    }
+
+    fonts
+        .into_iter()
+        .map(|(font, has_notdef)| {
+            let info = font.info();
+            FontUsage {
+                family: info.family.clone(),
+                style: style_name(info.variant.style).to_string(),
+                weight: info.variant.weight.to_number(),
+                stretch: info.variant.stretch.to_ratio().get(),
+                source: world
+                    .font_path(&font)
+                    .map_or_else(|| "embedded".to_string(), |path| path.display().to_string()),
+                coverage: has_notdef.then(|| {
+                    "some characters are not covered by this font (.notdef glyph)".to_string()
+                }),
+            }
+        })
+        .collect()
+}
+
+/// Returns the name of a font style: `normal`, `italic` or `oblique`.
+pub fn style_name(style: FontStyle) -> &'static str {
+    match style {
+        FontStyle::Normal => "normal",
+        FontStyle::Italic => "italic",
+        FontStyle::Oblique => "oblique",
+    }
+}
+
+/// Records the fonts of all text in a frame and its nested groups.
+fn collect_fonts(frame: &Frame, fonts: &mut Vec<(Font, bool)>) {
+    for (_, item) in frame.items() {
+        match item {
+            FrameItem::Text(text_item) => {
+                let index = match fonts.iter().position(|(font, _)| *font == text_item.font) {
+                    Some(index) => index,
+                    None => {
+                        fonts.push((text_item.font.clone(), false));
+                        fonts.len() - 1
+                    }
+                };
+                // Glyph 0 is `.notdef`, shown for characters the font lacks.
+                if text_item.glyphs.iter().any(|glyph| glyph.id == 0) {
+                    fonts[index].1 = true;
+                }
+            }
+            FrameItem::Group(group) => collect_fonts(&group.frame, fonts),
+            _ => {}
+        }
+    }
+}
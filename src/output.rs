@@ -0,0 +1,918 @@
+use std::borrow::{Borrow, Cow};
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::diff::BoxDiff;
+use crate::hash::Fnv;
+use crate::{Metadata, WordBox};
+
+/// The file format word boxes are written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// A pretty-printed JSON object with the version of the output, some
+    /// information about the document and the boxes of each page. See
+    /// [`Envelope`].
+    #[default]
+    Json,
+    /// One comma-separated row per box, with a header row.
+    Csv,
+    /// One JSON object per box and line (newline-delimited JSON), written
+    /// and flushed page by page, so that consumers can start reading early.
+    Ndjson,
+    /// An hOCR document with a `ocr_page` per page, an `ocr_line` per line
+    /// and an `ocrx_word` per word, with integer pixel coordinates.
+    Hocr,
+    /// An ALTO 4 XML document with a `Page` per page, and `TextBlock`s of
+    /// `TextLine`s of `String`s.
+    Alto,
+}
+
+/// The unit of the output coordinates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Coords {
+    /// Typographic points (1/72 inch), as used by Typst.
+    #[default]
+    Points,
+    /// Pixels of the rendered images.
+    Pixels,
+}
+
+/// The origin of the output coordinates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum CoordinateSpace {
+    /// The top-left corner of the page each box is on.
+    #[default]
+    Page,
+    /// The top-left corner of the merged image of all pages, where the pages
+    /// are stacked with a gap of 1pt.
+    Merged,
+}
+
+/// Where the y-axis of the output coordinates starts and which way it goes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Origin {
+    /// The top edge, with y increasing downwards, as in images and Typst.
+    #[default]
+    TopLeft,
+    /// The bottom edge, with y increasing upwards, as in PDF. Each `y` is
+    /// then that of the bottom edge of its box. See [`WordBox::flipped`].
+    BottomLeft,
+}
+
+/// Writes the word boxes in the given format.
+///
+/// `page_sizes` holds the `(width, height)` of each page, in the same unit as
+/// the boxes, which is given by `coords`. They are only used by formats that
+/// describe pages or declare their unit.
+pub fn write_boxes(
+    writer: impl Write,
+    word_boxes: &[WordBox],
+    page_sizes: &[(f64, f64)],
+    coords: Coords,
+    format: Format,
+) -> io::Result<()> {
+    match format {
+        Format::Json => write_json_envelope(writer, &Envelope::new(word_boxes, page_sizes, coords)),
+        Format::Csv => write_csv(writer, word_boxes),
+        Format::Ndjson => write_ndjson(writer, word_boxes),
+        Format::Hocr => write_hocr(writer, word_boxes, page_sizes),
+        Format::Alto => write_alto(writer, word_boxes, page_sizes, coords),
+    }
+}
+
+/// The version of the JSON output. It is increased whenever fields of the
+/// output are added, removed or change their meaning, so that consumers can
+/// tell which fields to expect.
+pub const JSON_VERSION: u32 = 20;
+
+/// The JSON output: the boxes of each page, together with the version of the
+/// output and information about the document.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Envelope<'a> {
+    /// Always [`JSON_VERSION`].
+    pub version: u32,
+    pub document: DocumentInfo<'a>,
+    /// The extracted pages, in order.
+    pub pages: Vec<PageBoxes<'a>>,
+}
+
+/// Information about the document in the JSON output.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DocumentInfo<'a> {
+    /// The number of pages of the document, including those not extracted.
+    pub page_count: usize,
+    /// The unit of all coordinates: `pt` or `px`.
+    pub unit: &'static str,
+    /// Where the y-axis starts: `top-left`, or `bottom-left` if y increases
+    /// upwards from the bottom edge of the page (or of the merged image).
+    pub origin: &'static str,
+    /// How many pixels of the rendered images a pt is.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pixel_per_pt: Option<f64>,
+    /// The size of the rendered images, if there are any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub images: Option<Vec<ImageSize>>,
+    /// The document's metadata, if requested.
+    #[serde(flatten)]
+    pub metadata: Option<&'a Metadata>,
+}
+
+/// The size of a rendered image in the JSON output.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct ImageSize {
+    /// The index of the page the image shows, or `None` for the merged
+    /// image of all pages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<usize>,
+    /// The width in pixels.
+    pub width: u32,
+    /// The height in pixels.
+    pub height: u32,
+}
+
+/// The boxes of a page in the JSON output.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PageBoxes<'a> {
+    /// The index of the page, starting at 0.
+    pub index: usize,
+    pub width: f64,
+    pub height: f64,
+    /// The boxes of the words (or lines, glyphs, ...) on the page.
+    pub words: Vec<&'a WordBox>,
+    /// The boxes of queried elements on the page, if there was a query.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<Vec<&'a WordBox>>,
+    /// The boxes of the occurrences of a phrase on the page, if one was
+    /// searched for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matches: Option<Vec<&'a WordBox>>,
+    /// The boxes of the cells of tables and grids on the page, if they were
+    /// searched for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cells: Option<Vec<&'a WordBox>>,
+}
+
+impl<'a> Envelope<'a> {
+    /// Creates the output for all pages, whose `(width, height)` are given in
+    /// the same unit as the boxes.
+    pub fn new(word_boxes: &'a [WordBox], page_sizes: &[(f64, f64)], coords: Coords) -> Self {
+        let pages = page_sizes
+            .iter()
+            .enumerate()
+            .map(|(index, &(width, height))| PageBoxes {
+                index,
+                width,
+                height,
+                words: word_boxes.iter().filter(|word_box| word_box.page == index).collect(),
+                labels: None,
+                matches: None,
+                cells: None,
+            })
+            .collect();
+        let unit = match coords {
+            Coords::Points => "pt",
+            Coords::Pixels => "px",
+        };
+        Envelope {
+            version: JSON_VERSION,
+            document: DocumentInfo {
+                page_count: page_sizes.len(),
+                unit,
+                origin: "top-left",
+                pixel_per_pt: None,
+                images: None,
+                metadata: None,
+            },
+            pages,
+        }
+    }
+
+    /// Keeps only the pages with the given indices, if there is a filter.
+    pub fn with_pages(mut self, page_filter: Option<&[usize]>) -> Self {
+        if let Some(indices) = page_filter {
+            self.pages.retain(|page| indices.contains(&page.index));
+        }
+        self
+    }
+
+    /// Adds the boxes of queried elements to their pages.
+    pub fn with_labels(mut self, labels: &'a [WordBox]) -> Self {
+        for page in &mut self.pages {
+            page.labels = Some(labels.iter().filter(|label| label.page == page.index).collect());
+        }
+        self
+    }
+
+    /// Adds the boxes of the occurrences of a phrase to their pages.
+    pub fn with_matches(mut self, matches: &'a [WordBox]) -> Self {
+        for page in &mut self.pages {
+            page.matches = Some(matches.iter().filter(|found| found.page == page.index).collect());
+        }
+        self
+    }
+
+    /// Adds the boxes of the cells of tables and grids to their pages.
+    pub fn with_cells(mut self, cells: &'a [WordBox]) -> Self {
+        for page in &mut self.pages {
+            page.cells = Some(cells.iter().filter(|cell| cell.page == page.index).collect());
+        }
+        self
+    }
+
+    /// Records where the y-axis of the coordinates starts.
+    pub fn with_origin(mut self, origin: Origin) -> Self {
+        self.document.origin = match origin {
+            Origin::TopLeft => "top-left",
+            Origin::BottomLeft => "bottom-left",
+        };
+        self
+    }
+
+    /// Adds the resolution and the sizes of the rendered images next to the
+    /// page count, so that consumers can convert between the output's unit
+    /// and the pixels of the images.
+    pub fn with_images(mut self, pixel_per_pt: f64, images: Vec<ImageSize>) -> Self {
+        self.document.pixel_per_pt = Some(pixel_per_pt);
+        self.document.images = Some(images);
+        self
+    }
+
+    /// Adds the document's metadata next to the page count.
+    pub fn with_metadata(mut self, metadata: &'a Metadata) -> Self {
+        self.document.metadata = Some(metadata);
+        self
+    }
+}
+
+/// Writes the output as a pretty-printed JSON object.
+pub fn write_json_envelope(mut writer: impl Write, envelope: &Envelope) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut writer, envelope)?;
+    writer.flush()
+}
+
+/// Returns the JSON Schema of the output written by [`write_json_envelope`].
+pub fn json_schema() -> serde_json::Value {
+    let number = serde_json::json!({ "type": "number" });
+    let optional_string = serde_json::json!({ "type": ["string", "null"] });
+    let string_list = serde_json::json!({ "type": "array", "items": { "type": "string" } });
+    // Separate values, as the whole schema in one macro call nests too deeply.
+    let rects = serde_json::json!({
+        "type": "array",
+        "items": {
+            "type": "object",
+            "required": ["x", "y", "width", "height"],
+            "properties": { "x": number, "y": number, "width": number, "height": number },
+        },
+    });
+    let quad = serde_json::json!({
+        "type": "array",
+        "items": { "type": "array", "items": number, "minItems": 2, "maxItems": 2 },
+        "minItems": 4,
+        "maxItems": 4,
+    });
+    let cluster = serde_json::json!({
+        "type": "array",
+        "items": { "type": "integer", "minimum": 0 },
+        "minItems": 2,
+        "maxItems": 2,
+    });
+    // The properties of a box in two parts, as one call would nest too
+    // deeply as well.
+    let mut properties = serde_json::json!({
+        "page": { "type": "integer", "minimum": 0 },
+        "order": { "type": "integer", "minimum": 0 },
+        "word": { "type": "string" },
+        "x": number,
+        "y": number,
+        "width": number,
+        "height": number,
+        "kind": { "type": "string" },
+        "font": { "type": "string" },
+        "subfamily": { "type": "string" },
+        "weight": { "type": "integer" },
+        "style": { "enum": ["normal", "italic", "oblique"] },
+        "stretch": number,
+        "font_size": number,
+        "mixed_sizes": { "type": "boolean" },
+        "color": { "type": "string" },
+        "rotation": number,
+        "quad": quad,
+        "link": { "type": "string" },
+        "label": { "type": "string" },
+        "table": { "type": "integer", "minimum": 0 },
+        "row": { "type": "integer", "minimum": 0 },
+        "col": { "type": "integer", "minimum": 0 },
+        "rowspan": { "type": "integer", "minimum": 1 },
+        "colspan": { "type": "integer", "minimum": 1 },
+        "missing_glyph_count": { "type": "integer", "minimum": 0 },
+    });
+    let more_properties = serde_json::json!({
+        "score": { "type": "number", "minimum": 0, "maximum": 1 },
+        "baseline": number,
+        "ascender": number,
+        "descender": number,
+        "word_index": { "type": "integer", "minimum": 0 },
+        "lang": { "type": "string" },
+        "script": { "type": "string" },
+        "dir": { "enum": ["ltr", "rtl"] },
+        "glyph_id": { "type": "integer", "minimum": 0 },
+        "cluster": cluster,
+        "x_advance": number,
+        "x_offset": number,
+        "src_start": { "type": "integer", "minimum": 0 },
+        "src_end": { "type": "integer", "minimum": 0 },
+        "file": { "type": "string" },
+        "line": { "type": "integer", "minimum": 1 },
+        "column": { "type": "integer", "minimum": 1 },
+        "rects": rects,
+        "children": { "type": "array", "items": { "$ref": "#/$defs/box" } },
+    });
+    if let (Some(properties), serde_json::Value::Object(more_properties)) =
+        (properties.as_object_mut(), more_properties)
+    {
+        properties.extend(more_properties);
+    }
+    let word_box = serde_json::json!({
+        "type": "object",
+        "required": ["page", "word", "x", "y", "width", "height", "kind"],
+        "properties": properties,
+    });
+    serde_json::json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "Typst box extractor output",
+        "type": "object",
+        "required": ["version", "document", "pages"],
+        "properties": {
+            "version": { "const": JSON_VERSION },
+            "document": {
+                "type": "object",
+                "required": ["page_count", "unit", "origin"],
+                "properties": {
+                    "page_count": { "type": "integer", "minimum": 0 },
+                    "unit": { "enum": ["pt", "px"] },
+                    "origin": { "enum": ["top-left", "bottom-left"] },
+                    "pixel_per_pt": number,
+                    "images": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "required": ["width", "height"],
+                            "properties": {
+                                "page": { "type": "integer", "minimum": 0 },
+                                "width": { "type": "integer", "minimum": 0 },
+                                "height": { "type": "integer", "minimum": 0 },
+                            },
+                        },
+                    },
+                    "title": optional_string,
+                    "author": string_list,
+                    "keywords": string_list,
+                    "fonts": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "required": ["family", "style", "weight", "stretch", "source"],
+                            "properties": {
+                                "family": { "type": "string" },
+                                "style": { "enum": ["normal", "italic", "oblique"] },
+                                "weight": { "type": "integer" },
+                                "stretch": number,
+                                "source": { "type": "string" },
+                                "coverage": { "type": "string" },
+                            },
+                        },
+                    },
+                },
+            },
+            "pages": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "required": ["index", "width", "height", "words"],
+                    "properties": {
+                        "index": { "type": "integer", "minimum": 0 },
+                        "width": number,
+                        "height": number,
+                        "words": { "type": "array", "items": { "$ref": "#/$defs/box" } },
+                        "labels": { "type": "array", "items": { "$ref": "#/$defs/box" } },
+                        "matches": { "type": "array", "items": { "$ref": "#/$defs/box" } },
+                        "cells": { "type": "array", "items": { "$ref": "#/$defs/box" } },
+                    },
+                },
+            },
+        },
+        "$defs": {
+            "box": word_box,
+        },
+    })
+}
+
+/// Writes the word boxes as a pretty-printed JSON array, the JSON output
+/// before it had a version.
+pub fn write_json(mut writer: impl Write, word_boxes: &[WordBox]) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut writer, word_boxes)?;
+    writer.flush()
+}
+
+/// Writes how the boxes of two versions of a document differ as a
+/// pretty-printed JSON object.
+pub fn write_diff(mut writer: impl Write, diff: &BoxDiff) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut writer, diff)?;
+    writer.flush()
+}
+
+/// Writes the word boxes as a pretty-printed JSON object like
+/// [`write_json`] does, but with the boxes under `words` and, if given, the boxes of queried elements under `labels`,
+/// those of the occurrences of a phrase under `matches`, those of table cells
+/// under `cells` and the document's metadata fields (e.g. `fonts`) next to
+/// them.
+pub fn write_json_object(
+    mut writer: impl Write,
+    word_boxes: &[WordBox],
+    labels: Option<&[WordBox]>,
+    matches: Option<&[WordBox]>,
+    cells: Option<&[WordBox]>,
+    metadata: Option<&Metadata>,
+) -> io::Result<()> {
+    #[derive(Serialize)]
+    struct Document<'a> {
+        words: &'a [WordBox],
+        #[serde(skip_serializing_if = "Option::is_none")]
+        labels: Option<&'a [WordBox]>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        matches: Option<&'a [WordBox]>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cells: Option<&'a [WordBox]>,
+        #[serde(flatten)]
+        metadata: Option<&'a Metadata>,
+    }
+
+    serde_json::to_writer_pretty(&mut writer, &Document { words: word_boxes, labels, matches, cells, metadata })?;
+    writer.flush()
+}
+
+/// Writes the word boxes as newline-delimited JSON, one compact object per
+/// line.
+///
+/// The boxes are serialized as the iterator yields them, e.g. from
+/// [`crate::word_boxes`], and the writer is flushed after each page, so that
+/// neither the boxes nor their serialization need to be held in memory.
+pub fn write_ndjson<B: Borrow<WordBox>>(
+    mut writer: impl Write,
+    word_boxes: impl IntoIterator<Item = B>,
+) -> io::Result<()> {
+    let mut last_page = None;
+    for word_box in word_boxes {
+        let word_box = word_box.borrow();
+        if last_page.is_some_and(|page| page != word_box.page) {
+            writer.flush()?;
+        }
+        last_page = Some(word_box.page);
+        serde_json::to_writer(&mut writer, word_box)?;
+        writeln!(writer)?;
+    }
+    writer.flush()
+}
+
+/// Writes the word boxes as CSV, one row per box.
+///
+/// The columns are `page,word,x,y,width,height,kind`, followed by `font`,
+/// `font_size`, `subfamily`, `weight`, `style`, `stretch`, `mixed_sizes`, `color`,
+/// `rotation`, `link` and `baseline`, `ascender`, `descender`, `word_index`,
+/// `lang`, `script` and `glyph_id`, `cluster_start`, `cluster_end`,
+/// `x_advance`, `x_offset`, `src_start`, `src_end`, `file`, `line`,
+/// `column`, `order`, `score` and `dir` if any box has them. Rows are written one at a time,
+/// so a buffered writer keeps memory use flat.
+pub fn write_csv(mut writer: impl Write, word_boxes: &[WordBox]) -> io::Result<()> {
+    let has_font = word_boxes.iter().any(|word_box| word_box.font.is_some());
+    let has_mixed_sizes = word_boxes.iter().any(|word_box| word_box.mixed_sizes);
+    let has_color = word_boxes.iter().any(|word_box| word_box.color.is_some());
+    let has_rotation = word_boxes.iter().any(|word_box| word_box.rotation.is_some());
+    let has_link = word_boxes.iter().any(|word_box| word_box.link.is_some());
+    let has_baseline = word_boxes.iter().any(|word_box| word_box.baseline.is_some());
+    let has_word_index = word_boxes.iter().any(|word_box| word_box.word_index.is_some());
+    let has_lang = word_boxes.iter().any(|word_box| word_box.lang.is_some());
+    let has_shaping = word_boxes.iter().any(|word_box| word_box.glyph_id.is_some());
+    let has_source = word_boxes.iter().any(|word_box| word_box.src_start.is_some());
+    let has_location = word_boxes.iter().any(|word_box| word_box.file.is_some());
+    let has_order = word_boxes.iter().any(|word_box| word_box.order.is_some());
+    let has_score = word_boxes.iter().any(|word_box| word_box.score.is_some());
+    let has_dir = word_boxes.iter().any(|word_box| word_box.dir.is_some());
+
+    let mut header = vec!["page", "word", "x", "y", "width", "height", "kind"];
+    if has_font {
+        header.extend(["font", "font_size", "subfamily", "weight", "style", "stretch"]);
+    }
+    if has_mixed_sizes {
+        header.push("mixed_sizes");
+    }
+    if has_color {
+        header.push("color");
+    }
+    if has_rotation {
+        header.push("rotation");
+    }
+    if has_link {
+        header.push("link");
+    }
+    if has_baseline {
+        header.extend(["baseline", "ascender", "descender"]);
+    }
+    if has_word_index {
+        header.push("word_index");
+    }
+    if has_lang {
+        header.extend(["lang", "script"]);
+    }
+    if has_shaping {
+        header.extend(["glyph_id", "cluster_start", "cluster_end", "x_advance", "x_offset"]);
+    }
+    if has_source {
+        header.extend(["src_start", "src_end"]);
+    }
+    if has_location {
+        header.extend(["file", "line", "column"]);
+    }
+    if has_order {
+        header.push("order");
+    }
+    if has_score {
+        header.push("score");
+    }
+    if has_dir {
+        header.push("dir");
+    }
+
+    writeln!(writer, "{}", header.join(","))?;
+
+    let optional = |value: Option<String>| value.unwrap_or_default();
+    for word_box in word_boxes {
+        let mut row = vec![
+            word_box.page.to_string(),
+            word_box.word.clone(),
+            word_box.x.to_string(),
+            word_box.y.to_string(),
+            word_box.width.to_string(),
+            word_box.height.to_string(),
+            word_box.kind.clone(),
+        ];
+        if has_font {
+            row.push(optional(word_box.font.clone()));
+            row.push(optional(word_box.font_size.map(|size| size.to_string())));
+            row.push(optional(word_box.subfamily.clone()));
+            row.push(optional(word_box.weight.map(|weight| weight.to_string())));
+            row.push(optional(word_box.style.clone()));
+            row.push(optional(word_box.stretch.map(|stretch| stretch.to_string())));
+        }
+        if has_mixed_sizes {
+            row.push(word_box.mixed_sizes.to_string());
+        }
+        if has_color {
+            row.push(optional(word_box.color.clone()));
+        }
+        if has_rotation {
+            row.push(optional(word_box.rotation.map(|rotation| rotation.to_string())));
+        }
+        if has_link {
+            row.push(optional(word_box.link.clone()));
+        }
+        if has_baseline {
+            for value in [word_box.baseline, word_box.ascender, word_box.descender] {
+                row.push(optional(value.map(|value| value.to_string())));
+            }
+        }
+        if has_word_index {
+            row.push(optional(word_box.word_index.map(|index| index.to_string())));
+        }
+        if has_lang {
+            row.push(optional(word_box.lang.clone()));
+            row.push(optional(word_box.script.clone()));
+        }
+        if has_shaping {
+            row.push(optional(word_box.glyph_id.map(|id| id.to_string())));
+            row.push(optional(word_box.cluster.map(|(start, _)| start.to_string())));
+            row.push(optional(word_box.cluster.map(|(_, end)| end.to_string())));
+            row.push(optional(word_box.x_advance.map(|x_advance| x_advance.to_string())));
+            row.push(optional(word_box.x_offset.map(|x_offset| x_offset.to_string())));
+        }
+        if has_source {
+            row.push(optional(word_box.src_start.map(|start| start.to_string())));
+            row.push(optional(word_box.src_end.map(|end| end.to_string())));
+        }
+        if has_location {
+            row.push(optional(word_box.file.clone()));
+            row.push(optional(word_box.line.map(|line| line.to_string())));
+            row.push(optional(word_box.column.map(|column| column.to_string())));
+        }
+        if has_order {
+            row.push(optional(word_box.order.map(|order| order.to_string())));
+        }
+        if has_score {
+            row.push(optional(word_box.score.map(|score| score.to_string())));
+        }
+        if has_dir {
+            row.push(optional(word_box.dir.clone()));
+        }
+
+        let row: Vec<_> = row.iter().map(|field| csv_field(field)).collect();
+        writeln!(writer, "{}", row.join(","))?;
+    }
+    writer.flush()
+}
+
+/// Writes the boxes of lines as an hOCR document.
+///
+/// Each box is an `ocr_line` whose children are its `ocrx_word`s; a box
+/// without children is a line of a single word. Lines are placed on the
+/// `ocr_page` of their page. Coordinates are rounded outwards to whole
+/// pixels, so the boxes should already be scaled to pixels.
+pub fn write_hocr(
+    mut writer: impl Write,
+    line_boxes: &[WordBox],
+    page_sizes: &[(f64, f64)],
+) -> io::Result<()> {
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Transitional//EN" "http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd">"#
+    )?;
+    writeln!(writer, r#"<html xmlns="http://www.w3.org/1999/xhtml" xml:lang="en" lang="en">"#)?;
+    writeln!(writer, " <head>")?;
+    writeln!(writer, "  <title></title>")?;
+    writeln!(writer, r#"  <meta http-equiv="Content-Type" content="text/html;charset=utf-8"/>"#)?;
+    writeln!(
+        writer,
+        r#"  <meta name="ocr-system" content="{} {}"/>"#,
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    )?;
+    writeln!(writer, r#"  <meta name="ocr-capabilities" content="ocr_page ocr_line ocrx_word"/>"#)?;
+    writeln!(writer, " </head>")?;
+    writeln!(writer, " <body>")?;
+
+    let mut word_number = 0;
+    let mut line_number = 0;
+    for (page, &(width, height)) in page_sizes.iter().enumerate() {
+        writeln!(
+            writer,
+            r#"  <div class="ocr_page" id="page_{}" title="bbox 0 0 {} {}; ppageno {page}">"#,
+            page + 1,
+            width.ceil() as i64,
+            height.ceil() as i64,
+        )?;
+        for line_box in line_boxes.iter().filter(|line_box| line_box.page == page) {
+            line_number += 1;
+            writeln!(
+                writer,
+                r#"   <span class="ocr_line" id="line_{line_number}" title="{}">"#,
+                hocr_bbox(line_box)
+            )?;
+            let words = if line_box.children.is_empty() {
+                std::slice::from_ref(line_box)
+            } else {
+                &line_box.children
+            };
+            for word_box in words.iter().filter(|word_box| !word_box.word.trim().is_empty()) {
+                word_number += 1;
+                writeln!(
+                    writer,
+                    r#"    <span class="ocrx_word" id="word_{word_number}" title="{}">{}</span>"#,
+                    hocr_bbox(word_box),
+                    escape_xml(&word_box.word)
+                )?;
+            }
+            writeln!(writer, "   </span>")?;
+        }
+        writeln!(writer, "  </div>")?;
+    }
+
+    writeln!(writer, " </body>")?;
+    writeln!(writer, "</html>")?;
+    writer.flush()
+}
+
+/// Writes the boxes of lines as an ALTO 4 document.
+///
+/// As for hOCR, each box is a `TextLine` whose children are its `String`s.
+/// Consecutive lines of a page are grouped into a `TextBlock` until a line
+/// is further below the previous one than half a line height, as between
+/// paragraphs, or moves back up, as at the start of a new column.
+///
+/// ALTO has no unit for points, so point coordinates are written in
+/// `inch1200` (1/1200 inch) and pixel coordinates as `pixel`.
+pub fn write_alto(
+    mut writer: impl Write,
+    line_boxes: &[WordBox],
+    page_sizes: &[(f64, f64)],
+    coords: Coords,
+) -> io::Result<()> {
+    let (unit, factor) = match coords {
+        Coords::Points => ("inch1200", 1200.0 / 72.0),
+        Coords::Pixels => ("pixel", 1.0),
+    };
+    let position = |x: f64, y: f64, width: f64, height: f64| {
+        format!(
+            r#"HPOS="{}" VPOS="{}" WIDTH="{}" HEIGHT="{}""#,
+            alto_number(x * factor),
+            alto_number(y * factor),
+            alto_number(width * factor),
+            alto_number(height * factor),
+        )
+    };
+    let box_position =
+        |word_box: &WordBox| position(word_box.x, word_box.y, word_box.width, word_box.height);
+
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<alto xmlns="http://www.loc.gov/standards/alto/ns-v4#" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:schemaLocation="http://www.loc.gov/standards/alto/ns-v4# http://www.loc.gov/standards/alto/v4/alto-4-2.xsd">"#
+    )?;
+    writeln!(writer, "  <Description>")?;
+    writeln!(writer, "    <MeasurementUnit>{unit}</MeasurementUnit>")?;
+    writeln!(writer, "    <OCRProcessing ID=\"processing_1\">")?;
+    writeln!(writer, "      <ocrProcessingStep>")?;
+    writeln!(writer, "        <processingSoftware>")?;
+    writeln!(writer, "          <softwareName>{}</softwareName>", env!("CARGO_PKG_NAME"))?;
+    writeln!(writer, "          <softwareVersion>{}</softwareVersion>", env!("CARGO_PKG_VERSION"))?;
+    writeln!(writer, "        </processingSoftware>")?;
+    writeln!(writer, "      </ocrProcessingStep>")?;
+    writeln!(writer, "    </OCRProcessing>")?;
+    writeln!(writer, "  </Description>")?;
+    writeln!(writer, "  <Layout>")?;
+
+    let (mut block_number, mut line_number, mut word_number) = (0, 0, 0);
+    for (page, &(width, height)) in page_sizes.iter().enumerate() {
+        writeln!(
+            writer,
+            r#"    <Page ID="page_{}" PHYSICAL_IMG_NR="{}" WIDTH="{}" HEIGHT="{}">"#,
+            page + 1,
+            page + 1,
+            alto_number(width * factor),
+            alto_number(height * factor),
+        )?;
+        writeln!(writer, "      <PrintSpace {}>", position(0.0, 0.0, width, height))?;
+        let page_lines: Vec<&WordBox> =
+            line_boxes.iter().filter(|line_box| line_box.page == page).collect();
+        for block in group_into_blocks(&page_lines) {
+            block_number += 1;
+            let (x, y, width, height) = block
+                .iter()
+                .map(|line_box| (line_box.x, line_box.y, line_box.width, line_box.height))
+                .reduce(crate::word_analysis::union_bbox)
+                .unwrap();
+            writeln!(
+                writer,
+                r#"        <TextBlock ID="block_{block_number}" {}>"#,
+                position(x, y, width, height)
+            )?;
+            for line_box in block {
+                line_number += 1;
+                writeln!(
+                    writer,
+                    r#"          <TextLine ID="line_{line_number}" {}>"#,
+                    box_position(line_box)
+                )?;
+                let words = if line_box.children.is_empty() {
+                    std::slice::from_ref(line_box)
+                } else {
+                    &line_box.children
+                };
+                for word_box in words.iter().filter(|word_box| !word_box.word.trim().is_empty()) {
+                    word_number += 1;
+                    writeln!(
+                        writer,
+                        r#"            <String ID="string_{word_number}" CONTENT="{}" {}/>"#,
+                        escape_xml(&word_box.word),
+                        box_position(word_box)
+                    )?;
+                }
+                writeln!(writer, "          </TextLine>")?;
+            }
+            writeln!(writer, "        </TextBlock>")?;
+        }
+        writeln!(writer, "      </PrintSpace>")?;
+        writeln!(writer, "    </Page>")?;
+    }
+
+    writeln!(writer, "  </Layout>")?;
+    writeln!(writer, "</alto>")?;
+    writer.flush()
+}
+
+/// Hashes serialized output with 64-bit FNV-1a, which unlike the std hasher
+/// gives the same hash on every platform and Rust version.
+pub fn output_hash(output: &[u8]) -> u64 {
+    Fnv::hash(output)
+}
+
+/// Describes the first record that differs between two serialized outputs,
+/// or returns `None` if they are the same.
+///
+/// JSON output is compared box by box (those of all pages, the top-level
+/// array, or the array under `words`), and any other format line by line.
+pub fn first_difference(previous: &[u8], current: &[u8]) -> Option<String> {
+    if previous == current {
+        return None;
+    }
+    let records = |output: &[u8]| -> Option<Vec<String>> {
+        let value: serde_json::Value = serde_json::from_slice(output).ok()?;
+        let array = match value {
+            serde_json::Value::Array(array) => array,
+            serde_json::Value::Object(mut object) => match (object.remove("pages"), object.remove("words")) {
+                (Some(serde_json::Value::Array(pages)), _) => pages
+                    .into_iter()
+                    .filter_map(|mut page| match page.get_mut("words")?.take() {
+                        serde_json::Value::Array(array) => Some(array),
+                        _ => None,
+                    })
+                    .flatten()
+                    .collect(),
+                (_, Some(serde_json::Value::Array(array))) => array,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(array.iter().map(|record| record.to_string()).collect())
+    };
+    let (previous, current, unit) = match (records(previous), records(current)) {
+        (Some(previous), Some(current)) => (previous, current, "box"),
+        _ => {
+            let lines = |output: &[u8]| -> Vec<String> {
+                String::from_utf8_lossy(output).lines().map(str::to_owned).collect()
+            };
+            (lines(previous), lines(current), "line")
+        }
+    };
+
+    let missing = "(none)".to_string();
+    for index in 0..previous.len().max(current.len()) {
+        let before = previous.get(index).unwrap_or(&missing);
+        let after = current.get(index).unwrap_or(&missing);
+        if before != after {
+            return Some(format!("{unit} {index} differs\n  before: {before}\n  after:  {after}"));
+        }
+    }
+    // The records are equal, but not their formatting (e.g. the metadata).
+    Some("the outputs differ outside of the boxes".to_string())
+}
+
+/// Splits the lines of a page into blocks of consecutive, closely spaced lines.
+fn group_into_blocks<'a>(lines: &[&'a WordBox]) -> Vec<Vec<&'a WordBox>> {
+    let mut blocks: Vec<Vec<&WordBox>> = Vec::new();
+    for &line in lines {
+        if let Some(previous) = blocks.last().and_then(|block| block.last()) {
+            let gap = line.y - (previous.y + previous.height);
+            if line.y > previous.y && gap <= previous.height / 2.0 {
+                blocks.last_mut().unwrap().push(line);
+                continue;
+            }
+        }
+        blocks.push(vec![line]);
+    }
+    blocks
+}
+
+/// Rounds an ALTO coordinate to two decimals.
+fn alto_number(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// Formats the `bbox x0 y0 x1 y1` property of a box, rounded outwards to
+/// whole pixels.
+fn hocr_bbox(word_box: &WordBox) -> String {
+    let x0 = word_box.x.floor().max(0.0) as i64;
+    let y0 = word_box.y.floor().max(0.0) as i64;
+    let x1 = (word_box.x + word_box.width).ceil().max(0.0) as i64;
+    let y1 = (word_box.y + word_box.height).ceil().max(0.0) as i64;
+    format!("bbox {x0} {y0} {x1} {y1}")
+}
+
+/// Escapes the characters that are special in XML (and HTML) text and
+/// attributes.
+pub(crate) fn escape_xml(text: &str) -> Cow<'_, str> {
+    if !text.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Quotes a CSV field if needed, as described in RFC 4180.
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
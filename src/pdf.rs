@@ -0,0 +1,90 @@
+use std::num::NonZeroUsize;
+
+use typst::layout::{Abs, Frame, FrameItem, PageRanges, PagedDocument, Point, Size};
+use typst::syntax::Span;
+use typst::visualize::{Color, Curve, FixedStroke, Geometry, Shape};
+use typst_pdf::PdfOptions;
+
+use crate::render::{box_color, box_width_factor, fill_color, RenderError, RenderOptions};
+use crate::word_analysis::selected_pages;
+use crate::WordBox;
+
+/// Exports the pages as a PDF with `typst-pdf` and overlays the word boxes
+/// as vector shapes on top, so that the text of the pages stays selectable.
+///
+/// Boxes are rectangles, a polygon if they are rotated, and one rectangle
+/// per line for a sentence that wraps. As in the SVG, the outlines are
+/// [`RenderOptions::box_width`] pt wide.
+pub fn render_to_pdf_with_boxes(
+    document: &PagedDocument,
+    word_boxes: &[WordBox],
+    options: &RenderOptions,
+    page_filter: Option<&[usize]>,
+) -> Result<Vec<u8>, RenderError> {
+    let selected: Vec<usize> = selected_pages(document, page_filter).map(|(page_index, _)| page_index).collect();
+    if selected.is_empty() {
+        return Err(RenderError::NoPages);
+    }
+
+    let mut document = document.clone();
+    for &page_index in &selected {
+        let frame = &mut document.pages[page_index].frame;
+        for word_box in word_boxes.iter().filter(|word_box| word_box.page == page_index) {
+            push_box(frame, word_box, options);
+        }
+    }
+
+    // Dropping the other pages from the document would leave links to them
+    // dangling, so they are left out of the export instead.
+    let page_ranges = selected
+        .iter()
+        .map(|&page_index| {
+            let number = NonZeroUsize::new(page_index + 1);
+            number..=number
+        })
+        .collect();
+    let pdf_options = PdfOptions { page_ranges: Some(PageRanges::new(page_ranges)), ..PdfOptions::default() };
+    typst_pdf::pdf(&document, &pdf_options).map_err(|diagnostics| {
+        let messages: Vec<&str> = diagnostics.iter().map(|diagnostic| diagnostic.message.as_str()).collect();
+        RenderError::Pdf(messages.join("; "))
+    })
+}
+
+/// Adds the shapes of a box on top of the contents of its page.
+fn push_box(frame: &mut Frame, word_box: &WordBox, options: &RenderOptions) {
+    let [r, g, b, a] = box_color(word_box, options);
+    let stroke = options.box_stroke.then(|| {
+        let width = options.box_width * box_width_factor(word_box, options);
+        FixedStroke::from_pair(Color::from_u8(r, g, b, a), Abs::pt(f64::from(width)))
+    });
+    let fill = fill_color(word_box, options).map(|[r, g, b, a]| Color::from_u8(r, g, b, a).into());
+    let shape = |geometry| {
+        FrameItem::Shape(
+            Shape { geometry, fill: fill.clone(), fill_rule: Default::default(), stroke: stroke.clone() },
+            Span::detached(),
+        )
+    };
+    let rect = |x: f64, y: f64, width: f64, height: f64| {
+        (Point::new(Abs::pt(x), Abs::pt(y)), shape(Geometry::Rect(Size::new(Abs::pt(width), Abs::pt(height)))))
+    };
+
+    if !word_box.rects.is_empty() {
+        // Wrapped sentences are drawn on each line they are on.
+        for line in &word_box.rects {
+            let (position, item) = rect(line.x, line.y, line.width, line.height);
+            frame.push(position, item);
+        }
+    } else if let Some(quad) = word_box.quad {
+        let mut curve = Curve::new();
+        let [first, rest @ ..] = quad.map(|(x, y)| Point::new(Abs::pt(x), Abs::pt(y)));
+        curve.move_(first);
+        for corner in rest {
+            curve.line(corner);
+        }
+        curve.close();
+        frame.push(Point::zero(), shape(Geometry::Curve(curve)));
+    } else {
+        let (position, item) = rect(word_box.x, word_box.y, word_box.width, word_box.height);
+        frame.push(position, item);
+    }
+}
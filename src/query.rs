@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+
+use comemo::Track;
+use typst::foundations::{LocatableSelector, Scope, Selector};
+use typst::introspection::{Location, Tag};
+use typst::layout::{Frame, FrameItem, PagedDocument, Transform};
+use typst::syntax::Span;
+use typst::World;
+use typst_eval::EvalMode;
+
+use crate::word_analysis::{lines_with_boxes, transform_word, union_bbox, BBox, TextOptions, Word};
+use crate::{TypstWrapperWorld, WordBox};
+
+/// Parses a selector like `typst query` does: it is evaluated as Typst code,
+/// so it can be a label (`<intro>`), an element function (`heading`), or a
+/// more complex selector (`heading.where(level: 1)`).
+pub fn parse_selector(world: &TypstWrapperWorld, selector: &str) -> Result<Selector, String> {
+    let world: &dyn World = world;
+    let value = typst_eval::eval_string(
+        &typst::ROUTINES,
+        world.track(),
+        selector,
+        Span::detached(),
+        EvalMode::Code,
+        Scope::new(),
+    )
+    .map_err(|errors| {
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        messages.join(", ")
+    })?;
+    let selector = value
+        .cast::<LocatableSelector>()
+        .map_err(|error| error.message().to_string())?;
+    Ok(selector.0)
+}
+
+/// Returns the boxes of all elements matching the selector.
+///
+/// The box of an element encloses everything laid out between its start and
+/// end tags: text, shapes and images. An element broken across pages gets a
+/// box on each page. Elements without any visible content (e.g. `metadata`)
+/// get an empty box at their position. The `word` of each box is the text
+/// laid out within it, and its `kind` the element's name.
+pub fn query_boxes(document: &PagedDocument, selector: &Selector) -> Vec<WordBox> {
+    let elements = document.introspector.query(selector);
+    let indices: HashMap<Location, usize> = elements
+        .iter()
+        .enumerate()
+        .filter_map(|(index, element)| Some((element.location()?, index)))
+        .collect();
+
+    // The box of each element on each page, found by walking the pages in
+    // order while tracking which of the elements are open.
+    let mut covered: Vec<Vec<(usize, Word)>> = vec![Vec::new(); elements.len()];
+    let mut open = Vec::new();
+    for (page_index, page) in document.pages.iter().enumerate() {
+        let mut page_covered = HashMap::new();
+        covered_in_frame(&page.frame, Transform::identity(), &indices, &mut open, &mut page_covered);
+        for (index, word) in page_covered {
+            covered[index].push((page_index, word));
+        }
+    }
+
+    let mut boxes = Vec::new();
+    for (element, mut pages) in elements.iter().zip(covered) {
+        if pages.is_empty()
+            && let Some(location) = element.location()
+        {
+            let position = document.introspector.position(location);
+            let (x, y) = (position.point.x.to_pt(), position.point.y.to_pt());
+            pages.push((position.page.get() - 1, Word::plain(String::new(), (x, y, 0.0, 0.0), "")));
+        }
+        pages.sort_by_key(|(page, _)| *page);
+        for (page, word) in pages {
+            let word = Word::plain(word.text, word.bbox, element.func().name());
+            boxes.push(WordBox {
+                label: element.label().map(|label| label.resolve().as_str().to_string()),
+                ..WordBox::new(page, word)
+            });
+        }
+    }
+    boxes
+}
+
+/// Adds the boxes and text of the items in a frame and its nested groups to
+/// each element that is open at the time, i.e. whose start tag has been seen
+/// but not its end tag.
+fn covered_in_frame(
+    frame: &Frame,
+    ts: Transform,
+    indices: &HashMap<Location, usize>,
+    open: &mut Vec<usize>,
+    covered: &mut HashMap<usize, Word>,
+) {
+    for (pos, item) in frame.items() {
+        let item_ts = ts.pre_concat(Transform::translate(pos.x, pos.y));
+        let (text, bbox): (&str, BBox) = match item {
+            FrameItem::Text(text_item) => {
+                let metrics = text_item.font.metrics();
+                let ascender = metrics.ascender.at(text_item.size).to_pt();
+                let descender = metrics.descender.at(text_item.size).to_pt();
+                (&text_item.text, (0.0, -ascender, text_item.width().to_pt(), ascender - descender))
+            }
+            FrameItem::Shape(shape, _) => {
+                let size = shape.geometry.bbox_size();
+                ("", (0.0, 0.0, size.x.to_pt(), size.y.to_pt()))
+            }
+            FrameItem::Image(_, size, _) => ("", (0.0, 0.0, size.x.to_pt(), size.y.to_pt())),
+            FrameItem::Group(group) => {
+                covered_in_frame(&group.frame, item_ts.pre_concat(group.transform), indices, open, covered);
+                continue;
+            }
+            FrameItem::Tag(Tag::Start(content)) => {
+                if let Some(&index) = content.location().and_then(|location| indices.get(&location)) {
+                    open.push(index);
+                }
+                continue;
+            }
+            FrameItem::Tag(Tag::End(location, _)) => {
+                if let Some(index) = indices.get(location) {
+                    open.retain(|open_index| open_index != index);
+                }
+                continue;
+            }
+            FrameItem::Link(_, _) => continue,
+        };
+
+        let bbox = transform_word(Word::plain(String::new(), bbox, ""), item_ts).bbox;
+        for &index in open.iter() {
+            let word = covered.entry(index).or_insert_with(|| Word::plain(String::new(), bbox, ""));
+            word.text.push_str(text);
+            word.bbox = union_bbox(word.bbox, bbox);
+        }
+    }
+}
+
+/// Returns the boxes of all occurrences of a phrase in the text of a
+/// document.
+///
+/// The lines of each page (see [`lines_with_boxes`]) are joined in the order
+/// they were laid out, so a phrase can span several runs of text and lines,
+/// but not pages. Matching ignores case and treats any run of whitespace as
+/// a single space. The box of an occurrence encloses all words it covers,
+/// even partially, its `word` is their text and its `kind` is `match`.
+pub fn find_phrase(document: &PagedDocument, phrase: &str) -> Vec<WordBox> {
+    let phrase = normalize(phrase, |_| None).0;
+    if phrase.is_empty() {
+        return Vec::new();
+    }
+    let options = TextOptions { include_delimiters: true, ..TextOptions::default() };
+
+    let mut boxes = Vec::new();
+    let mut lines = lines_with_boxes(document, options, None).peekable();
+    while let Some(&(page, _, _)) = lines.peek() {
+        // The text of the page, with the index of the word each of its
+        // characters is part of.
+        let mut text = String::new();
+        let mut owners = Vec::new();
+        let mut words = Vec::new();
+        while let Some((_, line, line_words)) = lines.next_if(|(line_page, _, _)| *line_page == page) {
+            text.push(' ');
+            owners.push(None);
+            // The words are in the line's text in order, separated by spaces.
+            let mut cursor = 0;
+            for word in line_words {
+                let start = line.text[cursor..].find(&word.text).map_or(cursor, |offset| cursor + offset);
+                text.push_str(&line.text[cursor..start]);
+                owners.extend(std::iter::repeat_n(None, start - cursor));
+                text.push_str(&word.text);
+                owners.extend(std::iter::repeat_n(Some(words.len()), word.text.len()));
+                cursor = start + word.text.len();
+                words.push(word);
+            }
+        }
+
+        let (text, owners) = normalize(&text, |index| owners[index]);
+        for (start, found) in text.match_indices(&phrase) {
+            let mut covered: Vec<usize> = owners[start..start + found.len()].iter().flatten().copied().collect();
+            covered.dedup();
+            let Some(&first) = covered.first() else {
+                continue;
+            };
+            let mut word = Word::plain(words[first].text.clone(), words[first].bbox, "match");
+            for &index in &covered[1..] {
+                word.text.push(' ');
+                word.text.push_str(&words[index].text);
+                word.bbox = union_bbox(word.bbox, words[index].bbox);
+            }
+            boxes.push(WordBox::new(page, word));
+        }
+    }
+    boxes
+}
+
+/// Lowercases text and replaces each run of whitespace with a single space,
+/// trimming it at both ends. Also returns, for each byte of the result, the
+/// `owner` of the byte of the text it comes from.
+fn normalize(text: &str, owner: impl Fn(usize) -> Option<usize>) -> (String, Vec<Option<usize>>) {
+    let trimmed = text.len() - text.trim_start().len();
+    let mut normalized = String::new();
+    let mut owners = Vec::new();
+    for (index, c) in text.trim().char_indices() {
+        if c.is_whitespace() {
+            if !normalized.ends_with(' ') {
+                normalized.push(' ');
+                owners.push(None);
+            }
+            continue;
+        }
+        for lower in c.to_lowercase() {
+            normalized.push(lower);
+            owners.extend(std::iter::repeat_n(owner(trimmed + index), lower.len_utf8()));
+        }
+    }
+    (normalized, owners)
+}
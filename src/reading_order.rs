@@ -0,0 +1,116 @@
+use std::cmp::Ordering;
+
+use crate::word_analysis::BBox;
+
+/// Returns the indices of the boxes of a page in the order the page is read.
+///
+/// The page is cut recursively (an XY cut). A region is split at its widest
+/// horizontal gap into a top and a bottom part if the gap is wider than half
+/// of `column_gap`, e.g. between a heading and the columns below it or
+/// between paragraphs. Otherwise, if its boxes leave a vertical gap at least
+/// `column_gap` wide all the way down, it is split into columns, read from
+/// left to right, and otherwise into its lines. A region of a single line is
+/// read from left to right. Boxes that overlap both horizontally and
+/// vertically are ordered by y, then x.
+pub fn visual_order(boxes: &[BBox], column_gap: f64) -> Vec<usize> {
+    let mut order = Vec::with_capacity(boxes.len());
+    cut(boxes, (0..boxes.len()).collect(), column_gap, &mut order);
+    order
+}
+
+fn cut(boxes: &[BBox], indices: Vec<usize>, column_gap: f64, order: &mut Vec<usize>) {
+    if indices.len() <= 1 {
+        order.extend(indices);
+        return;
+    }
+    let horizontal = |&index: &usize| (boxes[index].0, boxes[index].0 + boxes[index].2);
+    let vertical = |&index: &usize| (boxes[index].1, boxes[index].1 + boxes[index].3);
+
+    // The widest gap between bands of boxes that don't overlap vertically.
+    let bands = split_at_gaps(&indices, vertical, 0.0);
+    let widest = (1..bands.len())
+        .map(|band| (band, vertical(&bands[band][0]).0 - end(&bands[band - 1], vertical)))
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    let split_bands = |bands: Vec<Vec<usize>>, at: usize, order: &mut Vec<usize>| {
+        let mut bands = bands.into_iter();
+        cut(boxes, bands.by_ref().take(at).flatten().collect(), column_gap, order);
+        cut(boxes, bands.flatten().collect(), column_gap, order);
+    };
+
+    // The lines of columns side by side leave narrow gaps, which must not
+    // split the columns.
+    if let Some((band, gap)) = widest
+        && gap > column_gap / 2.0
+    {
+        split_bands(bands, band, order);
+        return;
+    }
+
+    let columns = split_at_gaps(&indices, horizontal, column_gap);
+    if columns.len() > 1 {
+        for column in columns {
+            cut(boxes, column, column_gap, order);
+        }
+        return;
+    }
+
+    if let Some((band, _)) = widest {
+        split_bands(bands, band, order);
+        return;
+    }
+
+    // A line, whose words may be apart by less than a column gap.
+    for mut segment in split_at_gaps(&indices, horizontal, 0.0) {
+        segment.sort_by(|&a, &b| {
+            let (a, b) = (boxes[a], boxes[b]);
+            a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal).then(a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal))
+        });
+        order.extend(segment);
+    }
+}
+
+/// Splits the boxes where there is a gap wider than `min_gap` between the
+/// ranges they cover along one axis, in the order of the axis. The boxes of
+/// each part are sorted by where their range starts.
+fn split_at_gaps(indices: &[usize], range: impl Fn(&usize) -> (f64, f64), min_gap: f64) -> Vec<Vec<usize>> {
+    let mut sorted = indices.to_vec();
+    sorted.sort_by(|a, b| range(a).0.partial_cmp(&range(b).0).unwrap_or(Ordering::Equal));
+
+    let mut parts: Vec<Vec<usize>> = Vec::new();
+    let mut covered_until = f64::NEG_INFINITY;
+    for index in sorted {
+        let (start, end) = range(&index);
+        match parts.last_mut() {
+            Some(part) if start - covered_until <= min_gap => part.push(index),
+            _ => parts.push(vec![index]),
+        }
+        covered_until = covered_until.max(end);
+    }
+    parts
+}
+
+/// Where the ranges of the boxes end along one axis.
+fn end(indices: &[usize], range: impl Fn(&usize) -> (f64, f64)) -> f64 {
+    indices.iter().map(|index| range(index).1).fold(f64::NEG_INFINITY, f64::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn left_column_is_read_before_the_right_one() {
+        let line = |x: f64, line: usize| (x, 18.0 + 12.0 * line as f64, 90.0, 10.0);
+        // Laid out line by line across the columns, below a heading.
+        let boxes = [
+            line(110.0, 0),
+            line(0.0, 0),
+            line(110.0, 1),
+            line(0.0, 1),
+            line(110.0, 2),
+            line(0.0, 2),
+            (0.0, 0.0, 200.0, 10.0),
+        ];
+        assert_eq!(visual_order(&boxes, 10.0), [6, 1, 3, 5, 0, 2, 4]);
+    }
+}
@@ -1,20 +1,132 @@
-use crate::WordBox;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::ops::RangeInclusive;
+
+use crate::hash::Fnv;
+use crate::output::escape_xml;
+use crate::word_analysis::selected_pages;
+use crate::{WordBox, WorldBuilder};
 use typst::layout::{Abs, PagedDocument};
 use typst::visualize::Color;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
 use tiny_skia;
-use tiny_skia::{Transform,Paint,Stroke,Rect,PathBuilder};
+use tiny_skia::{FillRule, Transform,Paint,PixmapPaint,Stroke,Rect,PathBuilder};
 
-/// Draw all frames into one image with padding in between.
-pub fn render_to_png(document: &PagedDocument, pixel_per_pt: f32) -> tiny_skia::Pixmap {
-    for page in &document.pages {
-        let limit = Abs::cm(100.0);
-        if page.frame.width() > limit || page.frame.height() > limit {
-            panic!("overlarge frame: {:?}", page.frame.size());
+/// Options for rendering pages and drawing word boxes onto them.
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// The largest width and height of a page that is rendered, or `None` to
+    /// render pages of any size. Larger pages make rendering fail.
+    pub max_page_size: Option<Abs>,
+    /// The color of the box outlines of words, glyphs, lines and sentences as RGBA.
+    /// Boxes of groups are instead colored by their kind, see [`kind_color`].
+    pub box_color: [u8; 4],
+    /// The width of the box outlines in pt, so that it grows with the
+    /// resolution. In pixels if `absolute_box_width` is set.
+    pub box_width: f32,
+    /// Whether `box_width` is in pixels, regardless of the resolution.
+    pub absolute_box_width: bool,
+    /// Whether to draw the outlines of the boxes.
+    pub box_stroke: bool,
+    /// The color to fill the boxes of words, glyphs, lines and sentences with
+    /// as RGBA, or `None` to leave them empty. Boxes of groups are filled in
+    /// the color of their kind with the same opacity.
+    pub box_fill: Option<[u8; 4]>,
+    /// The fonts to lay out a legend of the box colors with, which is stamped
+    /// onto the top-right corner, or `None` for no legend. Usually those of
+    /// the document, from [`crate::TypstWrapperWorld::builder`].
+    pub legend: Option<WorldBuilder>,
+    /// The most threads to render pages on at once, or `None` for one per
+    /// CPU core. Only [`render_pages_to_png`] renders pages in parallel, the
+    /// merged image of [`render_to_png`] is drawn on one thread.
+    pub jobs: Option<NonZeroUsize>,
+    /// The ranges of the `order` of the boxes to highlight. They are drawn
+    /// in [`HIGHLIGHT_COLOR`] with outlines three times as wide, and all
+    /// others faintly.
+    pub highlight: Option<Vec<RangeInclusive<usize>>>,
+}
+
+/// The color of highlighted boxes as RGBA.
+pub const HIGHLIGHT_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            max_page_size: Some(Abs::cm(100.0)),
+            box_color: [255, 0, 0, 180], // Red with some transparency
+            box_width: 1.0,
+            absolute_box_width: false,
+            box_stroke: true,
+            box_fill: None,
+            legend: None,
+            jobs: None,
+            highlight: None,
         }
     }
+}
+
+/// Errors that can occur while rendering a document.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderError {
+    /// A page is wider or taller than [`RenderOptions::max_page_size`].
+    PageTooLarge {
+        /// The index of the page, starting at 0.
+        page: usize,
+        /// The width and height of the page.
+        size: (Abs, Abs),
+        /// The largest width and height allowed.
+        limit: Abs,
+    },
+    /// The page filter selects no page of the document, so there is nothing
+    /// to draw an image of.
+    NoPages,
+    /// The threads to render pages on couldn't be started.
+    ThreadPool(String),
+    /// `typst-pdf` couldn't export the document, with its error messages.
+    Pdf(String),
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::PageTooLarge { page, size: (width, height), limit } => write!(
+                f,
+                "page {page} is {:.1}cm x {:.1}cm, larger than the limit of {:.1}cm",
+                width.to_cm(),
+                height.to_cm(),
+                limit.to_cm()
+            ),
+            RenderError::NoPages => write!(f, "there are no pages to render"),
+            RenderError::ThreadPool(error) => write!(f, "could not start the threads to render pages on: {error}"),
+            RenderError::Pdf(error) => write!(f, "could not export the PDF: {error}"),
+        }
+    }
+}
 
+impl std::error::Error for RenderError {}
+
+/// Draw all frames into one image with padding in between.
+///
+/// With a page filter, only the pages with the given indices are drawn, and
+/// rendering fails with [`RenderError::NoPages`] if it selects none.
+pub fn render_to_png(
+    document: &PagedDocument,
+    pixel_per_pt: f32,
+    options: &RenderOptions,
+    page_filter: Option<&[usize]>,
+) -> Result<tiny_skia::Pixmap, RenderError> {
+    check_page_sizes(document, options.max_page_size, page_filter)?;
+    if selected_pages(document, page_filter).next().is_none() {
+        return Err(RenderError::NoPages);
+    }
+
+    // The gap must be that of `merged_page_offsets`.
     let gap = Abs::pt(1.0);
-    typst_render::render_merged(document, pixel_per_pt, gap, Some(Color::BLACK))
+    let document = filter_pages(document, page_filter);
+    Ok(typst_render::render_merged(&document, pixel_per_pt, gap, Some(Color::BLACK)))
 }
 
 /// Draw all frames into one image with padding in between and overlay word boxes.
@@ -22,46 +134,437 @@ pub fn render_to_png_with_boxes(
     document: &PagedDocument,
     pixel_per_pt: f32,
     word_boxes: &[WordBox],
-    stroke: f32
-) -> tiny_skia::Pixmap {
-    for page in &document.pages {
-        let limit = Abs::cm(100.0);
-        if page.frame.width() > limit || page.frame.height() > limit {
-            panic!("overlarge frame: {:?}", page.frame.size());
+    options: &RenderOptions,
+    page_filter: Option<&[usize]>,
+) -> Result<tiny_skia::Pixmap, RenderError> {
+    let mut pixmap = render_to_png(document, pixel_per_pt, options, page_filter)?;
+
+    // Pages are stacked vertically, so shift each page's boxes down to it.
+    let offsets = merged_page_offsets(document, page_filter);
+    for (page_index, _) in selected_pages(document, page_filter) {
+        let page_boxes = word_boxes.iter().filter(|word_box| word_box.page == page_index);
+        let ts = Transform::from_translate(0.0, offsets[page_index] as f32 * pixel_per_pt);
+        draw_boxes(&mut pixmap, pixel_per_pt, page_boxes, options, ts);
+    }
+    if let Some(legend) = render_legend(word_boxes, pixel_per_pt, options) {
+        stamp_legend(&mut pixmap, &legend);
+    }
+    Ok(pixmap)
+}
+
+/// Draws all pages into one SVG with a gap of 1pt around and between them,
+/// and overlays the word boxes as vector shapes on top.
+///
+/// Each box is a `<rect>` (a `<polygon>` if it is rotated, or a `<g>` of
+/// `<rect>`s for a sentence that wraps) in the coordinate space of the SVG,
+/// with the classes `box` and its kind (e.g. `word` or `heading`) to style it
+/// by, and its text and kind in the `data-word` and `data-kind` attributes.
+/// The stroke is set with presentation attributes, which stylesheets
+/// override.
+pub fn render_to_svg_with_boxes(
+    document: &PagedDocument,
+    word_boxes: &[WordBox],
+    options: &RenderOptions,
+    page_filter: Option<&[usize]>,
+) -> String {
+    let gap = Abs::pt(1.0);
+    let svg = typst_svg::svg_merged(&filter_pages(document, page_filter), gap);
+
+    // Unlike in the PNG image, there is also a gap before the first page.
+    let offsets = merged_page_offsets(document, page_filter);
+    let mut overlay = String::from(r#"<g class="boxes" fill="none">"#);
+    for (page_index, _) in selected_pages(document, page_filter) {
+        let (dx, dy) = (gap.to_pt(), gap.to_pt() + offsets[page_index]);
+        for word_box in word_boxes.iter().filter(|word_box| word_box.page == page_index) {
+            let [r, g, b, a] = box_color(word_box, options);
+            let mut attributes = format!(
+                r#"class="box {kind}" data-word="{word}" data-kind="{kind}""#,
+                kind = escape_xml(&word_box.kind),
+                word = escape_xml(&word_box.word),
+            );
+            if options.box_stroke {
+                attributes.push_str(&format!(
+                    r#" stroke="rgb({r},{g},{b})" stroke-opacity="{opacity}" stroke-width="{width}""#,
+                    opacity = f32::from(a) / 255.0,
+                    width = options.box_width * box_width_factor(word_box, options),
+                ));
+            }
+            if let Some([r, g, b, a]) = fill_color(word_box, options) {
+                attributes.push_str(&format!(r#" fill="rgb({r},{g},{b})" fill-opacity="{}""#, f32::from(a) / 255.0));
+            }
+            let rect = |x: f64, y: f64, width: f64, height: f64| {
+                format!(r#"<rect x="{}" y="{}" width="{width}" height="{height}""#, x + dx, y + dy)
+            };
+            if !word_box.rects.is_empty() {
+                // Wrapped sentences are drawn on each line they are on.
+                overlay.push_str(&format!("<g {attributes}>"));
+                for line in &word_box.rects {
+                    overlay.push_str(&rect(line.x, line.y, line.width, line.height));
+                    overlay.push_str("/>");
+                }
+                overlay.push_str("</g>");
+            } else if let Some(quad) = word_box.quad {
+                // Rotated words are drawn along their actual outline.
+                let points: Vec<String> = quad.iter().map(|(x, y)| format!("{},{}", x + dx, y + dy)).collect();
+                overlay.push_str(&format!(r#"<polygon points="{}" {attributes}/>"#, points.join(" ")));
+            } else {
+                overlay.push_str(&rect(word_box.x, word_box.y, word_box.width, word_box.height));
+                overlay.push_str(&format!(" {attributes}/>"));
+            }
         }
     }
+    overlay.push_str("</g>");
+
+    // The overlay is the last child of the root element, so that it is drawn
+    // on top of the pages.
+    let end = svg.rfind("</svg>").unwrap_or(svg.len());
+    format!("{}{overlay}{}", &svg[..end], &svg[end..])
+}
 
+/// Returns how far down each page of a document is in the image of
+/// [`render_to_png`] in pt, indexed by the page's index. Pages are stacked
+/// with a gap of 1pt; pages left out by the page filter get the offset of the
+/// next page.
+pub fn merged_page_offsets(document: &PagedDocument, page_filter: Option<&[usize]>) -> Vec<f64> {
     let gap = Abs::pt(1.0);
-    let mut pixmap = typst_render::render_merged(document, pixel_per_pt, gap, Some(Color::BLACK));
+    let mut offsets = vec![0.0; document.pages.len()];
+    let mut offset = Abs::zero();
+    for (page_index, page) in document.pages.iter().enumerate() {
+        offsets[page_index] = offset.to_pt();
+        if page_filter.is_none_or(|pages| pages.contains(&page_index)) {
+            offset += page.frame.height() + gap;
+        }
+    }
+    offsets
+}
+
+/// Draw each frame into its own image, in page order.
+///
+/// Pages are rendered in parallel, on at most `options.jobs` threads. With a
+/// page filter, only the pages with the given indices are drawn.
+pub fn render_pages_to_png(
+    document: &PagedDocument,
+    pixel_per_pt: f32,
+    options: &RenderOptions,
+    page_filter: Option<&[usize]>,
+) -> Result<Vec<tiny_skia::Pixmap>, RenderError> {
+    check_page_sizes(document, options.max_page_size, page_filter)?;
 
-    // Define the paint for the stroke
-    let mut stroke_paint = Paint::default();
-    stroke_paint.set_color_rgba8(255, 0, 0, 180); // Red with some transparency
-    stroke_paint.anti_alias = true;
+    let pages: Vec<_> = selected_pages(document, page_filter).map(|(_, page)| page).collect();
+    let render = || pages.par_iter().map(|page| typst_render::render(page, pixel_per_pt)).collect();
+    // Without a limit, the global pool has a thread per core.
+    let pool = options
+        .jobs
+        .map(|jobs| ThreadPoolBuilder::new().num_threads(jobs.get()).build())
+        .transpose()
+        .map_err(|error| RenderError::ThreadPool(error.to_string()))?;
+    Ok(match pool {
+        Some(pool) => pool.install(render),
+        None => render(),
+    })
+}
+
+/// Draw each frame into its own image and overlay the word boxes of that page.
+pub fn render_pages_to_png_with_boxes(
+    document: &PagedDocument,
+    pixel_per_pt: f32,
+    word_boxes: &[WordBox],
+    options: &RenderOptions,
+    page_filter: Option<&[usize]>,
+) -> Result<Vec<tiny_skia::Pixmap>, RenderError> {
+    let mut pixmaps = render_pages_to_png(document, pixel_per_pt, options, page_filter)?;
+    let page_indices = selected_pages(document, page_filter).map(|(page_index, _)| page_index);
+    for (page_index, pixmap) in page_indices.zip(pixmaps.iter_mut()) {
+        let page_boxes = word_boxes.iter().filter(|word_box| word_box.page == page_index);
+        draw_boxes(pixmap, pixel_per_pt, page_boxes, options, Transform::identity());
+    }
+    if let Some(legend) = render_legend(word_boxes, pixel_per_pt, options) {
+        for pixmap in &mut pixmaps {
+            stamp_legend(pixmap, &legend);
+        }
+    }
+    Ok(pixmaps)
+}
+
+/// Returns the document with only the pages with the given indices, if
+/// there is a page filter.
+fn filter_pages<'a>(document: &'a PagedDocument, page_filter: Option<&[usize]>) -> Cow<'a, PagedDocument> {
+    match page_filter {
+        None => Cow::Borrowed(document),
+        Some(_) => Cow::Owned(PagedDocument {
+            pages: selected_pages(document, page_filter).map(|(_, page)| page.clone()).collect(),
+            ..document.clone()
+        }),
+    }
+}
 
+/// Fails if any page to render is wider or taller than the limit, if there
+/// is one.
+fn check_page_sizes(
+    document: &PagedDocument,
+    limit: Option<Abs>,
+    page_filter: Option<&[usize]>,
+) -> Result<(), RenderError> {
+    let Some(limit) = limit else {
+        return Ok(());
+    };
+    for (page_index, page) in selected_pages(document, page_filter) {
+        if page.frame.width() > limit || page.frame.height() > limit {
+            return Err(RenderError::PageTooLarge {
+                page: page_index,
+                size: (page.frame.width(), page.frame.height()),
+                limit,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Stroke the outline of each word box onto the pixmap, moved by `ts`.
+fn draw_boxes<'a>(
+    pixmap: &mut tiny_skia::Pixmap,
+    pixel_per_pt: f32,
+    word_boxes: impl Iterator<Item = &'a WordBox>,
+    options: &RenderOptions,
+    ts: Transform,
+) {
     // Define the stroke properties
-    let stroke = Stroke {
-        width: stroke,
-        ..Default::default()
+    let width = if options.absolute_box_width {
+        options.box_width
+    } else {
+        options.box_width * pixel_per_pt
     };
 
     // Iterate over the word boxes and draw a rectangle for each
     for word_box in word_boxes {
-        // Create a rectangle from the word box coordinates, scaling by pixel_per_pt
-        let rect = Rect::from_xywh(
-            word_box.x as f32 * pixel_per_pt,
-            word_box.y as f32 * pixel_per_pt,
-            word_box.width as f32 * pixel_per_pt,
-            word_box.height as f32 * pixel_per_pt,
-        );
-
-        if let Some(rect) = rect {
-            // Create a path from the rectangle
-            let path = PathBuilder::from_rect(rect);
+        // Create a path from the word box outline, scaling by pixel_per_pt
+        let path = match word_box.quad {
+            // Wrapped sentences are drawn on each line they are on.
+            _ if !word_box.rects.is_empty() => {
+                let mut builder = PathBuilder::new();
+                for rect in &word_box.rects {
+                    if let Some(rect) = Rect::from_xywh(
+                        rect.x as f32 * pixel_per_pt,
+                        rect.y as f32 * pixel_per_pt,
+                        rect.width as f32 * pixel_per_pt,
+                        rect.height as f32 * pixel_per_pt,
+                    ) {
+                        builder.push_rect(rect);
+                    }
+                }
+                builder.finish()
+            }
+            // Rotated words are drawn along their actual outline.
+            Some(quad) => {
+                let mut builder = PathBuilder::new();
+                let [first, rest @ ..] = quad;
+                builder.move_to(first.0 as f32 * pixel_per_pt, first.1 as f32 * pixel_per_pt);
+                for (x, y) in rest {
+                    builder.line_to(x as f32 * pixel_per_pt, y as f32 * pixel_per_pt);
+                }
+                builder.close();
+                builder.finish()
+            }
+            None => Rect::from_xywh(
+                word_box.x as f32 * pixel_per_pt,
+                word_box.y as f32 * pixel_per_pt,
+                word_box.width as f32 * pixel_per_pt,
+                word_box.height as f32 * pixel_per_pt,
+            )
+            .map(PathBuilder::from_rect),
+        };
+
+        let Some(path) = path else {
+            continue;
+        };
+        // The fill goes below the outline.
+        if let Some([r, g, b, a]) = fill_color(word_box, options) {
+            let mut fill_paint = Paint::default();
+            fill_paint.set_color_rgba8(r, g, b, a);
+            fill_paint.anti_alias = true;
+            pixmap.fill_path(&path, &fill_paint, FillRule::Winding, ts, None);
+        }
+        if options.box_stroke {
+            // Define the paint for the stroke
+            let mut stroke_paint = Paint::default();
+            let [r, g, b, a] = box_color(word_box, options);
+            stroke_paint.set_color_rgba8(r, g, b, a);
+            stroke_paint.anti_alias = true;
+
             // Stroke the path on the pixmap
-            pixmap.stroke_path(&path, &stroke_paint, &stroke, Transform::identity(), None);
+            let stroke = Stroke {
+                width: width * box_width_factor(word_box, options),
+                ..Default::default()
+            };
+            pixmap.stroke_path(&path, &stroke_paint, &stroke, ts, None);
+        }
+    }
+}
+
+/// Returns the color to draw a box in: the color of its kind for groups, and
+/// the configured box color for words, glyphs, lines and sentences. With
+/// boxes to highlight, those are drawn in [`HIGHLIGHT_COLOR`] and the others
+/// with a quarter of their opacity.
+pub(crate) fn box_color(word_box: &WordBox, options: &RenderOptions) -> [u8; 4] {
+    let [r, g, b, a] = match word_box.kind.as_str() {
+        "word" | "glyph" | "line" | "sentence" => options.box_color,
+        kind => kind_color(kind),
+    };
+    match &options.highlight {
+        Some(_) if is_highlighted(word_box, options) => HIGHLIGHT_COLOR,
+        Some(_) => [r, g, b, a / 4],
+        None => [r, g, b, a],
+    }
+}
+
+/// Returns the color to fill a box with, if boxes are filled: the fill color
+/// for words, glyphs, lines and sentences, and the color of their kind for
+/// groups, with the opacity of the fill color. Highlighting changes it like
+/// it does the color of the outline.
+pub(crate) fn fill_color(word_box: &WordBox, options: &RenderOptions) -> Option<[u8; 4]> {
+    let [r, g, b, a] = options.box_fill?;
+    let [r, g, b] = match word_box.kind.as_str() {
+        "word" | "glyph" | "line" | "sentence" => [r, g, b],
+        kind => {
+            let [r, g, b, _] = kind_color(kind);
+            [r, g, b]
+        }
+    };
+    Some(match &options.highlight {
+        Some(_) if is_highlighted(word_box, options) => {
+            let [r, g, b, _] = HIGHLIGHT_COLOR;
+            [r, g, b, a]
+        }
+        Some(_) => [r, g, b, a / 4],
+        None => [r, g, b, a],
+    })
+}
+
+/// How many times as wide as the configured width the outline of a box is.
+pub(crate) fn box_width_factor(word_box: &WordBox, options: &RenderOptions) -> f32 {
+    if is_highlighted(word_box, options) { 3.0 } else { 1.0 }
+}
+
+fn is_highlighted(word_box: &WordBox, options: &RenderOptions) -> bool {
+    let Some(highlight) = &options.highlight else {
+        return false;
+    };
+    word_box.order.is_some_and(|order| highlight.iter().any(|range| range.contains(&order)))
+}
+
+/// Returns the RGBA color of boxes of the given kind.
+///
+/// Common elements have fixed colors, e.g. blue for headings and green for
+/// links. Any other kind gets a color derived from a hash of its name, which
+/// stays the same across runs.
+pub fn kind_color(kind: &str) -> [u8; 4] {
+    match kind {
+        "heading" => [0, 0, 255, 180],
+        "link" => [0, 160, 0, 180],
+        "footnote" => [255, 140, 0, 180],
+        "figure" => [160, 0, 160, 180],
+        "equation" => [0, 150, 150, 180],
+        "group" => [128, 128, 128, 180],
+        _ => {
+            let hash = Fnv::hash(kind.as_bytes());
+            let [r, g, b] = hue_to_rgb((hash % 360) as f32);
+            [r, g, b, 180]
         }
     }
+}
+
+/// Converts a hue in degrees to a saturated, fairly dark RGB color.
+fn hue_to_rgb(hue: f32) -> [u8; 3] {
+    let (saturation, value) = (0.8, 0.8);
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 / 60 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = value - chroma;
+    [r, g, b].map(|channel| ((channel + m) * 255.0).round() as u8)
+}
+
+/// Renders a legend of the colors of the given boxes, one swatch per kind.
+///
+/// The legend is itself laid out by Typst, with the fonts of
+/// [`RenderOptions::legend`]. Returns `None` without a legend, if there are
+/// no boxes or if the legend fails to compile.
+fn render_legend(
+    word_boxes: &[WordBox],
+    pixel_per_pt: f32,
+    options: &RenderOptions,
+) -> Option<tiny_skia::Pixmap> {
+    let fonts = options.legend.as_ref()?;
+    // One box of each kind, to pick its color.
+    let kinds: BTreeMap<&str, &WordBox> =
+        word_boxes.iter().map(|word_box| (word_box.kind.as_str(), word_box)).collect();
+    if kinds.is_empty() {
+        return None;
+    }
+
+    let mut source = String::from(
+        "#set page(width: auto, height: auto, margin: 4pt, fill: white)\n\
+         #set text(size: 8pt)\n\
+         #grid(columns: 2, column-gutter: 4pt, row-gutter: 3pt, align: horizon,\n",
+    );
+    for (kind, word_box) in kinds {
+        let [r, g, b, a] = box_color(word_box, options);
+        let label = kind.replace('\\', "\\\\").replace('"', "\\\"");
+        source.push_str(&format!(
+            "  box(width: 8pt, height: 8pt, stroke: 1pt + rgb(\"#{r:02x}{g:02x}{b:02x}{a:02x}\")), \"{label}\",\n"
+        ));
+    }
+    source.push(')');
+
+    let world = fonts.build(String::new(), source);
+    let legend = crate::compile(&world).output.ok()?;
+    Some(typst_render::render(legend.pages.first()?, pixel_per_pt))
+}
+
+/// Draws the legend onto the top-right corner of the pixmap.
+fn stamp_legend(pixmap: &mut tiny_skia::Pixmap, legend: &tiny_skia::Pixmap) {
+    let margin = 4;
+    let x = pixmap.width() as i32 - legend.width() as i32 - margin;
+    pixmap.draw_pixmap(x.max(0), margin, legend.as_ref(), &PixmapPaint::default(), Transform::identity(), None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boxes_on_the_second_page_are_drawn_over_their_glyphs() {
+        let source = "#set page(width: 4cm, height: 3cm)\nFirst #pagebreak() #v(1cm) Second";
+        let world = WorldBuilder::new(&[]).build(String::new(), source.into());
+        let document = crate::compile(&world).output.unwrap();
+        let boxes = crate::collect_word_boxes(&document, &crate::ExtractOptions::default());
+        let second = boxes.iter().find(|word_box| word_box.page == 1).unwrap();
 
-    pixmap
-}
\ No newline at end of file
+        let scale = 2.0;
+        let options = RenderOptions { box_color: [255, 0, 0, 255], ..RenderOptions::default() };
+        let plain = render_to_png(&document, scale as f32, &options, None).unwrap();
+        let drawn = render_to_png_with_boxes(&document, scale as f32, &boxes, &options, None).unwrap();
+        let offset = merged_page_offsets(&document, None)[1];
+        let to_pixels = |value: f64| (value * scale).round() as u32;
+        let (left, top) = (to_pixels(second.x), to_pixels(second.y + offset));
+        let (right, bottom) = (to_pixels(second.x + second.width), to_pixels(second.y + offset + second.height));
+
+        // The glyphs are within the box on the merged image...
+        let dark = (left..right).any(|x| (top..bottom).any(|y| plain.pixel(x, y).unwrap().red() < 128));
+        assert!(dark);
+        // ...and its outline is drawn around them, not where the word is on
+        // its own page.
+        let is_red = |pixmap: &tiny_skia::Pixmap, x, y| {
+            let pixel = pixmap.pixel(x, y).unwrap();
+            pixel.red() > 200 && pixel.green() < 80
+        };
+        assert!(is_red(&drawn, (left + right) / 2, top));
+        assert!(!is_red(&drawn, (left + right) / 2, to_pixels(second.y)));
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+
+use typst::layout::PagedDocument;
+use typst::syntax::ast;
+use typst::syntax::{Source, Span, SyntaxKind};
+
+use crate::word_analysis::{union_bbox, words_with_boxes, TextOptions, Word};
+use crate::WordBox;
+
+/// How far apart in pt the text of two cells may be and still count as
+/// overlapping, e.g. for rounding errors.
+const EPSILON: f64 = 0.01;
+
+/// Returns the boxes of the cells of the tables and grids of a document, of
+/// kind `cell`, with the boxes of their words as children.
+///
+/// Typst lays cells out without marking them, so the cell of a word is found
+/// from where its text is in the sources: in the content given to a `table`
+/// or `grid` call, or to its header or footer, or in a `table.cell` or
+/// `grid.cell` call among it, whose `colspan` and `rowspan` are read if they
+/// are written as numbers. Cells without text are not found. The box of a
+/// cell is the box around its text, not the cell's outline, and its `word`
+/// is the text of its words, separated by a space where they aren't
+/// adjacent.
+///
+/// Cells spread into the call, e.g. with `..csv("data.csv").flatten()`, all
+/// have the text of the spread argument, so they are told apart by where
+/// they are laid out instead: the words of one of them follow each other on
+/// a line, less than half their height apart. A cell of those that wraps is
+/// one cell per line, and each spans one row and one column.
+///
+/// The text of the cells of different rows never overlaps vertically, so
+/// the cells spanning one row whose text overlaps are in the same row, and
+/// likewise for columns. A cell spanning several rows (or columns) starts in
+/// the first one that doesn't end before its text starts. The rows of a table
+/// broken across pages are counted on from one page to the next.
+pub fn table_cells(document: &PagedDocument, sources: &[Source]) -> Vec<WordBox> {
+    let options = TextOptions { include_delimiters: true, ..TextOptions::default() };
+
+    // The words of each cell on each page, in the order they were laid out.
+    let mut cells: Vec<Cell> = Vec::new();
+    let mut indices: HashMap<(Span, usize), usize> = HashMap::new();
+    // The cell of the last word that was spread into a table.
+    let mut spread: Option<usize> = None;
+    for (page, word) in words_with_boxes(document, options, None) {
+        if word.kind != "word" && word.kind != "equation" {
+            continue;
+        }
+        let index = match word.spans.iter().find_map(|(span, _)| cell_source(sources, *span)) {
+            Some(Place::Cell(source)) => *indices.entry((source.cell, page)).or_insert_with(|| {
+                cells.push(Cell { page, source, words: Vec::new() });
+                cells.len() - 1
+            }),
+            Some(Place::Spread(table)) => match spread {
+                Some(index) if cells[index].page == page
+                    && cells[index].source.table == table
+                    && cells[index].words.last().is_some_and(|last| follows_on_line(last, &word)) =>
+                {
+                    index
+                }
+                _ => {
+                    let source = CellSource { cell: Span::detached(), table, rowspan: 1, colspan: 1 };
+                    cells.push(Cell { page, source, words: Vec::new() });
+                    spread = Some(cells.len() - 1);
+                    cells.len() - 1
+                }
+            },
+            None => continue,
+        };
+        cells[index].words.push(word);
+    }
+
+    // Tables are numbered in the order their first cell was laid out.
+    let mut tables: Vec<Span> = Vec::new();
+    for cell in &cells {
+        if !tables.contains(&cell.source.table) {
+            tables.push(cell.source.table);
+        }
+    }
+
+    let mut boxes = Vec::new();
+    for (table_index, &table) in tables.iter().enumerate() {
+        let table_cells: Vec<&Cell> = cells.iter().filter(|cell| cell.source.table == table).collect();
+        let mut first_row = 0;
+        let mut pages: Vec<usize> = table_cells.iter().map(|cell| cell.page).collect();
+        pages.sort();
+        pages.dedup();
+        for page in pages {
+            let page_cells: Vec<&Cell> = table_cells.iter().copied().filter(|cell| cell.page == page).collect();
+            let rows = tracks(&page_cells.iter().map(|cell| cell.rows()).collect::<Vec<_>>());
+            let cols = tracks(&page_cells.iter().map(|cell| cell.cols()).collect::<Vec<_>>());
+            for ((cell, &row), &col) in page_cells.iter().zip(&rows.starts).zip(&cols.starts) {
+                boxes.push(WordBox {
+                    table: Some(table_index),
+                    row: Some(first_row + row),
+                    col: Some(col),
+                    rowspan: Some(cell.source.rowspan),
+                    colspan: Some(cell.source.colspan),
+                    children: cell.words.iter().map(|word| WordBox::new(page, word.clone())).collect(),
+                    ..WordBox::new(page, cell.word())
+                });
+            }
+            first_row += rows.count;
+        }
+    }
+    boxes.sort_by_key(|cell| (cell.page, cell.table, cell.row, cell.col));
+    boxes
+}
+
+/// Whether a word is on the same line as the one before it and close enough
+/// after it to be in the same cell.
+fn follows_on_line(last: &Word, word: &Word) -> bool {
+    let (last_x, last_y, last_w, last_h) = last.bbox;
+    let (x, y, _, h) = word.bbox;
+    let overlap = (last_y + last_h).min(y + h) - last_y.max(y);
+    let gap = x - (last_x + last_w);
+    overlap > 0.0 && -EPSILON <= gap && gap < last_h / 2.0
+}
+
+/// The words of a cell on a page.
+struct Cell {
+    page: usize,
+    source: CellSource,
+    words: Vec<Word>,
+}
+
+impl Cell {
+    /// The word of kind `cell` for the text of all words of the cell.
+    fn word(&self) -> Word {
+        let first = &self.words[0];
+        let mut cell = Word::plain(first.text.clone(), first.bbox, "cell");
+        for (last, word) in self.words.iter().zip(&self.words[1..]) {
+            let (x, _, w, _) = word.bbox;
+            let (last_x, _, last_w, last_h) = last.bbox;
+            let gap = (x - (last_x + last_w)).max(last_x - (x + w));
+            if gap > 0.1 * last_h {
+                cell.text.push(' ');
+            }
+            cell.text.push_str(&word.text);
+            cell.bbox = union_bbox(cell.bbox, word.bbox);
+        }
+        cell
+    }
+
+    /// Where the cell's text starts and ends vertically, and how many rows
+    /// it spans. The text of a line reaches above and below the line itself,
+    /// e.g. in a grid without inset, so only the middle half of each word
+    /// counts.
+    fn rows(&self) -> (f64, f64, usize) {
+        let start = self.words.iter().map(|word| word.bbox.1 + word.bbox.3 / 4.0).fold(f64::INFINITY, f64::min);
+        let end = self.words.iter().map(|word| word.bbox.1 + word.bbox.3 * 3.0 / 4.0).fold(f64::NEG_INFINITY, f64::max);
+        (start, end, self.source.rowspan)
+    }
+
+    /// Where the cell's text starts and ends horizontally, and how many
+    /// columns it spans.
+    fn cols(&self) -> (f64, f64, usize) {
+        let start = self.words.iter().map(|word| word.bbox.0).fold(f64::INFINITY, f64::min);
+        let end = self.words.iter().map(|word| word.bbox.0 + word.bbox.2).fold(f64::NEG_INFINITY, f64::max);
+        (start, end, self.source.colspan)
+    }
+}
+
+/// The rows (or columns) of the cells of a table on a page.
+struct Tracks {
+    /// How many there are.
+    count: usize,
+    /// The index of the first one each cell is in.
+    starts: Vec<usize>,
+}
+
+/// Finds the rows (or columns) of cells from where their text starts and
+/// ends along the axis, and how many rows they span. Each row is where the
+/// text of cells spanning one row overlaps.
+fn tracks(extents: &[(f64, f64, usize)]) -> Tracks {
+    let mut single: Vec<(f64, f64)> =
+        extents.iter().filter(|&&(_, _, span)| span == 1).map(|&(start, end, _)| (start, end)).collect();
+    single.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut merged: Vec<(f64, f64)> = Vec::new();
+    for (start, end) in single {
+        match merged.last_mut() {
+            Some(last) if start < last.1 - EPSILON => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    let starts = extents
+        .iter()
+        .map(|&(start, _, _)| merged.iter().filter(|&&(_, end)| end <= start + EPSILON).count())
+        .collect();
+    Tracks { count: merged.len(), starts }
+}
+
+/// Where the text of a word is in a table or grid.
+#[derive(Debug, Clone, Copy)]
+enum Place {
+    /// In a cell written out in the call.
+    Cell(CellSource),
+    /// In an argument spread into the call of the table with this span,
+    /// which may be any number of its cells.
+    Spread(Span),
+}
+
+/// Where the text of a word is in the sources of a table or grid.
+#[derive(Debug, Clone, Copy)]
+struct CellSource {
+    /// The span of the argument the cell was given as.
+    cell: Span,
+    /// The span of the call of `table` or `grid`.
+    table: Span,
+    rowspan: usize,
+    colspan: usize,
+}
+
+/// Finds the cell whose argument of a `table` or `grid` call (or of its
+/// header or footer) the text of a span is in, or `None` if it isn't in one
+/// of the sources or not in a table. In nested tables, the innermost cell is
+/// found.
+fn cell_source(sources: &[Source], span: Span) -> Option<Place> {
+    let source = sources.iter().find(|source| span.id() == Some(source.id()))?;
+    let mut node = source.find(span)?;
+    loop {
+        let parent = node.parent()?.clone();
+        // Named arguments like `columns` are no cells, but may be in one,
+        // e.g. of a grid of tables.
+        if parent.kind() == SyntaxKind::Args
+            && node.kind() != SyntaxKind::Named
+            && let Some(call) = parent.parent()
+            && let Some(callee) = call.cast::<ast::FuncCall>().and_then(callee_name)
+            && matches!(callee.as_str(), "table" | "grid" | "table.header" | "table.footer" | "grid.header" | "grid.footer")
+        {
+            let mut table = call.clone();
+            while !table.cast::<ast::FuncCall>().and_then(callee_name).is_some_and(|name| name == "table" || name == "grid") {
+                table = table.parent()?.clone();
+            }
+            if node.kind() == SyntaxKind::Spread {
+                return Some(Place::Spread(table.span()));
+            }
+            let span_arg = |name: &str| {
+                let call = node.cast::<ast::FuncCall>()?;
+                if !callee_name(call).is_some_and(|callee| callee == "table.cell" || callee == "grid.cell") {
+                    return None;
+                }
+                call.args().items().find_map(|arg| match arg {
+                    ast::Arg::Named(named) if named.name().as_str() == name => match named.expr() {
+                        ast::Expr::Int(int) => usize::try_from(int.get()).ok().filter(|&span| span > 0),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+            };
+            return Some(Place::Cell(CellSource {
+                cell: node.span(),
+                table: table.span(),
+                rowspan: span_arg("rowspan").unwrap_or(1),
+                colspan: span_arg("colspan").unwrap_or(1),
+            }));
+        }
+        node = parent;
+    }
+}
+
+/// The name of the function a call calls, like `table` or `table.cell`, if
+/// it is called by name.
+fn callee_name(call: ast::FuncCall) -> Option<String> {
+    match call.callee() {
+        ast::Expr::Ident(ident) => Some(ident.as_str().to_string()),
+        ast::Expr::FieldAccess(access) => match access.target() {
+            ast::Expr::Ident(target) => Some(format!("{}.{}", target.as_str(), access.field().as_str())),
+            _ => None,
+        },
+        _ => None,
+    }
+}
@@ -0,0 +1,48 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::Deserialize;
+use typst::syntax::VirtualPath;
+
+/// The contents of a file in a virtual file system.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Contents {
+    /// A text file.
+    Text(String),
+    /// A binary file, e.g. an image, encoded as base64.
+    Binary { base64: String },
+}
+
+/// Parses a virtual file system from a JSON object that maps paths within the
+/// project root to file contents.
+///
+/// Text files are given as strings and binary files as `{"base64": "..."}`:
+///
+/// ```json
+/// {
+///   "main.typ": "#include \"chapters/one.typ\"\n#image(\"logo.png\")",
+///   "chapters/one.typ": "= One",
+///   "logo.png": { "base64": "iVBORw0KGgo..." }
+/// }
+/// ```
+pub fn parse_vfs(json: &str) -> Result<Vec<(VirtualPath, Vec<u8>)>, String> {
+    let entries: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(json).map_err(|error| format!("invalid file system: {error}"))?;
+    entries
+        .into_iter()
+        .map(|(path, value)| {
+            let contents = match Contents::deserialize(value) {
+                Ok(Contents::Text(text)) => text.into_bytes(),
+                Ok(Contents::Binary { base64 }) => STANDARD
+                    .decode(base64)
+                    .map_err(|error| format!("invalid base64 contents of {path}: {error}"))?,
+                Err(_) => {
+                    return Err(format!(
+                        "contents of {path} must be a string or an object with a base64 string"
+                    ));
+                }
+            };
+            Ok((VirtualPath::new(&path), contents))
+        })
+        .collect()
+}
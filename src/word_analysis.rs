@@ -1,160 +1,1024 @@
-use typst::layout::{Abs, Frame, FrameItem, PagedDocument, Point};
-use typst::text::{Glyph, TextItem};
-use typst::introspection::Tag;
-
-/// Returns an iterator over all words in a document, with their bounding boxes.
-pub fn words_with_boxes(
-    document: &PagedDocument,
-    include_whitespace: bool,
-    include_delimiters: bool
-) -> impl Iterator<Item = (String, (f64, f64, f64, f64))> + '_ {
-    document.pages.iter().flat_map(move |page| {
-        words_in_frame(&page.frame, include_whitespace, include_delimiters)
+use std::borrow::Cow;
+use std::ops::Range;
+
+use typst::foundations::Selector;
+use typst::layout::{Abs, Dir, Frame, FrameItem, Page, PagedDocument, Point, Position, Transform};
+use typst::model::Destination;
+use typst::syntax::Span;
+use ttf_parser::name_id;
+use typst::text::{Font, FontVariant, Glyph, TextItem};
+use typst::introspection::{Location, Tag};
+use typst::visualize::Paint;
+use unicode_bidi::{bidi_class, BidiClass};
+use unicode_properties::{GeneralCategoryGroup, UnicodeGeneralCategory};
+use unicode_script::{Script, UnicodeScript};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{BoxMode, SplitMode};
+
+/// A bounding box as `(x, y, width, height)` in pt.
+pub type BBox = (f64, f64, f64, f64);
+
+/// A word, or a group of words, together with its bounding box.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Word {
+    /// The text of the word.
+    pub text: String,
+    /// The bounding box relative to the page.
+    pub bbox: BBox,
+    /// The font family, if the word was set in a single text run.
+    pub font: Option<String>,
+    /// The subfamily name of the font (e.g. `Bold Italic`), if the word was
+    /// set in a single text run.
+    pub subfamily: Option<String>,
+    /// The weight, style and stretch of the font, if the word was set in a
+    /// single text run.
+    pub variant: Option<FontVariant>,
+    /// The font size in pt, if the word was set in a single text run. For a
+    /// word joined from parts on different lines, that of its first part.
+    pub font_size: Option<f64>,
+    /// Whether the word was joined from parts in different font sizes (see
+    /// [`merge_hyphenated`]). Text items are only joined within a line if
+    /// their size is the same, so text in another size is another word.
+    pub mixed_sizes: bool,
+    /// The fill color as a hex string (e.g. `#ff0000`), or `non-solid` for
+    /// gradients and tilings, if the word was set in a single text run.
+    pub color: Option<String>,
+    /// The rotation of the word in degrees (clockwise), if it is rotated.
+    pub rotation: Option<f64>,
+    /// The corners of the word's box (top-left, top-right, bottom-right,
+    /// bottom-left), if it is rotated, scaled or skewed. `bbox` is then the
+    /// axis-aligned box around them.
+    pub quad: Option<[(f64, f64); 4]>,
+    /// What the box covers: `word`, `char`, `glyph` or `line`, or for a group of
+    /// words the name of the element it was laid out for (e.g. `heading` or
+    /// `link`), or `group` for plain frame groups outside of any element.
+    /// Nested groups each have their own kind, i.e. the innermost element
+    /// wins.
+    pub kind: String,
+    /// The destination of the link the word is part of: a URL for external
+    /// links, and `#label` or `#page=N&x=X&y=Y` for links within the document.
+    pub link: Option<String>,
+    /// How many glyphs of the word are the font's `.notdef` glyph (usually a
+    /// box), shown for characters that no available font covers.
+    pub missing_glyphs: usize,
+    /// How reliable the box is, from 0 to 1, if the word was set in a single
+    /// text run. See [`glyph_score`].
+    pub score: Option<f64>,
+    /// The y-coordinate of the baseline the word was set on, relative to the
+    /// page, if the word was set in a single text run. For rotated words,
+    /// this is the y-coordinate of the start of the baseline.
+    pub baseline: Option<f64>,
+    /// The font's ascender and descender in pt, i.e. how far the box reaches
+    /// above (positive) and below (negative) the baseline.
+    pub metrics: Option<(f64, f64)>,
+    /// For a character, the index of the word it is part of among all words
+    /// of the document, in the order they were laid out.
+    pub word_index: Option<usize>,
+    /// The language the word was set in, with its region if there is one
+    /// (e.g. `de` or `en-GB`), if the word was set in a single text run.
+    pub lang: Option<String>,
+    /// The script most characters of the word are in, e.g. `Latin` or `Han`.
+    /// Characters of all scripts (like digits and punctuation) only count
+    /// if there are no others.
+    pub script: Option<String>,
+    /// The direction the word was set in, left to right or right to left, if
+    /// the word was set in a single text run.
+    pub dir: Option<Dir>,
+    /// For a glyph, how it was shaped.
+    pub shaping: Option<Shaping>,
+    /// If the word ends its run with a hyphen, and so may be the first part
+    /// of a word broken across lines: the text the hyphen stands for when
+    /// the parts are joined, which is empty for the hyphen Typst adds when it
+    /// hyphenates a word or breaks it at a soft hyphen. See
+    /// [`merge_hyphenated`].
+    pub hyphen: Option<String>,
+    /// The spans of the source the text of the word was made from, each with
+    /// the byte range of that text within the span. Empty for groups and
+    /// lines.
+    pub spans: Vec<(Span, Range<usize>)>,
+}
+
+/// How a glyph was shaped from the text of its run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shaping {
+    /// The index of the glyph in its font.
+    pub glyph_id: u16,
+    /// The byte range in the text of the run that the glyph was shaped from.
+    /// It covers several characters for ligatures.
+    pub cluster: Range<usize>,
+    /// How far the glyph moves the pen, in pt.
+    pub x_advance: f64,
+    /// How far the glyph is moved right from the pen position, in pt. Glyphs
+    /// have no vertical offset, which is in the position of their text item.
+    pub x_offset: f64,
+}
+
+impl Word {
+    /// Creates a word without style information, e.g. for a group of words.
+    pub(crate) fn plain(text: String, bbox: BBox, kind: &str) -> Self {
+        Self {
+            text,
+            bbox,
+            font: None,
+            subfamily: None,
+            variant: None,
+            font_size: None,
+            mixed_sizes: false,
+            color: None,
+            rotation: None,
+            quad: None,
+            kind: kind.to_string(),
+            link: None,
+            missing_glyphs: 0,
+            score: None,
+            baseline: None,
+            metrics: None,
+            word_index: None,
+            lang: None,
+            script: None,
+            dir: None,
+            shaping: None,
+            hyphen: None,
+            spans: Vec::new(),
+        }
+    }
+}
+
+/// How text is split into words and how their boxes are measured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TextOptions<'a> {
+    /// Whether to include boxes of whitespace.
+    pub include_whitespace: bool,
+    /// Whether to include boxes of delimiters.
+    pub include_delimiters: bool,
+    /// Where text is split into words.
+    pub split_mode: SplitMode,
+    /// Whether boxes reach only as far up and down as the outlines of their
+    /// glyphs, instead of the font's ascender and descender.
+    pub tight_boxes: bool,
+    /// How far up boxes reach, unless they are tight.
+    pub box_mode: BoxMode,
+    /// Whether all Unicode punctuation (like curly quotes, dashes and `…`)
+    /// is a delimiter where ASCII punctuation is, instead of only ASCII
+    /// punctuation.
+    pub unicode_delimiters: bool,
+    /// More characters that are delimiters where ASCII punctuation is, like
+    /// `“”–…`.
+    pub delimiters: &'a str,
+    /// Whether the symbols of equations are words of their own, within a
+    /// group of kind `equation`, instead of all of an equation being one box.
+    pub split_math: bool,
+}
+
+/// Returns an iterator over all words in a document, with the index of the
+/// page they are on. Bounding boxes are relative to that page.
+///
+/// With a page filter, only the pages with the given indices are searched.
+pub fn words_with_boxes<'a>(
+    document: &'a PagedDocument,
+    options: TextOptions<'a>,
+    page_filter: Option<&'a [usize]>,
+) -> impl Iterator<Item = (usize, Word)> + 'a {
+    selected_pages(document, page_filter).flat_map(move |(page_index, page)| {
+        let mut words: Vec<Word> = words_in_frame(&page.frame, options).collect();
+        attach_links(document, &page.frame, words.iter_mut().filter(|word| word.kind == "word"));
+        words.into_iter().map(move |word| (page_index, word))
+    })
+}
+
+/// Like [`words_with_boxes`], but joins the parts of words that are broken
+/// across lines with a hyphen (see [`merge_hyphenated`]), with the box of
+/// each part.
+pub fn unhyphenated_words_with_boxes<'a>(
+    document: &'a PagedDocument,
+    options: TextOptions<'a>,
+    page_filter: Option<&'a [usize]>,
+) -> impl Iterator<Item = (usize, Word, Vec<BBox>)> + 'a {
+    selected_pages(document, page_filter).flat_map(move |(page_index, page)| {
+        let mut words: Vec<Word> = words_in_frame(&page.frame, options).collect();
+        attach_links(document, &page.frame, words.iter_mut().filter(|word| word.kind == "word"));
+        merge_hyphenated(words).into_iter().map(move |(word, parts)| (page_index, word, parts))
+    })
+}
+
+/// What a word is to the words before it when joining hyphenated words.
+#[derive(Clone, Copy, PartialEq)]
+enum Part {
+    /// A word of its own, or the first part of a hyphenated word.
+    Start,
+    /// The hyphen after the first part, if delimiters are included.
+    Hyphen,
+    /// The part of a hyphenated word on the next line.
+    Continuation,
+}
+
+/// Joins each word that ends its line with a hyphen with the word that
+/// starts the next line of the same block, and returns the words of a page
+/// with the boxes of their parts, in the order they were laid out.
+///
+/// The box of a joined word is the box around its parts. Its text is that of
+/// its parts, with the hyphen left out if Typst added it or it is a soft
+/// hyphen, and its other properties are those of its first part, except that
+/// it is marked as having mixed sizes if its parts differ in size. A word that
+/// ends with a hyphen within a line, like the `well` of `well- and
+/// ill-formed`, is kept as it is.
+pub fn merge_hyphenated(words: Vec<Word>) -> Vec<(Word, Vec<BBox>)> {
+    let mut parts = vec![Part::Start; words.len()];
+    for (index, word) in words.iter().enumerate() {
+        let Some(hyphen) = &word.hyphen else {
+            continue;
+        };
+        let mut next = index + 1;
+        let has_hyphen_word = words.get(next).is_some_and(|next| !hyphen.is_empty() && next.text == *hyphen);
+        if has_hyphen_word {
+            next += 1;
+        }
+        if let Some(next_word) = words.get(next)
+            && next_word.kind == "word"
+            && starts_next_line(word, next_word)
+        {
+            if has_hyphen_word {
+                parts[index + 1] = Part::Hyphen;
+            }
+            parts[next] = Part::Continuation;
+        }
+    }
+
+    let mut merged: Vec<(Word, Vec<BBox>)> = Vec::with_capacity(words.len());
+    for (word, part) in words.into_iter().zip(parts) {
+        let Some((last, boxes)) = merged.last_mut().filter(|_| part != Part::Start) else {
+            let bbox = word.bbox;
+            merged.push((word, vec![bbox]));
+            continue;
+        };
+        last.bbox = union_bbox(last.bbox, word.bbox);
+        if part == Part::Hyphen {
+            let line_box = boxes.last_mut().unwrap();
+            *line_box = union_bbox(*line_box, word.bbox);
+            continue;
+        }
+        let hyphen = last.hyphen.take().unwrap_or_default();
+        let text = last.text.trim_end_matches(SOFT_HYPHEN);
+        last.text = if text.ends_with(hyphen.as_str()) {
+            format!("{text}{}", word.text)
+        } else {
+            format!("{text}{hyphen}{}", word.text)
+        };
+        last.missing_glyphs += word.missing_glyphs;
+        last.mixed_sizes |= word.mixed_sizes || word.font_size != last.font_size;
+        last.score = last.score.zip(word.score).map(|(a, b)| a.min(b));
+        last.hyphen = word.hyphen;
+        last.spans.extend(word.spans);
+        boxes.push(word.bbox);
+    }
+    merged
+}
+
+/// Whether a word is on the line right below that of a word before it, as
+/// the next line of a block is (see [`blocks_with_boxes`]).
+fn starts_next_line(word: &Word, next: &Word) -> bool {
+    let (_, y, _, h) = word.bbox;
+    let (_, next_y, _, next_h) = next.bbox;
+    baseline(next) - baseline(word) > h / 2.0 && next_y - (y + h) <= h.min(next_h) / 2.0
+}
+
+/// Returns an iterator over the words and groups in a document that are not
+/// part of a group, with the index of the page they are on. Unlike
+/// [`words_with_boxes`], groups keep the words and groups laid out within
+/// them, so that their nesting is preserved.
+pub fn word_trees_with_boxes<'a>(
+    document: &'a PagedDocument,
+    options: TextOptions<'a>,
+    page_filter: Option<&'a [usize]>,
+) -> impl Iterator<Item = (usize, Element)> + 'a {
+    fn words_mut<'e>(elements: &'e mut [Element], words: &mut Vec<&'e mut Word>) {
+        for element in elements {
+            match element {
+                Element::Word(word) => words.push(word),
+                Element::Group(_, elements) => words_mut(elements, words),
+            }
+        }
+    }
+
+    selected_pages(document, page_filter).flat_map(move |(page_index, page)| {
+        let mut elements = elements_in_frame(&page.frame, options);
+        let mut words = Vec::new();
+        words_mut(&mut elements, &mut words);
+        attach_links(document, &page.frame, words.into_iter());
+        elements.into_iter().map(move |element| (page_index, element))
+    })
+}
+
+/// Returns an iterator over all glyphs in a document, with the index of the
+/// page they are on. Bounding boxes are relative to that page.
+///
+/// The text of each glyph is the cluster it was shaped from, so a ligature
+/// yields all the characters it covers, and each glyph records how it was
+/// shaped. Words are split like for [`words_with_boxes`], and each glyph of a
+/// word records the index of its word. Whitespace and delimiters only have
+/// one if they are included as words.
+pub fn glyphs_with_boxes<'a>(
+    document: &'a PagedDocument,
+    options: TextOptions<'a>,
+    page_filter: Option<&'a [usize]>,
+) -> impl Iterator<Item = (usize, Word)> + 'a {
+    let mut next_word = 0;
+    selected_pages(document, page_filter).flat_map(move |(page_index, page)| {
+        let mut glyphs = Vec::new();
+        glyphs_in_frame(&page.frame, Transform::identity(), options, &mut next_word, &mut glyphs);
+        attach_links(document, &page.frame, glyphs.iter_mut());
+        glyphs.into_iter().map(move |glyph| (page_index, glyph))
+    })
+}
+
+/// Collects all glyphs in a frame and its nested groups, with their bounding
+/// boxes, numbering their words from `next_word` on.
+fn glyphs_in_frame(frame: &Frame, ts: Transform, options: TextOptions, next_word: &mut usize, glyphs: &mut Vec<Word>) {
+    for (pos, item) in frame.items() {
+        let item_ts = ts.pre_concat(Transform::translate(pos.x, pos.y));
+        match item {
+            FrameItem::Text(text_item) => {
+                let size = text_item.size;
+                let word_ranges = word_ranges(text_item, options);
+                // Each glyph is a word of its own, placed at the current pen position.
+                let mut current_x = Abs::zero();
+                for (i, glyph) in text_item.glyphs.iter().enumerate() {
+                    let mut item_glyphs = Vec::new();
+                    let word_glyphs = std::slice::from_ref(glyph);
+                    finalize_word(&Point::zero(), text_item, word_glyphs, current_x, options, &mut item_glyphs);
+                    current_x += glyph.x_advance.at(size);
+                    let word_index = word_ranges.iter().position(|range| range.contains(&i));
+                    glyphs.extend(item_glyphs.into_iter().map(|word| Word {
+                        kind: "glyph".to_string(),
+                        word_index: word_index.map(|index| index + *next_word),
+                        shaping: Some(Shaping {
+                            glyph_id: glyph.id,
+                            cluster: glyph.range(),
+                            x_advance: glyph.x_advance.at(size).to_pt(),
+                            x_offset: glyph.x_offset.at(size).to_pt(),
+                        }),
+                        ..transform_word(word, item_ts)
+                    }));
+                }
+                *next_word += word_ranges.len();
+            }
+            FrameItem::Group(group) => {
+                glyphs_in_frame(&group.frame, item_ts.pre_concat(group.transform), options, next_word, glyphs)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Returns an iterator over all characters in a document, with the index of
+/// the page they are on. Bounding boxes are relative to that page.
+///
+/// A character is a grapheme cluster, so combining marks are part of the
+/// character they are attached to. Words are split like for
+/// [`words_with_boxes`], and each character records the index of its word.
+pub fn chars_with_boxes<'a>(
+    document: &'a PagedDocument,
+    options: TextOptions<'a>,
+    page_filter: Option<&'a [usize]>,
+) -> impl Iterator<Item = (usize, Word)> + 'a {
+    let mut next_word = 0;
+    selected_pages(document, page_filter).flat_map(move |(page_index, page)| {
+        let mut chars = Vec::new();
+        chars_in_frame(&page.frame, Transform::identity(), options, &mut next_word, &mut chars);
+        attach_links(document, &page.frame, chars.iter_mut());
+        chars.into_iter().map(move |char| (page_index, char))
+    })
+}
+
+/// Collects all characters in a frame and its nested groups, numbering their
+/// words from `next_word` on.
+fn chars_in_frame(frame: &Frame, ts: Transform, options: TextOptions, next_word: &mut usize, chars: &mut Vec<Word>) {
+    for (pos, item) in joined_items(frame, options) {
+        let item_ts = ts.pre_concat(Transform::translate(pos.x, pos.y));
+        match item.as_ref() {
+            FrameItem::Text(text_item) => {
+                // The word indices of the characters start at 0 in each text item.
+                let mut item_chars = Vec::new();
+                process_text_item(&Point::zero(), text_item, &mut item_chars, options, true);
+                let word_count = item_chars.last().and_then(|char| char.word_index).map_or(0, |index| index + 1);
+                chars.extend(item_chars.into_iter().map(|char| Word {
+                    kind: "char".to_string(),
+                    word_index: char.word_index.map(|index| index + *next_word),
+                    ..transform_word(char, item_ts)
+                }));
+                *next_word += word_count;
+            }
+            FrameItem::Group(group) => {
+                chars_in_frame(&group.frame, item_ts.pre_concat(group.transform), options, next_word, chars)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Returns an iterator over all lines of text in a document.
+///
+/// Consecutive words are part of the same line while their baselines are
+/// less than half a line height apart (so that super- and subscripts are part
+/// of the line) and they are set in the same run of text, in runs less than
+/// half a line height apart (e.g. a bold word within a paragraph, or the
+/// parts of an equation), or in frames nested in one another (e.g. an inline
+/// box). Words of different columns or of text blocks side by
+/// side, like the cells of a grid, are thus not joined. Words are separated by
+/// a single space in the line's text wherever there is a horizontal gap
+/// between them, unless the whitespace between them is included as words of
+/// its own.
+pub fn lines_with_boxes<'a>(
+    document: &'a PagedDocument,
+    options: TextOptions<'a>,
+    page_filter: Option<&'a [usize]>,
+) -> impl Iterator<Item = Line> + 'a {
+    selected_pages(document, page_filter).flat_map(move |(page_index, page)| {
+        let (runs, words) = runs_on_page(document, page, options);
+        group_into_lines(&runs, words.into_iter()).into_iter().map(move |(line, words)| {
+            (page_index, line, words.into_iter().map(|(_, word)| word).collect())
+        })
+    })
+}
+
+/// Returns an iterator over all blocks of text in a document: paragraphs,
+/// headings and the items of lists, enumerations and term lists.
+///
+/// Blocks are made of lines (see [`lines_with_boxes`]). Lines in the same
+/// element, as found from its `Tag::Start` and `Tag::End`, are one block of
+/// the kind `content.func().name()`, e.g. `heading`, `par` or `item`, except
+/// that a line all above the previous one (in the next column) starts another.
+///
+/// Typst only tags elements that are locatable or labelled, which paragraphs
+/// and list items aren't unless they have a label. Other lines continue the
+/// block of the previous line if it isn't tagged either, they are right below
+/// it, with a gap of less than half the height of the shorter line
+/// (paragraphs are spaced further apart), overlap it horizontally and don't
+/// start with a list marker, i.e. a run of text set apart from the rest of
+/// the line. Such blocks are of kind `item` if they start with a marker, or
+/// else `par`. The text of a block is that of its lines, separated by single
+/// spaces.
+pub fn blocks_with_boxes<'a>(
+    document: &'a PagedDocument,
+    options: TextOptions<'a>,
+    page_filter: Option<&'a [usize]>,
+) -> impl Iterator<Item = Block> + 'a {
+    selected_pages(document, page_filter).flat_map(move |(page_index, page)| {
+        let (runs, words) = runs_on_page(document, page, options);
+        let lines = group_into_lines(&runs, words.into_iter());
+        group_into_blocks(&runs, lines)
+            .into_iter()
+            .map(move |(block, lines)| (page_index, block, lines))
+    })
+}
+
+/// A block of text as `(page, block, lines)`.
+pub type Block = (usize, Word, Vec<BlockLine>);
+
+/// A line of a block, with its words.
+pub type BlockLine = (Word, Vec<Word>);
+
+/// Returns an iterator over all sentences in a document.
+///
+/// The text of each block (see [`blocks_with_boxes`]) is split at the
+/// Unicode sentence boundaries (UAX #29), except after common abbreviations
+/// like `e.g.` or `Dr.` and after initials. A word belongs to the sentence
+/// its text starts in. Besides the box around all its words, each sentence
+/// has one box per line it is on.
+///
+/// Delimiters are always part of the text and the boxes of sentences, as
+/// their punctuation is needed to find them, but they are only among the
+/// words of a sentence if `include_delimiters` is set.
+pub fn sentences_with_boxes<'a>(
+    document: &'a PagedDocument,
+    options: TextOptions<'a>,
+    page_filter: Option<&'a [usize]>,
+) -> impl Iterator<Item = Sentence> + 'a {
+    let with_delimiters = TextOptions { include_delimiters: true, ..options };
+    blocks_with_boxes(document, with_delimiters, page_filter).flat_map(move |(page_index, _, lines)| {
+        split_into_sentences(lines).into_iter().map(move |(sentence, line_boxes, mut words)| {
+            if !options.include_delimiters {
+                words.retain(|word| !word.text.chars().all(|c| is_delimiter_char(c, options)));
+            }
+            (page_index, sentence, line_boxes, words)
+        })
     })
 }
 
+/// A sentence as `(page, sentence, line_boxes, words)`, where `line_boxes`
+/// are the boxes around its words on each line it is on.
+pub type Sentence = (usize, Word, Vec<BBox>, Vec<Word>);
+
+/// Abbreviations that are followed by a period without ending a sentence,
+/// in lowercase. Those that often end sentences, like `etc.`, are left out.
+const ABBREVIATIONS: &[&str] = &[
+    "e.g.", "i.e.", "cf.", "vs.", "dr.", "mr.", "mrs.", "ms.", "prof.", "st.", "jr.", "sr.", "no.", "fig.",
+    "eq.", "approx.",
+];
+
+/// Splits the lines of a block into sentences, each with its box on each
+/// line and its words.
+fn split_into_sentences(lines: Vec<BlockLine>) -> Vec<(Word, Vec<BBox>, Vec<Word>)> {
+    // The text of the block, with the line and start of each word in it.
+    let mut text = String::new();
+    let mut words = Vec::new();
+    for (line_index, (line, line_words)) in lines.into_iter().enumerate() {
+        if line_index > 0 {
+            text.push(' ');
+        }
+        let line_start = text.len();
+        text.push_str(&line.text);
+        // The words are in the line's text in order, separated by spaces.
+        let mut cursor = line_start;
+        for word in line_words {
+            let start = text[cursor..].find(&word.text).map_or(cursor, |offset| cursor + offset);
+            cursor = start + word.text.len();
+            words.push((line_index, start, word));
+        }
+    }
+
+    // The start of each sentence, skipping boundaries after abbreviations.
+    let mut starts: Vec<usize> = Vec::new();
+    let mut previous = "";
+    for (start, sentence) in text.split_sentence_bound_indices() {
+        if starts.is_empty() || !ends_with_abbreviation(previous) {
+            starts.push(start);
+        }
+        previous = sentence;
+    }
+
+    let mut sentences: Vec<(Word, Vec<BBox>, Vec<Word>)> = Vec::new();
+    let mut last_sentence = None;
+    let mut last_line = None;
+    for (line_index, start, word) in words {
+        let sentence_index = starts.partition_point(|&sentence_start| sentence_start <= start).saturating_sub(1);
+        if last_sentence != Some(sentence_index) {
+            let end = starts.get(sentence_index + 1).copied().unwrap_or(text.len());
+            let sentence_text = text[starts[sentence_index]..end].split_whitespace().collect::<Vec<_>>().join(" ");
+            let sentence = Word {
+                missing_glyphs: word.missing_glyphs,
+                ..Word::plain(sentence_text, word.bbox, "sentence")
+            };
+            sentences.push((sentence, vec![word.bbox], vec![word]));
+        } else {
+            let (sentence, line_boxes, sentence_words) = sentences.last_mut().unwrap();
+            sentence.bbox = union_bbox(sentence.bbox, word.bbox);
+            sentence.missing_glyphs += word.missing_glyphs;
+            if last_line == Some(line_index) {
+                let line_box = line_boxes.last_mut().unwrap();
+                *line_box = union_bbox(*line_box, word.bbox);
+            } else {
+                line_boxes.push(word.bbox);
+            }
+            sentence_words.push(word);
+        }
+        last_sentence = Some(sentence_index);
+        last_line = Some(line_index);
+    }
+    sentences
+}
+
+/// Whether a piece of text ends with an abbreviation or an initial, like the
+/// `J.` of `J. Smith`, after which a period doesn't end the sentence.
+fn ends_with_abbreviation(text: &str) -> bool {
+    let Some(last) = text.split_whitespace().last() else {
+        return false;
+    };
+    let last = last.trim_start_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+    let mut chars = last.chars();
+    let is_initial =
+        matches!((chars.next(), chars.next(), chars.next()), (Some(c), Some('.'), None) if c.is_alphabetic());
+    is_initial || ABBREVIATIONS.contains(&last.as_str())
+}
+
+/// Returns all runs of text on a page, and their words with the index of
+/// their run, with links attached.
+fn runs_on_page(document: &PagedDocument, page: &Page, options: TextOptions) -> (Vec<Run>, Vec<(usize, Word)>) {
+    let mut collector = RunCollector {
+        options,
+        path: vec![0],
+        last_id: 0,
+        blocks: Vec::new(),
+        runs: Vec::new(),
+        words: Vec::new(),
+    };
+    collector.collect(&page.frame, Transform::identity());
+    let RunCollector { runs, mut words, .. } = collector;
+    attach_links(document, &page.frame, words.iter_mut().map(|(_, word)| word));
+    (runs, words)
+}
+
+/// A run of text, i.e. a `TextItem`, as placed on the page.
+struct Run {
+    /// The frame the run was laid out in, as the numbers of the frame and the
+    /// groups it is nested in, outermost first.
+    frame: Vec<usize>,
+    /// The horizontal extent of the run on the page.
+    left: f64,
+    right: f64,
+    /// The innermost block element the run is part of, with its name.
+    block: Option<BlockElement>,
+}
+
+/// A tagged element whose text is a block, as its location and name.
+type BlockElement = (Location, &'static str);
+
+/// The names of the elements whose text is a block, if they are tagged.
+const BLOCK_ELEMENTS: &[&str] = &["heading", "par", "item"];
+
+/// Collects the runs of text and their words in a frame and its nested groups.
+struct RunCollector<'a> {
+    options: TextOptions<'a>,
+    /// The path of the current frame. Groups are numbered after `last_id`.
+    path: Vec<usize>,
+    last_id: usize,
+    /// The block elements whose start tag has been seen, but not their end
+    /// tag, outermost first.
+    blocks: Vec<BlockElement>,
+    runs: Vec<Run>,
+    /// The words of the runs, each with the index of its run.
+    words: Vec<(usize, Word)>,
+}
+
+impl RunCollector<'_> {
+    fn collect(&mut self, frame: &Frame, ts: Transform) {
+        for (pos, item) in joined_items(frame, self.options) {
+            let item_ts = ts.pre_concat(Transform::translate(pos.x, pos.y));
+            match item.as_ref() {
+                FrameItem::Text(text_item) => {
+                    let extent = Word::plain(String::new(), (0.0, 0.0, text_item.width().to_pt(), 0.0), "");
+                    let (left, _, width, _) = transform_word(extent, item_ts).bbox;
+                    let frame = self.path.clone();
+                    self.runs.push(Run { frame, left, right: left + width, block: self.blocks.last().copied() });
+
+                    let mut item_words = Vec::new();
+                    process_text_item(&Point::zero(), text_item, &mut item_words, self.options, false);
+                    let run = self.runs.len() - 1;
+                    self.words.extend(item_words.into_iter().map(|word| (run, transform_word(word, item_ts))));
+                }
+                FrameItem::Group(group) => {
+                    self.last_id += 1;
+                    self.path.push(self.last_id);
+                    self.collect(&group.frame, item_ts.pre_concat(group.transform));
+                    self.path.pop();
+                }
+                FrameItem::Tag(Tag::Start(content)) => {
+                    let name = content.func().name();
+                    if let Some(location) = content.location()
+                        && BLOCK_ELEMENTS.contains(&name)
+                    {
+                        self.blocks.push((location, name));
+                    }
+                }
+                FrameItem::Tag(Tag::End(location, _)) => {
+                    self.blocks.retain(|(open, _)| open != location);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Returns the pages of a document with their indices, only those with the
+/// given indices if there is a page filter.
+pub fn selected_pages<'a>(
+    document: &'a PagedDocument,
+    page_filter: Option<&'a [usize]>,
+) -> impl Iterator<Item = (usize, &'a Page)> + 'a {
+    document
+        .pages
+        .iter()
+        .enumerate()
+        .filter(move |(page_index, _)| page_filter.is_none_or(|pages| pages.contains(page_index)))
+}
+
+/// A line of text as `(page, line, words)`.
+pub type Line = (usize, Word, Vec<Word>);
+
+/// Groups words, in the order they were laid out and with the indices of the
+/// runs they are part of, into lines. `runs` are all runs in the order they
+/// were laid out.
+fn group_into_lines(runs: &[Run], words: impl Iterator<Item = (usize, Word)>) -> Vec<(Word, Vec<(usize, Word)>)> {
+    let mut lines: Vec<(Word, Vec<(usize, Word)>)> = Vec::new();
+    for (run, word) in words {
+        let (x, _, w, _) = word.bbox;
+        if let Some((line, line_words)) = lines.last_mut() {
+            let (_, _, _, line_h) = line.bbox;
+            let (last_run, last) = line_words.last().unwrap();
+            let (last_x, _, last_w, last_h) = last.bbox;
+            // All runs from the last word's to this one's must be connected,
+            // including those without words (e.g. an omitted `+`).
+            let connected = runs[*last_run..=run].windows(2).all(|pair| {
+                let (a, b) = (&pair[0], &pair[1]);
+                if a.frame == b.frame {
+                    // Runs of the same line of a paragraph follow each other
+                    // without a gap, or with math spacing in equations,
+                    // either way for right-to-left text.
+                    (b.left - a.right).abs().min((a.left - b.right).abs()) <= last_h / 2.0
+                } else {
+                    a.frame.starts_with(&b.frame) || b.frame.starts_with(&a.frame)
+                }
+            });
+            if connected && (baseline(&word) - baseline(last)).abs() <= line_h / 2.0 {
+                // Separate words that aren't directly adjacent with a space,
+                // either side as words of right-to-left text go leftwards.
+                let gap = (x - (last_x + last_w)).max(last_x - (x + w));
+                let is_blank = |s: &str| s.chars().all(char::is_whitespace);
+                if gap > 0.1 * last_h && !is_blank(&word.text) && !line.text.ends_with(char::is_whitespace) {
+                    line.text.push(' ');
+                }
+                line.text.push_str(&word.text);
+                line.bbox = union_bbox(line.bbox, word.bbox);
+                line.missing_glyphs += word.missing_glyphs;
+                line_words.push((run, word));
+                continue;
+            }
+        }
+        let line = Word { missing_glyphs: word.missing_glyphs, ..Word::plain(word.text.clone(), word.bbox, "line") };
+        lines.push((line, vec![(run, word)]));
+    }
+    lines
+}
+
+/// Groups lines, in the order they were laid out and with the run of each
+/// word, into blocks.
+fn group_into_blocks(runs: &[Run], lines: Vec<(Word, Vec<(usize, Word)>)>) -> Vec<(Word, Vec<BlockLine>)> {
+    // Each block with the element it is, if it is tagged.
+    let mut blocks: Vec<(Option<BlockElement>, Word, Vec<BlockLine>)> = Vec::new();
+    for (line, words) in lines {
+        let element = runs[words[0].0].block;
+        let marker = element.is_none() && starts_with_marker(runs, &words);
+        let words: Vec<Word> = words.into_iter().map(|(_, word)| word).collect();
+        if let Some((block_element, block, block_lines)) = blocks.last_mut() {
+            let (x, y, w, h) = line.bbox;
+            let (last_x, last_y, last_w, last_h) = block_lines.last().unwrap().0.bbox;
+            let continues = match element {
+                Some(_) => *block_element == element && y + h > last_y,
+                None => {
+                    // Relative to the shorter line, as inline boxes or
+                    // equations can make a line taller.
+                    let below = y > last_y && y - (last_y + last_h) <= last_h.min(h) / 2.0;
+                    let overlapping = x < last_x + last_w && last_x < x + w;
+                    block_element.is_none() && below && overlapping && !marker
+                }
+            };
+            if continues {
+                block.text.push(' ');
+                block.text.push_str(&line.text);
+                block.bbox = union_bbox(block.bbox, line.bbox);
+                block.missing_glyphs += line.missing_glyphs;
+                block_lines.push((line, words));
+                continue;
+            }
+        }
+        let kind = match element {
+            Some((_, name)) => name,
+            None if marker => "item",
+            None => "par",
+        };
+        let block = Word { missing_glyphs: line.missing_glyphs, ..Word::plain(line.text.clone(), line.bbox, kind) };
+        blocks.push((element, block, vec![(line, words)]));
+    }
+    blocks
+        .into_iter()
+        .map(|(_, mut block, lines)| {
+            block.text = block.text.split_whitespace().collect::<Vec<_>>().join(" ");
+            (block, lines)
+        })
+        .collect()
+}
+
+/// Whether a line of untagged text starts with a list marker, like `•`, `1.`
+/// or the term of a term list: a run of text that is set apart from the next
+/// run of the line by more than a third of a line height, more than spacing
+/// in equations.
+fn starts_with_marker(runs: &[Run], words: &[(usize, Word)]) -> bool {
+    let Some((first_run, first)) = words.first() else {
+        return false;
+    };
+    let Some((next_run, _)) = words.iter().find(|(run, _)| run != first_run) else {
+        return false;
+    };
+    let (a, b) = (&runs[*first_run], &runs[*next_run]);
+    let (_, _, _, h) = first.bbox;
+    // Either side, as markers of right-to-left text are on the right.
+    a.frame == b.frame && (b.left - a.right).max(a.left - b.right) > h / 3.0
+}
+
+/// The y-coordinate of a word's baseline, or of the center of its box for
+/// words without one.
+fn baseline(word: &Word) -> f64 {
+    let (_, y, _, h) = word.bbox;
+    word.baseline.unwrap_or(y + h / 2.0)
+}
+
+/// Sets the link of each word whose center lies within the area of a link in
+/// the frame.
+fn attach_links<'a>(document: &PagedDocument, frame: &Frame, words: impl Iterator<Item = &'a mut Word>) {
+    let mut links = Vec::new();
+    links_in_frame(document, frame, Transform::identity(), &mut links);
+    if links.is_empty() {
+        return;
+    }
+    for word in words {
+        let (x, y, w, h) = word.bbox;
+        let (center_x, center_y) = (x + w / 2.0, y + h / 2.0);
+        word.link = links
+            .iter()
+            .find(|((lx, ly, lw, lh), _)| {
+                (*lx..=lx + lw).contains(&center_x) && (*ly..=ly + lh).contains(&center_y)
+            })
+            .map(|(_, destination)| destination.clone());
+    }
+}
+
+/// Collects the clickable areas of all links in a frame and its nested
+/// groups, with their destinations.
+fn links_in_frame(document: &PagedDocument, frame: &Frame, ts: Transform, links: &mut Vec<(BBox, String)>) {
+    for (pos, item) in frame.items() {
+        let item_ts = ts.pre_concat(Transform::translate(pos.x, pos.y));
+        match item {
+            FrameItem::Link(destination, size) => {
+                let area = Word::plain(String::new(), (0.0, 0.0, size.x.to_pt(), size.y.to_pt()), "link");
+                links.push((transform_word(area, item_ts).bbox, link_destination(document, destination)));
+            }
+            FrameItem::Group(group) => {
+                links_in_frame(document, &group.frame, item_ts.pre_concat(group.transform), links)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Describes where a link leads: the URL of external links, the label of
+/// the target element as `#label`, or else the target position as
+/// `#page=N&x=X&y=Y` (in pt).
+fn link_destination(document: &PagedDocument, destination: &Destination) -> String {
+    let position_fragment = |position: Position| {
+        format!(
+            "#page={}&x={:.2}&y={:.2}",
+            position.page,
+            position.point.x.to_pt(),
+            position.point.y.to_pt()
+        )
+    };
+    match destination {
+        Destination::Url(url) => url.as_str().to_string(),
+        Destination::Position(position) => position_fragment(*position),
+        Destination::Location(location) => document
+            .introspector
+            .query_first(&Selector::Location(*location))
+            .and_then(|content| content.label())
+            .map(|label| format!("#{}", label.resolve().as_str()))
+            .unwrap_or_else(|| position_fragment(document.introspector.position(*location))),
+    }
+}
+
+/// A word or a group of words as produced by the frame traversal.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Element {
+    Word(Word),
+    /// A group, whose kind is the group type, with the words and groups laid
+    /// out within it.
+    Group(Word, Vec<Element>),
+}
+
+/// Computes the smallest bounding box containing both boxes.
+pub fn union_bbox(a: BBox, b: BBox) -> BBox {
+    let (x1, y1, w1, h1) = a;
+    let (x2, y2, w2, h2) = b;
+    let left = x1.min(x2);
+    let top = y1.min(y2);
+    let right = (x1 + w1).max(x2 + w2);
+    let bottom = (y1 + h1).max(y2 + h2);
+    (left, top, right - left, bottom - top)
+}
+
 /// Returns an iterator over all words and groups in a frame, with their bounding boxes.
+///
+/// Each group follows its words. Groups nested in other groups are left out,
+/// though their words are not.
 fn words_in_frame(
     frame: &Frame,
-    include_whitespace: bool,
-    include_delimiters: bool
-) -> impl Iterator<Item = (String, (f64, f64, f64, f64))> + '_ {
-    #[derive(Debug,Clone)]
-    enum Element {
-        Word(String, (f64, f64, f64, f64)),
-        Group(String, (f64, f64, f64, f64), String), // (content, bbox, group_type)
-    }
-
-    // Helper to compute the union of two bounding boxes
-    fn union_bbox(a: (f64, f64, f64, f64), b: (f64, f64, f64, f64)) -> (f64, f64, f64, f64) {
-        let (x1, y1, w1, h1) = a;
-        let (x2, y2, w2, h2) = b;
-        let left = x1.min(x2);
-        let top = y1.min(y2);
-        let right = (x1 + w1).max(x2 + w2);
-        let bottom = (y1 + h1).max(y2 + h2);
-        (left, top, right - left, bottom - top)
+    options: TextOptions,
+) -> impl Iterator<Item = Word> {
+    fn flatten(element: Element, top_level: bool, words: &mut Vec<Word>) {
+        match element {
+            Element::Word(word) => words.push(word),
+            Element::Group(group, elements) => {
+                for element in elements {
+                    flatten(element, false, words);
+                }
+                if top_level {
+                    words.push(group);
+                }
+            }
+        }
+    }
+
+    let mut words = Vec::new();
+    for element in elements_in_frame(frame, options) {
+        flatten(element, true, &mut words);
     }
+    words.into_iter()
+}
+
+/// A group whose words are still being collected.
+struct OpenGroup {
+    /// The name of the element the group was laid out for, or `group` for a
+    /// plain frame group.
+    kind: String,
+    /// The location of the element, to find where it ends.
+    location: Option<Location>,
+    elements: Vec<Element>,
+}
+
+/// Returns the words and groups in a frame that are not part of a group, in
+/// the order they were laid out.
+///
+/// Groups are made for the elements whose start and end tags are in the
+/// frame, and for the frame groups (e.g. of transformed content) that are not
+/// in such an element, so that the kind of an element isn't hidden by the
+/// frames it is laid out in. Elements that only start or end in the frame,
+/// like a paragraph that breaks across pages, end with the frame.
+fn elements_in_frame(
+    frame: &Frame,
+    options: TextOptions,
+) -> Vec<Element> {
 
     // The recursive traversal function
     fn traverse_frames(
         frame: &Frame,
-        base_pos: Point,
+        ts: Transform,
         output: &mut Vec<Element>,
-        group_stack: &mut Vec<(String, Vec<Element>)>, // (group_type, elements)
-        include_whitespace: bool,
-        include_delimiters: bool,
+        group_stack: &mut Vec<OpenGroup>,
+        options: TextOptions,
     ) {
-        for (pos, item) in frame.items() {
-            let absolute_pos = base_pos + *pos;
-            match item {
+        for (pos, item) in joined_items(frame, options) {
+            let item_ts = ts.pre_concat(Transform::translate(pos.x, pos.y));
+            match item.as_ref() {
                 FrameItem::Text(text_item) => {
+                    // The operators of an equation that is one box are part
+                    // of its text.
+                    let in_math = !options.split_math && group_stack.iter().any(|group| group.kind == "equation");
                     let mut words = Vec::new();
                     process_text_item(
-                        &absolute_pos,
+                        &Point::zero(),
                         text_item,
                         &mut words,
-                        include_whitespace,
-                        include_delimiters,
+                        TextOptions { include_delimiters: options.include_delimiters || in_math, ..options },
+                        false,
                     );
 
                     // Add each word to the current group or top-level output
-                    for (text, bbox) in words {
-                        let element = Element::Word(text, bbox);
-                        if let Some((_, current_group)) = group_stack.last_mut() {
-                            current_group.push(element.clone());
+                    for word in words {
+                        let element = Element::Word(transform_word(word, item_ts));
+                        match group_stack.last_mut() {
+                            Some(current_group) => current_group.elements.push(element),
+                            None => output.push(element),
                         }
-                        output.push(element);
                     }
                 }
                 FrameItem::Group(group) => {
-                    // Start a new group for the nested frame
-                    group_stack.push(("group".to_string(), Vec::new()));
-                    
+                    // Within an element, the words of the nested frame are
+                    // part of the element's group.
+                    let depth = group_stack.len();
+                    if depth == 0 {
+                        group_stack.push(OpenGroup { kind: "group".to_string(), location: None, elements: Vec::new() });
+                    }
+
                     // Recursively process the nested frame
                     traverse_frames(
                         &group.frame,
-                        absolute_pos,
+                        item_ts.pre_concat(group.transform),
                         output,
                         group_stack,
-                        include_whitespace,
-                        include_delimiters,
+                        options,
                     );
-                    
-                    // Finalize the group
-                    if let Some((group_type, elements)) = group_stack.pop() {
-                        if !elements.is_empty() {
-                            // Compute the group's string and bounding box
-                            let mut full_text = String::new();
-                            let mut overall_bbox = None;
-
-                            for element in &elements {
-                                match element {
-                                    Element::Word(text, bbox) => {
-                                        full_text.push_str(text);
-                                        overall_bbox = overall_bbox
-                                            .map(|bb| union_bbox(bb, *bbox))
-                                            .or(Some(*bbox));
-                                    }
-                                    Element::Group(text, bbox, _) => {
-                                        full_text.push_str(text);
-                                        overall_bbox = overall_bbox
-                                            .map(|bb| union_bbox(bb, *bbox))
-                                            .or(Some(*bbox));
-                                    }
-                                }
-                            }
-
-                            if let Some(bbox) = overall_bbox {
-                                let group_element = Element::Group(full_text, bbox, group_type);
-                                // Add the group to the current group or top-level output
-                                if let Some((_, current_group)) = group_stack.last_mut() {
-                                    current_group.push(group_element);
-                                } else {
-                                    output.push(group_element);
-                                }
-                            }
+
+                    // Finalize the group, with the elements that started in
+                    // it but did not end.
+                    if depth == 0 {
+                        while !group_stack.is_empty() {
+                            finalize_group(output, group_stack, options);
                         }
                     }
                 }
                 FrameItem::Tag(Tag::Start(content)) => {
                     // Use function name as group type
-                    let group_type = content.func().name().to_string();
-                    group_stack.push((group_type, Vec::new()));
+                    group_stack.push(OpenGroup {
+                        kind: content.func().name().to_string(),
+                        location: content.location(),
+                        elements: Vec::new(),
+                    });
                 }
-                FrameItem::Tag(Tag::End(_, _)) => {
-                    if let Some((group_type, elements)) = group_stack.pop() {
-                        if !elements.is_empty() {
-                            // Compute the group's string and bounding box
-                            let mut full_text = String::new();
-                            let mut overall_bbox = None;
-
-                            for element in &elements {
-                                match element {
-                                    Element::Word(text, bbox) => {
-                                        full_text.push_str(text);
-                                        overall_bbox = overall_bbox
-                                            .map(|bb| union_bbox(bb, *bbox))
-                                            .or(Some(*bbox));
-                                    }
-                                    Element::Group(text, bbox, _) => {
-                                        full_text.push_str(text);
-                                        overall_bbox = overall_bbox
-                                            .map(|bb| union_bbox(bb, *bbox))
-                                            .or(Some(*bbox));
-                                    }
-                                }
-                            }
-
-                            if let Some(bbox) = overall_bbox {
-                                let group_element = Element::Group(full_text, bbox, group_type);
-                                // Add the group to the current group or top-level output
-                                if let Some((_, current_group)) = group_stack.last_mut() {
-                                    current_group.push(group_element);
-                                } else {
-                                    output.push(group_element);
-                                }
-                            }
+                FrameItem::Tag(Tag::End(location, _)) => {
+                    // Elements end in the order they started, but the start
+                    // may be on an earlier page.
+                    if let Some(index) = group_stack.iter().rposition(|group| group.location == Some(*location)) {
+                        while group_stack.len() > index {
+                            finalize_group(output, group_stack, options);
                         }
                     }
                 }
@@ -163,111 +1027,525 @@ fn words_in_frame(
         }
     }
 
+    // Pops the innermost group and adds it to its parent group or the output.
+    fn finalize_group(output: &mut Vec<Element>, group_stack: &mut Vec<OpenGroup>, options: TextOptions) {
+        let Some(OpenGroup { kind: group_type, elements, .. }) = group_stack.pop() else {
+            return;
+        };
+
+        if group_type == "equation" && !options.split_math {
+            if let Some(equation) = merge_equation(elements) {
+                match group_stack.last_mut() {
+                    Some(current_group) => current_group.elements.push(Element::Word(equation)),
+                    None => output.push(Element::Word(equation)),
+                }
+            }
+            return;
+        }
+
+        // Compute the group's string and bounding box
+        let mut full_text = String::new();
+        let mut overall_bbox = None;
+        let mut missing_glyphs = 0;
+        for element in &elements {
+            let (Element::Word(word) | Element::Group(word, _)) = element;
+            full_text.push_str(&word.text);
+            missing_glyphs += word.missing_glyphs;
+            overall_bbox = overall_bbox
+                .map(|bb| union_bbox(bb, word.bbox))
+                .or(Some(word.bbox));
+        }
+
+        if let Some(bbox) = overall_bbox {
+            let group = Word { missing_glyphs, ..Word::plain(full_text, bbox, &group_type) };
+            let group_element = Element::Group(group, elements);
+            // Add the group to the current group or top-level output
+            if let Some(current_group) = group_stack.last_mut() {
+                current_group.elements.push(group_element);
+            } else {
+                output.push(group_element);
+            }
+        }
+    }
+
     let mut output = Vec::new();
     let mut group_stack = Vec::new();
     traverse_frames(
         frame,
-        Point::zero(),
+        Transform::identity(),
         &mut output,
         &mut group_stack,
-        include_whitespace,
-        include_delimiters,
+        options,
     );
+    // Elements that continue on the next page end with this one.
+    while !group_stack.is_empty() {
+        finalize_group(&mut output, &mut group_stack, options);
+    }
+    output
+}
 
-    // Convert elements to (String, bbox, type) tuples
-    output.into_iter().map(|elem| {
-        match elem {
-            Element::Word(s, b) => (s,b), //(s, b, "word".to_string()),
-            Element::Group(s, b, t) => (s,b) //(s, b, t),
+/// Joins the words of an equation, in the order they were laid out, into one
+/// word of kind `equation`, or returns `None` if it has none. Like in the
+/// text of a line, words that aren't directly adjacent, like the sides of an
+/// `=` or the numerator and denominator of a fraction, are separated by a
+/// space. The equation has no font information, as it is made of several
+/// runs.
+fn merge_equation(elements: Vec<Element>) -> Option<Word> {
+    fn words(element: Element, output: &mut Vec<Word>) {
+        match element {
+            Element::Word(word) => output.push(word),
+            Element::Group(_, elements) => elements.into_iter().for_each(|element| words(element, output)),
         }
-    })
+    }
+
+    let mut parts = Vec::new();
+    elements.into_iter().for_each(|element| words(element, &mut parts));
+    let mut parts = parts.into_iter();
+    let first = parts.next()?;
+    let mut equation = Word {
+        missing_glyphs: first.missing_glyphs,
+        spans: first.spans.clone(),
+        ..Word::plain(first.text.clone(), first.bbox, "equation")
+    };
+    let mut last = first;
+    for part in parts {
+        let (x, _, w, _) = part.bbox;
+        let (last_x, _, last_w, last_h) = last.bbox;
+        let gap = (x - (last_x + last_w)).max(last_x - (x + w));
+        if gap > 0.1 * last_h {
+            equation.text.push(' ');
+        }
+        equation.text.push_str(&part.text);
+        equation.bbox = union_bbox(equation.bbox, part.bbox);
+        equation.missing_glyphs += part.missing_glyphs;
+        equation.spans.extend(part.spans.iter().cloned());
+        last = part;
+    }
+    Some(equation)
 }
 
 /// Processes a text item to extract words and their bounding boxes.
-/// This function splits words based on whitespace and punctuation.
+/// Where words are split depends on the split mode. With `chars`, the boxes
+/// of the characters of each word are extracted instead.
 fn process_text_item(
     pos: &Point, 
     text_item: &TextItem, 
-    words: &mut Vec<(String, (f64, f64, f64, f64))>,
-    include_whitespace: bool,
-    include_delimiters: bool
+    words: &mut Vec<Word>,
+    options: TextOptions,
+    chars: bool,
 ) {
-    let text = &text_item.text;
+    let text_item = split_straddling_glyphs(text_item, options);
+    let text_item = text_item.as_ref();
     let glyphs = &text_item.glyphs;
-    if glyphs.is_empty() {
-        return;
+    let size = text_item.size;
+
+    // The horizontal position of each glyph, relative to the TextItem's origin.
+    let glyph_x: Vec<Abs> = glyphs
+        .iter()
+        .scan(Abs::zero(), |x, glyph| {
+            let glyph_x = *x;
+            *x += glyph.x_advance.at(size);
+            Some(glyph_x)
+        })
+        .collect();
+
+    let first_word = words.len();
+    for range in word_ranges(text_item, options) {
+        let word_glyphs = &glyphs[range.clone()];
+        let word_start_x = glyph_x[range.start];
+        if chars {
+            finalize_chars(pos, text_item, word_glyphs, word_start_x, options, words);
+        } else {
+            finalize_word(pos, text_item, word_glyphs, word_start_x, options, words);
+        }
     }
 
-    let size = text_item.size;
-    let ascender = text_item.font.metrics().ascender.at(size).to_pt();
-    let descender = text_item.font.metrics().descender.at(size).to_pt();
-    let height = ascender - descender;
-
-    // Index of the first glyph of the current word.
-    let mut word_start_glyph_index = 0;
-    // Horizontal position where the current word starts, relative to the TextItem's origin.
-    let mut word_start_x = Abs::zero();
-    // The current horizontal position, advancing with each glyph.
-    let mut current_x = Abs::zero();
-
-    for (i, glyph) in glyphs.iter().enumerate() {
-        let start_byte = glyph.range.start as usize;
-        let end_byte = glyph.range.end as usize;
-        let glyph_text = &text[start_byte..end_byte];
-    
-        // A glyph is a delimiter if all its characters are whitespace or punctuation.
-        let is_delimiter = !glyph_text.is_empty() && glyph_text.chars().all(|c| c.is_whitespace() || c.is_ascii_punctuation());
+    if !chars && let Some(hyphen) = trailing_hyphen(text_item) {
+        // With delimiters included, a hyphen in the text is a word of its own
+        // after the word it belongs to.
+        let item_words = &mut words[first_word..];
+        let is_hyphen = |word: &Word| !hyphen.is_empty() && word.text == hyphen;
+        let skip = usize::from(item_words.last().is_some_and(is_hyphen));
+        if let Some(word) = item_words.iter_mut().rev().nth(skip) {
+            word.hyphen = Some(hyphen);
+        }
+    }
+}
+
+/// Returns the hyphen a text item ends with, as the text it stands for when
+/// the word before it is joined with the word after it: empty for the hyphen
+/// Typst adds at the end of a line when it hyphenates a word or breaks it at
+/// a soft hyphen, which has no text of its own, and the hyphen itself for one
+/// in the text.
+fn trailing_hyphen(text_item: &TextItem) -> Option<String> {
+    let glyphs = &text_item.glyphs;
+    let last = if is_rtl(glyphs) { glyphs.first() } else { glyphs.last() }?;
+    let added = last.range.is_empty()
+        && text_item.text[usize::from(last.range.start)..].chars().all(|c| c == SOFT_HYPHEN)
+        && text_item.font.ttf().glyph_index('-').is_some_and(|id| id.0 == last.id);
+    if added {
+        return Some(String::new());
+    }
+    let text = &text_item.text[last.range()];
+    matches!(text, "-" | "\u{2010}").then(|| text.to_string())
+}
+
+/// A hyphen that is only shown where a line is broken.
+const SOFT_HYPHEN: char = '\u{ad}';
+
+/// Splits each glyph whose cluster the split mode would split into several
+/// words (e.g. a ligature of a digit, a slash and another digit) into a
+/// glyph for each part, sharing its advance in proportion to their
+/// characters, so that each word gets its own part of the glyph's box and
+/// words are only split at the clusters of glyphs.
+fn split_straddling_glyphs<'t>(text_item: &'t TextItem, options: TextOptions) -> Cow<'t, TextItem> {
+    let bounds = split_points(&text_item.text, options);
+    let cuts = |glyph: &Glyph| {
+        let range = glyph.range();
+        bounds.iter().copied().filter(move |&bound| range.start < bound && bound < range.end)
+    };
+    if text_item.glyphs.iter().all(|glyph| cuts(glyph).next().is_none()) {
+        return Cow::Borrowed(text_item);
+    }
+
+    let rtl = is_rtl(&text_item.glyphs);
+    let mut glyphs = Vec::with_capacity(text_item.glyphs.len());
+    for glyph in &text_item.glyphs {
+        let range = glyph.range();
+        let mut edges = vec![range.start];
+        edges.extend(cuts(glyph));
+        edges.push(range.end);
+        let total = text_item.text[range.clone()].chars().count() as f64;
+        let part = |start: usize, end: usize| -> Option<Glyph> {
+            let offset = u16::try_from(start - range.start).ok()?;
+            Some(Glyph {
+                x_advance: glyph.x_advance * (text_item.text[start..end].chars().count() as f64 / total),
+                range: u16::try_from(start).ok()?..u16::try_from(end).ok()?,
+                span: (glyph.span.0, glyph.span.1.checked_add(offset)?),
+                ..glyph.clone()
+            })
+        };
+        // A glyph whose parts are at offsets beyond `u16` is left whole.
+        let Some(mut parts) = edges.windows(2).map(|edge| part(edge[0], edge[1])).collect::<Option<Vec<_>>>() else {
+            glyphs.push(glyph.clone());
+            continue;
+        };
+        // The parts are laid out in the order of the glyphs.
+        if rtl {
+            parts.reverse();
+        }
+        glyphs.extend(parts);
+    }
+    Cow::Owned(TextItem { glyphs, ..text_item.clone() })
+}
+
+/// Returns the byte offsets in a text where the split mode may split it into
+/// words: the Unicode word boundaries, or else where the text changes from
+/// delimiters to the characters of words or back, and around each CJK
+/// character in the CJK mode.
+fn split_points(text: &str, options: TextOptions) -> Vec<usize> {
+    if options.split_mode == SplitMode::Unicode {
+        return text.split_word_bound_indices().map(|(offset, _)| offset).collect();
+    }
+    // Whether a character is a delimiter, and whether it is a word of its own.
+    let class = |c: char| match options.split_mode {
+        SplitMode::Whitespace => (c.is_whitespace(), false),
+        SplitMode::CjkChars => (is_delimiter_char(c, options) || is_cjk_punctuation(c), is_cjk(c)),
+        _ => (is_delimiter_char(c, options), false),
+    };
+    let mut points = Vec::new();
+    let mut previous: Option<(bool, bool)> = None;
+    for (offset, c) in text.char_indices() {
+        let current = class(c);
+        if previous.is_some_and(|previous| previous != current || current.1) {
+            points.push(offset);
+        }
+        previous = Some(current);
+    }
+    points
+}
+
+/// Returns the ranges of the glyphs of a text item that make up its words,
+/// and its whitespace and delimiters as far as the options include them, in
+/// the order of the text.
+fn word_ranges(text_item: &TextItem, options: TextOptions) -> Vec<Range<usize>> {
+    let text = &text_item.text;
+    let glyphs = &text_item.glyphs;
+    let mut ranges = Vec::new();
+
+    // Glyphs are laid out from left to right, so for right-to-left text (like
+    // Arabic or Hebrew) they are in the reverse of the logical order of the
+    // text. Words are found in logical order, so that their text and their
+    // order is that of the text, while their boxes are where the glyphs are.
+    let rtl = is_rtl(glyphs);
+    let count = glyphs.len();
+    let visual = |i: usize| if rtl { count - 1 - i } else { i };
+
+    let segments = glyph_segments(text, glyphs, options);
+    // Adds the word made of the glyphs in the given logical range.
+    let mut finalize = |range: Range<usize>| {
+        ranges.push(if rtl { count - range.end..count - range.start } else { range });
+    };
+
+    // Logical index of the first glyph of the current word.
+    let mut word_start = 0;
+
+    for i in 0..count {
+        let glyph = &glyphs[visual(i)];
+        let glyph_text = &text[glyph.range()];
+
+        let (segment, is_delimiter) = segments[visual(i)];
         let is_whitespace = !glyph_text.is_empty() && glyph_text.chars().all(|c| c.is_whitespace());
 
+        // Adjacent words of different segments are split without a delimiter
+        // in between, e.g. two ideographs.
+        if !is_delimiter && word_start < i && segments[visual(i - 1)].0 != segment {
+            finalize(word_start..i);
+            word_start = i;
+        }
+
         if is_delimiter {
             // If we have a pending word, finalize it.
-            if word_start_glyph_index < i {
-                let word_glyphs = &glyphs[word_start_glyph_index..i];
-                finalize_word(pos, text, word_glyphs, word_start_x, ascender, height, size, words);
+            if word_start < i {
+                finalize(word_start..i);
             }
             // Finalize the delimiter or whitespace itself.
-            if (!is_whitespace || include_whitespace) && (is_whitespace || include_delimiters) {
-                finalize_word(pos, text, &[glyph.clone()], current_x, ascender, height, size, words);
+            if (!is_whitespace || options.include_whitespace) && (is_whitespace || options.include_delimiters) {
+                finalize(i..i + 1);
             }
             // The next word will start after this delimiter glyph.
-            word_start_glyph_index = i + 1;
+            word_start = i + 1;
         }
+    }
 
-        // Advance the cursor by the width of the current glyph.
-        current_x += glyph.x_advance.at(size);
+    // Finalize any trailing word at the end of the text item.
+    if word_start < count {
+        finalize(word_start..count);
+    }
+    ranges
+}
 
-        if is_delimiter {
-            // The next word will start at the new cursor position.
-            word_start_x = current_x;
+/// Whether the glyphs of a text item are set from right to left, i.e. their
+/// clusters appear in the text in the reverse of their visual order.
+fn is_rtl(glyphs: &[Glyph]) -> bool {
+    match (glyphs.first(), glyphs.last()) {
+        (Some(first), Some(last)) => first.range.start > last.range.start,
+        _ => false,
+    }
+}
+
+/// Returns the items of a frame, with the text items that Typst split a run
+/// of text into where its glyphs are moved up or down (e.g. for some points
+/// of Hebrew) joined again, so that the words across them are not split.
+fn joined_items<'f>(frame: &'f Frame, options: TextOptions) -> Vec<(Point, Cow<'f, FrameItem>)> {
+    let mut items: Vec<(Point, Cow<'_, FrameItem>)> = Vec::new();
+    for (pos, item) in frame.items() {
+        if let FrameItem::Text(next) = item
+            && let Some((previous_pos, previous_item)) = items.last_mut()
+            && let FrameItem::Text(previous) = previous_item.as_ref()
+            && continues_word((*previous_pos, previous), (*pos, next), options)
+            && let Some(joined) = join_text_items(previous, next)
+        {
+            *previous_item = Cow::Owned(FrameItem::Text(joined));
+            continue;
         }
+        items.push((*pos, Cow::Borrowed(item)));
     }
+    items
+}
 
-    // Finalize any trailing word at the end of the text item.
-    if word_start_glyph_index < glyphs.len() {
-        let word_glyphs = &glyphs[word_start_glyph_index..];
-        finalize_word(pos, text, word_glyphs, word_start_x, ascender, height, size, words);
+/// Whether a text item continues the word at the end of the text item laid
+/// out right before it, as another part of the same run. Text from different
+/// places in the source, like that of adjacent cells of a grid, is not
+/// joined.
+fn continues_word(
+    (previous_pos, previous): (Point, &TextItem),
+    (pos, next): (Point, &TextItem),
+    options: TextOptions,
+) -> bool {
+    let rtl = item_is_rtl(previous);
+    let same_style = previous.font == next.font
+        && previous.size == next.size
+        && previous.fill == next.fill
+        && previous.lang == next.lang
+        && rtl == item_is_rtl(next);
+    // The pen continues where the previous item ended.
+    let adjacent = ((previous_pos.x + previous.width()) - pos.x).to_pt().abs() < 1e-3
+        && (previous_pos.y - pos.y).abs() < next.size / 2.0;
+    // The characters of the visually adjacent glyphs, which are the logical
+    // start of the previous item and the logical end of the next one in
+    // right-to-left text.
+    let first_char = |item: &TextItem, glyph: Option<&Glyph>| {
+        glyph.and_then(|glyph| item.text.get(glyph.range())).and_then(|text| text.chars().next())
+    };
+    let (left, right) = (first_char(previous, previous.glyphs.last()), first_char(next, next.glyphs.first()));
+    let is_word_char = |c: char| match options.split_mode {
+        SplitMode::Whitespace => !c.is_whitespace(),
+        SplitMode::Punctuation => !is_delimiter_char(c, options),
+        SplitMode::Unicode => c.is_alphanumeric(),
+        // Characters of different CJK words are never joined.
+        SplitMode::CjkChars => !is_delimiter_char(c, options) && !is_cjk_punctuation(c) && !is_cjk(c),
+    };
+    let same_span = match (previous.glyphs.last(), next.glyphs.first()) {
+        (Some(last), Some(first)) => last.span.0 == first.span.0,
+        _ => true,
+    };
+    // A glyph without text is part of a cluster whose text is in the other
+    // item, like a Hebrew letter whose points are moved up or down.
+    let within_word = match (left, right) {
+        (Some(left), Some(right)) => is_word_char(left) && is_word_char(right),
+        _ => true,
+    };
+    same_style && adjacent && same_span && within_word
+}
+
+/// Whether a text item is set from right to left. A single cluster doesn't
+/// tell by the order of its glyphs, so then its first strong character does.
+fn item_is_rtl(text_item: &TextItem) -> bool {
+    let glyphs = &text_item.glyphs;
+    match (glyphs.first(), glyphs.last()) {
+        (Some(first), Some(last)) if first.range.start != last.range.start => is_rtl(glyphs),
+        _ => text_item
+            .text
+            .chars()
+            .map(bidi_class)
+            .find(|class| matches!(class, BidiClass::L | BidiClass::R | BidiClass::AL))
+            .is_some_and(|class| class != BidiClass::L),
+    }
+}
+
+/// Joins a text item with the one laid out right after it, keeping their
+/// text in logical order and their glyphs in visual order. Returns `None` if
+/// the joined text is too long for the byte ranges of glyphs, which are
+/// `u16`.
+fn join_text_items(previous: &TextItem, next: &TextItem) -> Option<TextItem> {
+    fn shifted(glyphs: &[Glyph], by: u16) -> impl Iterator<Item = Glyph> + '_ {
+        glyphs.iter().map(move |glyph| Glyph { range: glyph.range.start + by..glyph.range.end + by, ..glyph.clone() })
+    }
+    // The ranges of the glyphs of each item are within its text, so they
+    // can't overflow if all of the text fits.
+    u16::try_from(previous.text.len() + next.text.len()).ok()?;
+    let (text, glyphs) = if item_is_rtl(previous) {
+        let by = u16::try_from(next.text.len()).ok()?;
+        let glyphs = shifted(&previous.glyphs, by).chain(shifted(&next.glyphs, 0)).collect();
+        (next.text.clone() + previous.text.as_str(), glyphs)
+    } else {
+        let by = u16::try_from(previous.text.len()).ok()?;
+        let glyphs = shifted(&previous.glyphs, 0).chain(shifted(&next.glyphs, by)).collect();
+        (previous.text.clone() + next.text.as_str(), glyphs)
+    };
+    Some(TextItem { text, glyphs, ..previous.clone() })
+}
+
+/// Returns the byte range in the text of their item that glyphs were shaped
+/// from, in either direction.
+fn text_range(glyphs: &[Glyph]) -> Range<usize> {
+    let start = glyphs.iter().map(|glyph| glyph.range.start).min().unwrap_or(0);
+    let end = glyphs.iter().map(|glyph| glyph.range.end).max().unwrap_or(0);
+    start as usize..end as usize
+}
+
+/// Determines for each glyph the word segment it belongs to and whether it
+/// is a delimiter between words.
+///
+/// In the whitespace and punctuation modes, words are only split at
+/// delimiters, so all glyphs are in the same segment. In the Unicode mode,
+/// segments are the word boundaries of UAX #29 in the text of the item, which
+/// are mapped to glyphs through the byte ranges of their clusters. A segment
+/// without letters or digits (like spaces, em dashes or CJK punctuation) is a
+/// delimiter, and text without spaces (like Chinese) is split into its words.
+/// In the CJK mode, each glyph of a CJK character is a segment of its own,
+/// and full-width punctuation is a delimiter like ASCII punctuation.
+fn glyph_segments(text: &str, glyphs: &[Glyph], options: TextOptions) -> Vec<(usize, bool)> {
+    let glyph_text = |glyph: &Glyph| &text[glyph.range.start as usize..glyph.range.end as usize];
+    match options.split_mode {
+        SplitMode::Whitespace => glyphs
+            .iter()
+            .map(|glyph| {
+                let glyph_text = glyph_text(glyph);
+                (0, !glyph_text.is_empty() && glyph_text.chars().all(char::is_whitespace))
+            })
+            .collect(),
+        SplitMode::Punctuation => glyphs
+            .iter()
+            .map(|glyph| {
+                // A glyph is a delimiter if all its characters are whitespace or punctuation.
+                let glyph_text = glyph_text(glyph);
+                let is_delimiter = !glyph_text.is_empty() && glyph_text.chars().all(|c| is_delimiter_char(c, options));
+                (0, is_delimiter)
+            })
+            .collect(),
+        SplitMode::CjkChars => glyphs
+            .iter()
+            .enumerate()
+            .map(|(i, glyph)| {
+                let glyph_text = glyph_text(glyph);
+                let is_delimiter = !glyph_text.is_empty()
+                    && glyph_text.chars().all(|c| is_delimiter_char(c, options) || is_cjk_punctuation(c));
+                // Other glyphs share segment 0, so that only delimiters split them.
+                let segment = if glyph_text.starts_with(is_cjk) { i + 1 } else { 0 };
+                (segment, is_delimiter)
+            })
+            .collect(),
+        SplitMode::Unicode => {
+            // Each segment as its byte range and whether it is a delimiter.
+            let bounds: Vec<(Range<usize>, bool)> = text
+                .split_word_bound_indices()
+                .map(|(offset, segment)| {
+                    let is_delimiter = !segment.chars().any(char::is_alphanumeric);
+                    (offset..offset + segment.len(), is_delimiter)
+                })
+                .collect();
+            glyphs
+                .iter()
+                .map(|glyph| {
+                    // A glyph belongs to the segment its cluster starts in. A
+                    // cluster (e.g. a ligature) can span several segments, and
+                    // is then only a delimiter if all of them are.
+                    let range = glyph.range();
+                    let first = bounds.partition_point(|(segment, _)| segment.end <= range.start);
+                    let is_delimiter = bounds[first..]
+                        .iter()
+                        .take_while(|(segment, _)| segment.start < range.end.max(range.start + 1))
+                        .all(|&(_, is_delimiter)| is_delimiter);
+                    (first, is_delimiter)
+                })
+                .collect()
+        }
     }
 }
 
 /// Helper to construct the word string and bounding box and add it to the list.
+///
+/// The box spans from the height given by the box mode (by default the
+/// font's ascender) down to the font's descender, or with tight boxes only
+/// the outlines of the word's glyphs. Looking up the outline bounds of each
+/// glyph makes extraction noticeably slower for large documents.
 fn finalize_word(
     pos: &Point,
-    text: &str,
+    text_item: &TextItem,
     word_glyphs: &[Glyph],
     word_start_x: Abs,
-    ascender: f64,
-    height: f64,
-    font_size: Abs,
-    words: &mut Vec<(String, (f64, f64, f64, f64))>,
+    options: TextOptions,
+    words: &mut Vec<Word>,
 ) {
     if word_glyphs.is_empty() {
         return;
     }
-    
-    // Determine the text of the word from the glyph ranges.
-    let start_byte = word_glyphs.first().unwrap().range.start as usize;
-    let end_byte = word_glyphs.last().unwrap().range.end as usize;
-    let word_text = &text[start_byte..end_byte];
+
+    let font_size = text_item.size;
+    let metrics = text_item.font.metrics();
+    let ascender = metrics.ascender.at(font_size).to_pt();
+    let descender = metrics.descender.at(font_size).to_pt();
+    let top = match options.box_mode {
+        BoxMode::Ascent => ascender,
+        BoxMode::CapHeight => metrics.cap_height.at(font_size).to_pt(),
+        BoxMode::XHeight => metrics.x_height.at(font_size).to_pt(),
+    };
+
+    // Determine the text of the word from the glyph ranges. For right-to-left
+    // text, the first glyph is the last in the text.
+    let word_text = &text_item.text[text_range(word_glyphs)];
 
     // The width of the word is the sum of the advances of its glyphs.
     let width: Abs = word_glyphs.iter().map(|g| g.x_advance.at(font_size)).sum();
@@ -275,11 +1553,278 @@ fn finalize_word(
     // The glyph's x_offset is a slight adjustment to its position.
     // We only need the one from the first glyph.
     let x_offset = word_glyphs.first().unwrap().x_offset.at(font_size);
+    // Spacing that Typst adds before a glyph (like that between Chinese and
+    // Latin text) is in both its offset and its advance, so the box still
+    // ends where the advance does.
+    let width = width - x_offset;
 
     // Calculate the final bounding box coordinates.
     let x = pos.x.to_pt() + word_start_x.to_pt() + x_offset.to_pt();
-    let y = pos.y.to_pt() - ascender;
+    let (y, height) = match options.tight_boxes.then(|| outline_extent(text_item, word_glyphs)).flatten() {
+        Some((top, bottom)) => (pos.y.to_pt() - top, top - bottom),
+        None => (pos.y.to_pt() - top, top - descender),
+    };
 
     // The splitting logic is now precise, so no .trim() is needed.
-    words.push((word_text.to_string(), (x, y, width.to_pt(), height)));
-}
\ No newline at end of file
+    words.push(Word {
+        text: word_text.to_string(),
+        bbox: (x, y, width.to_pt(), height),
+        font: Some(text_item.font.info().family.clone()),
+        subfamily: subfamily_name(&text_item.font),
+        variant: Some(text_item.font.info().variant),
+        font_size: Some(font_size.to_pt()),
+        mixed_sizes: false,
+        color: Some(paint_to_string(&text_item.fill)),
+        rotation: None,
+        quad: None,
+        kind: "word".to_string(),
+        link: None,
+        // Glyph 0 is `.notdef`.
+        missing_glyphs: word_glyphs.iter().filter(|glyph| glyph.id == 0).count(),
+        score: Some(glyph_score(text_item, word_glyphs, word_text)),
+        baseline: Some(pos.y.to_pt()),
+        metrics: Some((ascender, descender)),
+        word_index: None,
+        lang: Some(match text_item.region {
+            Some(region) => format!("{}-{}", text_item.lang.as_str(), region.as_str()),
+            None => text_item.lang.as_str().to_string(),
+        }),
+        script: script_name(word_text),
+        dir: Some(if item_is_rtl(text_item) { Dir::RTL } else { Dir::LTR }),
+        shaping: None,
+        hyphen: None,
+        spans: glyph_spans(word_glyphs),
+    });
+}
+
+/// Estimates how reliable the box of a word is from its glyphs, from 0 to 1.
+///
+/// The score is the product of three factors, each 1 for ordinary text:
+/// - the share of glyphs that are not `.notdef`, since the box of a missing
+///   glyph has the width of the font's placeholder, not of the text;
+/// - one minus half the share of glyphs without an advance that are not
+///   combining marks, since their width is not part of the box;
+/// - one minus half the share of characters that are not letters or digits,
+///   since a box of mostly punctuation or symbols is rarely a word.
+///
+/// Fonts that Typst falls back to for characters the requested fonts lack
+/// are not visible in the laid out text, so they don't lower the score.
+fn glyph_score(text_item: &TextItem, glyphs: &[Glyph], text: &str) -> f64 {
+    let share = |count: usize, total: usize| if total == 0 { 0.0 } else { count as f64 / total as f64 };
+    let missing = glyphs.iter().filter(|glyph| glyph.id == 0).count();
+    let zero_advance = glyphs
+        .iter()
+        .filter(|glyph| glyph.x_advance.get() == 0.0)
+        .filter(|glyph| !text_item.text[glyph.range()].chars().all(|c| bidi_class(c) == BidiClass::NSM))
+        .count();
+    let chars: Vec<char> = text.chars().filter(|&c| bidi_class(c) != BidiClass::NSM).collect();
+    let symbols = chars.iter().filter(|c| !c.is_alphanumeric()).count();
+    (1.0 - share(missing, glyphs.len()))
+        * (1.0 - share(zero_advance, glyphs.len()) / 2.0)
+        * (1.0 - share(symbols, chars.len()) / 2.0)
+}
+
+/// Returns the spans the glyphs were made from, each with the byte range of
+/// the clusters of the glyphs within it.
+fn glyph_spans(glyphs: &[Glyph]) -> Vec<(Span, Range<usize>)> {
+    let mut spans: Vec<(Span, Range<usize>)> = Vec::new();
+    for glyph in glyphs {
+        let (span, offset) = glyph.span;
+        let range = usize::from(offset)..usize::from(offset) + glyph.range().len();
+        match spans.iter_mut().find(|(other, _)| *other == span) {
+            Some((_, covered)) => *covered = covered.start.min(range.start)..covered.end.max(range.end),
+            None => spans.push((span, range)),
+        }
+    }
+    spans
+}
+
+/// Whether a character is whitespace or punctuation, which separate words in
+/// the punctuation mode. Punctuation is only ASCII punctuation, unless the
+/// options ask for that of all of Unicode (the general categories `P*`).
+fn is_delimiter_char(c: char, options: TextOptions) -> bool {
+    c.is_whitespace()
+        || c.is_ascii_punctuation()
+        || (options.unicode_delimiters && c.general_category_group() == GeneralCategoryGroup::Punctuation)
+        || options.delimiters.contains(c)
+}
+
+/// Whether a character is Chinese, Japanese or Korean, including the long
+/// vowel mark of Katakana.
+fn is_cjk(c: char) -> bool {
+    matches!(c.script(), Script::Han | Script::Hiragana | Script::Katakana | Script::Hangul | Script::Bopomofo)
+        || c == 'ー'
+}
+
+/// Whether a character is CJK punctuation, like `、`, `。`, `「` or the
+/// full-width forms of ASCII punctuation.
+fn is_cjk_punctuation(c: char) -> bool {
+    !c.is_alphanumeric()
+        && matches!(c, '\u{3000}'..='\u{303f}' | '\u{30fb}' | '\u{fe30}'..='\u{fe4f}' | '\u{ff00}'..='\u{ff65}')
+}
+
+/// Returns the name of the script most characters of a text are in, not
+/// counting characters common to several scripts (or inherited from the
+/// previous character) unless there are no others. Ties go to the script
+/// that comes first.
+fn script_name(text: &str) -> Option<String> {
+    let mut counts: Vec<(Script, usize)> = Vec::new();
+    for c in text.chars() {
+        let script = c.script();
+        match counts.iter_mut().find(|(counted, _)| *counted == script) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((script, 1)),
+        }
+    }
+    let is_specific = |script: &Script| !matches!(script, Script::Common | Script::Inherited | Script::Unknown);
+    // Reversed, as the last of equal counts is the maximum.
+    let most = |specific_only: bool| {
+        counts
+            .iter()
+            .rev()
+            .filter(|(script, _)| !specific_only || is_specific(script))
+            .max_by_key(|(_, count)| *count)
+    };
+    most(true).or_else(|| most(false)).map(|(script, _)| script.full_name().to_string())
+}
+
+/// Returns the subfamily name of a font, e.g. `Bold Italic`, preferring the
+/// typographic subfamily name over the legacy one, which only distinguishes
+/// the four styles of a family.
+fn subfamily_name(font: &Font) -> Option<String> {
+    let names = font.ttf().names();
+    [name_id::TYPOGRAPHIC_SUBFAMILY, name_id::SUBFAMILY].into_iter().find_map(|id| {
+        names.into_iter().filter(|name| name.name_id == id).find_map(|name| name.to_string())
+    })
+}
+
+/// Like [`finalize_word`], but adds a box for each character (grapheme
+/// cluster) of the word, with the index of the word after that of the
+/// previous characters in the list.
+///
+/// A character spans the advances of the glyphs shaped from it. A glyph
+/// shaped from several characters, like the ligature `ﬁ`, is split evenly
+/// among them. Glyphs of combining marks belong to the cluster of their base
+/// character and so stay part of its box.
+fn finalize_chars(
+    pos: &Point,
+    text_item: &TextItem,
+    word_glyphs: &[Glyph],
+    word_start_x: Abs,
+    options: TextOptions,
+    words: &mut Vec<Word>,
+) {
+    if word_glyphs.is_empty() {
+        return;
+    }
+    let word_index = words.last().and_then(|word| word.word_index).map_or(0, |index| index + 1);
+    let size = text_item.size;
+
+    // The characters of the word in logical order, with their byte ranges in
+    // the text item and the glyphs and horizontal extent covering them.
+    let word_range = text_range(word_glyphs);
+    let mut chars: Vec<_> = text_item.text[word_range.clone()]
+        .grapheme_indices(true)
+        .map(|(offset, grapheme)| {
+            let start = word_range.start + offset;
+            (start..start + grapheme.len(), Vec::<Glyph>::new(), None::<(Abs, Abs)>)
+        })
+        .collect();
+
+    // Consecutive glyphs of the same cluster, like a base and its marks,
+    // share the advances of all of them.
+    let mut x = word_start_x;
+    for cluster in word_glyphs.chunk_by(|a, b| a.range == b.range) {
+        let start = x + cluster[0].x_offset.at(size);
+        let width: Abs = cluster.iter().map(|glyph| glyph.x_advance.at(size)).sum();
+        x += width;
+
+        let range = cluster[0].range();
+        let covered: Vec<usize> = (0..chars.len())
+            .filter(|&index| chars[index].0.start < range.end && range.start < chars[index].0.end)
+            .collect();
+        let share = width / covered.len().max(1) as f64;
+        for (nth, &index) in covered.iter().enumerate() {
+            let (from, to) = (start + share * nth as f64, start + share * (nth + 1) as f64);
+            let (_, glyphs, extent) = &mut chars[index];
+            glyphs.extend_from_slice(cluster);
+            *extent = Some(extent.map_or((from, to), |(left, right)| (left.min(from), right.max(to))));
+        }
+    }
+
+    for (range, glyphs, extent) in chars {
+        let Some((left, right)) = extent else {
+            continue;
+        };
+        let mut char_words = Vec::new();
+        finalize_word(pos, text_item, &glyphs, left, options, &mut char_words);
+        let Some(mut char) = char_words.pop() else {
+            continue;
+        };
+        char.text = text_item.text[range].to_string();
+        char.script = script_name(&char.text);
+        char.bbox.0 = pos.x.to_pt() + left.to_pt();
+        char.bbox.2 = (right - left).to_pt();
+        char.word_index = Some(word_index);
+        words.push(char);
+    }
+}
+
+/// Returns how far the outlines of the glyphs reach above and below the
+/// baseline (negative if below), or `None` if none of them has an outline,
+/// e.g. for spaces.
+fn outline_extent(text_item: &TextItem, glyphs: &[Glyph]) -> Option<(f64, f64)> {
+    let font = &text_item.font;
+    let at = |units: i16| font.to_em(units).at(text_item.size).to_pt();
+    glyphs
+        .iter()
+        .filter_map(|glyph| {
+            let bbox = font.ttf().glyph_bounding_box(ttf_parser::GlyphId(glyph.id))?;
+            Some((at(bbox.y_max), at(bbox.y_min)))
+        })
+        .reduce(|(top, bottom), (glyph_top, glyph_bottom)| (top.max(glyph_top), bottom.min(glyph_bottom)))
+}
+
+/// Maps a word's box from the coordinates of its text item to the page.
+///
+/// If the transform does more than translate, the box becomes the axis-aligned
+/// bounding box of the transformed rectangle, whose corners are kept as `quad`.
+pub(crate) fn transform_word(mut word: Word, ts: Transform) -> Word {
+    let (x, y, w, h) = word.bbox;
+    let is_translation = ts.sx.is_one() && ts.sy.is_one() && ts.kx.is_zero() && ts.ky.is_zero();
+    if is_translation {
+        word.bbox = (x + ts.tx.to_pt(), y + ts.ty.to_pt(), w, h);
+        word.baseline = word.baseline.map(|baseline| baseline + ts.ty.to_pt());
+        return word;
+    }
+
+    word.baseline = word.baseline.map(|baseline| {
+        Point::new(Abs::pt(x), Abs::pt(baseline)).transform(ts).y.to_pt()
+    });
+
+    let quad = [(x, y), (x + w, y), (x + w, y + h), (x, y + h)].map(|(cx, cy)| {
+        let corner = Point::new(Abs::pt(cx), Abs::pt(cy)).transform(ts);
+        (corner.x.to_pt(), corner.y.to_pt())
+    });
+    let left = quad.iter().map(|c| c.0).fold(f64::INFINITY, f64::min);
+    let top = quad.iter().map(|c| c.1).fold(f64::INFINITY, f64::min);
+    let right = quad.iter().map(|c| c.0).fold(f64::NEG_INFINITY, f64::max);
+    let bottom = quad.iter().map(|c| c.1).fold(f64::NEG_INFINITY, f64::max);
+    word.bbox = (left, top, right - left, bottom - top);
+    word.quad = Some(quad);
+
+    // The angle of the transformed baseline.
+    let rotation = ts.ky.get().atan2(ts.sx.get()).to_degrees();
+    if rotation.abs() > 1e-9 {
+        word.rotation = Some(rotation);
+    }
+    word
+}
+
+/// Describes a paint as a hex color, or as `non-solid` if it is not a plain color.
+fn paint_to_string(paint: &Paint) -> String {
+    match paint {
+        Paint::Solid(color) => color.to_hex().to_string(),
+        Paint::Gradient(_) | Paint::Tiling(_) => "non-solid".to_string(),
+    }
+}
@@ -1,11 +1,12 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 use typst::diag::{eco_format, FileError, FileResult, PackageError, PackageResult};
-use typst::foundations::{Bytes, Datetime};
+use typst::foundations::{Bytes, Datetime, Dict, Value};
 use typst::syntax::package::PackageSpec;
-use typst::syntax::{FileId, Source};
+use typst::syntax::{FileId, Source, VirtualPath};
 use typst::text::{Font, FontBook};
 use typst::utils::LazyHash;
 use typst::Library;
@@ -17,65 +18,361 @@ pub struct TypstWrapperWorld {
     root: PathBuf,
     /// The content of a source.
     source: Source,
+    /// The id of a separate file compiled before the main source, and the
+    /// code in it before it includes the main source. Set by
+    /// [`TypstWrapperWorld::with_prepended`].
+    prelude: Option<(FileId, String)>,
     /// The standard library.
-    library: LazyHash<Library>,
+    library: Arc<LazyHash<Library>>,
     /// Metadata about all known fonts.
-    book: LazyHash<FontBook>,
+    book: Arc<LazyHash<FontBook>>,
     /// Slots for all known fonts.
-    fonts: Vec<FontSlot>,
+    fonts: Arc<Vec<FontSlot>>,
     /// Map of all known files.
     files: Arc<Mutex<HashMap<FileId, FileEntry>>>,
     /// Cache directory (e.g. where packages are downloaded to).
     cache_directory: PathBuf,
+    /// Whether packages missing from the cache directory may be downloaded.
+    offline: bool,
+    /// Whether files that aren't in memory may be read from the root.
+    disk_access: bool,
+    /// Directories outside the root that files may be read from through
+    /// symlinks in the root, canonicalized.
+    allowed_paths: Vec<PathBuf>,
     /// http agent to download packages.
     http: ureq::Agent,
-    /// Datetime.
+    /// The current time, fixed for the whole compilation so that repeated
+    /// calls to `datetime.today()` agree. Its offset is the local timezone.
     time: time::OffsetDateTime,
+    /// The time given to [`Self::with_now`], which [`Self::reset`] keeps
+    /// instead of taking the current time again.
+    pinned_time: Option<time::OffsetDateTime>,
 }
 
-impl TypstWrapperWorld {
-    pub fn new(root: String, source: String) -> Self {
-        let root = PathBuf::from(root);
-        let fonts = FontSearcher::new().include_system_fonts(true).search();
+/// The parts of a world that are expensive to set up and can be shared by
+/// the worlds of many documents: the fonts and the standard library.
+///
+/// When compiling many documents, build the worlds from one `WorldBuilder` so
+/// that fonts are searched and loaded only once.
+#[derive(Clone)]
+pub struct WorldBuilder {
+    /// The standard library.
+    library: Arc<LazyHash<Library>>,
+    /// Metadata about all known fonts.
+    book: Arc<LazyHash<FontBook>>,
+    /// Slots for all known fonts. A font is loaded on first use and then
+    /// kept in its slot.
+    fonts: Arc<Vec<FontSlot>>,
+    /// http agent to download packages.
+    http: ureq::Agent,
+}
+
+impl WorldBuilder {
+    /// Searches for fonts in `font_paths` (recursively), falling back to the
+    /// fonts embedded in Typst. System fonts are not used, so that documents
+    /// lay out the same on every machine.
+    pub fn new(font_paths: &[PathBuf]) -> Self {
+        Self::with_fonts(font_paths, false)
+    }
+
+    /// Like [`Self::new`], but also uses the fonts installed on the system.
+    ///
+    /// When several fonts have the same family and variant, fonts from
+    /// `font_paths` take precedence over system fonts, which in turn take
+    /// precedence over the embedded fonts.
+    pub fn with_system_fonts(font_paths: &[PathBuf]) -> Self {
+        Self::with_fonts(font_paths, true)
+    }
+
+    fn with_fonts(font_paths: &[PathBuf], include_system_fonts: bool) -> Self {
+        // Only the metadata of each font is read here. Fonts are loaded on
+        // first use, so that searching many system fonts stays fast.
+        let fonts = FontSearcher::new()
+            .include_system_fonts(include_system_fonts)
+            .search_with(font_paths);
+
+        // The embedded fonts are added after those on disk, so a copy of an
+        // embedded font in a font directory would be listed twice. Only the
+        // copy on disk is kept.
+        let on_disk: Vec<bool> = fonts.fonts.iter().map(|slot| slot.path().is_some()).collect();
+        let mut book = FontBook::new();
+        let mut slots = Vec::with_capacity(fonts.fonts.len());
+        for (index, slot) in fonts.fonts.into_iter().enumerate() {
+            let info = fonts.book.info(index).expect("each font slot has its info");
+            if !on_disk[index] && (0..index).any(|other| on_disk[other] && fonts.book.info(other) == Some(info)) {
+                continue;
+            }
+            book.push(info.clone());
+            slots.push(slot);
+        }
 
         Self {
-            library: LazyHash::new(Library::default()),
-            book: LazyHash::new(fonts.book),
+            library: Arc::new(LazyHash::new(Library::default())),
+            book: Arc::new(LazyHash::new(book)),
+            fonts: Arc::new(slots),
+            http: ureq::Agent::new(),
+        }
+    }
+
+    /// Makes the given key-value pairs available to all documents as
+    /// `sys.inputs`, just like `typst compile --input`.
+    pub fn with_inputs(mut self, inputs: impl IntoIterator<Item = (String, String)>) -> Self {
+        self.library = Arc::new(library_with_inputs(inputs));
+        self
+    }
+
+    /// Creates a world for the given source, with files resolved against `root`.
+    pub fn build(&self, root: String, source: String) -> TypstWrapperWorld {
+        // An empty root (e.g. the parent of a bare file name) is the current
+        // directory. Making it absolute ensures `..` can't escape it.
+        let root = if root.is_empty() { PathBuf::from(".") } else { PathBuf::from(root) };
+        let root = root.canonicalize().unwrap_or(root);
+
+        TypstWrapperWorld {
+            library: Arc::clone(&self.library),
+            book: Arc::clone(&self.book),
             root,
-            fonts: fonts.fonts,
+            fonts: Arc::clone(&self.fonts),
             source: Source::detached(source),
-            time: time::OffsetDateTime::now_utc(),
-            cache_directory: std::env::var_os("CACHE_DIRECTORY")
-                .map(|os_path| os_path.into())
-                .unwrap_or(std::env::temp_dir()),
-            http: ureq::Agent::new(),
+            prelude: None,
+            time: now_local(),
+            pinned_time: None,
+            cache_directory: package_cache_directory(),
+            offline: false,
+            disk_access: true,
+            allowed_paths: Vec::new(),
+            http: self.http.clone(),
             files: Arc::new(Mutex::new(HashMap::new())),
         }
     }
+}
+
+impl std::fmt::Debug for WorldBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WorldBuilder").field("fonts", &self.fonts.len()).finish_non_exhaustive()
+    }
+}
+
+impl TypstWrapperWorld {
+    /// Creates a world for the given source.
+    ///
+    /// Fonts are searched in `font_paths` (recursively), falling back to the
+    /// fonts embedded in Typst. To compile many documents, use a
+    /// [`WorldBuilder`] instead, which searches for fonts only once.
+    pub fn new(root: String, source: String, font_paths: &[PathBuf]) -> Self {
+        WorldBuilder::new(font_paths).build(root, source)
+    }
+
+    /// Like [`Self::new`], but also uses the fonts installed on the system,
+    /// with lower precedence than those in `font_paths`.
+    pub fn with_system_fonts(root: String, source: String, font_paths: &[PathBuf]) -> Self {
+        WorldBuilder::with_system_fonts(font_paths).build(root, source)
+    }
+
+    /// Like [`Self::new`], but fails if one of the font directories doesn't
+    /// exist, instead of silently falling back to other fonts.
+    pub fn with_fonts(root: String, source: String, font_paths: &[PathBuf]) -> io::Result<Self> {
+        check_font_paths(font_paths)?;
+        Ok(Self::new(root, source, font_paths))
+    }
+
+    /// Gives the main source a path within the project root, so that its
+    /// diagnostics name the actual file and relative imports resolve from its
+    /// directory. By default, the main source is a virtual `/main.typ`.
+    pub fn with_main(mut self, path: VirtualPath) -> Self {
+        let id = FileId::new(None, path);
+        self.source = Source::new(id, self.source.text().to_owned());
+        self
+    }
+
+    /// Compiles the given code before the main source, e.g. to override the
+    /// page size with `#set page(..)`. The code is placed in a separate file
+    /// that includes the main source, so diagnostics in the main source still
+    /// point at the right lines. The main source may still be moved with
+    /// [`Self::with_main`] afterwards.
+    pub fn with_prepended(mut self, code: impl IntoIterator<Item = String>) -> Self {
+        let text: String = code.into_iter().map(|line| line + "\n").collect();
+        let id = FileId::new_fake(VirtualPath::new("prepend.typ"));
+        self.prelude = Some((id, text));
+        self
+    }
+
+    /// The file of [`Self::with_prepended`], which includes the main source
+    /// from where it is now.
+    fn prelude(&self) -> Option<Source> {
+        let (id, code) = self.prelude.as_ref()?;
+        let main = self.source.id().vpath().as_rooted_path().to_string_lossy().replace('\\', "/");
+        let main = main.replace('"', "\\\"");
+        Some(Source::new(*id, format!("{code}#include \"{main}\"\n")))
+    }
+
+    /// The fonts and standard library of this world, to build more worlds
+    /// that share them without searching for fonts again.
+    pub fn builder(&self) -> WorldBuilder {
+        WorldBuilder {
+            library: Arc::clone(&self.library),
+            book: Arc::clone(&self.book),
+            fonts: Arc::clone(&self.fonts),
+            http: self.http.clone(),
+        }
+    }
+
+    /// The absolute root path files are resolved against.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Makes the given key-value pairs available to the document as
+    /// `sys.inputs`, just like `typst compile --input`.
+    pub fn with_inputs(mut self, inputs: impl IntoIterator<Item = (String, String)>) -> Self {
+        self.library = Arc::new(library_with_inputs(inputs));
+        self
+    }
+
+    /// Pins the current time, e.g. to make `datetime.today()` reproducible.
+    ///
+    /// The offset of `now` is used as the local timezone.
+    pub fn with_now(mut self, now: time::OffsetDateTime) -> Self {
+        self.time = now;
+        self.pinned_time = Some(now);
+        self
+    }
+
+    /// Resolves packages from `<dir>/<namespace>/<name>/<version>/` instead
+    /// of the default package cache. Downloaded packages are stored there too.
+    pub fn with_package_path(mut self, dir: PathBuf) -> Self {
+        self.cache_directory = dir;
+        self
+    }
+
+    /// Disables package downloads. Packages that are not in the package
+    /// directory then fail to load with an error naming them.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Serves the given files, with paths within the root, from memory and
+    /// disables reading any other file from disk. Files that are not given
+    /// then fail to load as not found. Packages are still loaded as usual.
+    pub fn with_vfs(mut self, files: impl IntoIterator<Item = (VirtualPath, Vec<u8>)>) -> Self {
+        let mut entries = self.files.lock().unwrap();
+        for (path, contents) in files {
+            entries.insert(FileId::new(None, path), FileEntry::new(contents, None));
+        }
+        drop(entries);
+        self.disk_access = false;
+        self
+    }
+
+    /// Allows reading files from the given directories, although they are
+    /// outside the root. Files there can be reached through symlinks within
+    /// the root, e.g. to a shared assets folder.
+    pub fn with_allowed_paths(mut self, paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        self.allowed_paths
+            .extend(paths.into_iter().map(|path| path.canonicalize().unwrap_or(path)));
+        self
+    }
+
+    /// Refuses access to files that are outside the root and the allowed
+    /// directories once symlinks are resolved.
+    fn check_access(&self, path: &Path) -> FileResult<()> {
+        // Files that don't exist are reported as not found when read.
+        let Ok(path) = path.canonicalize() else {
+            return Ok(());
+        };
+        let allowed = std::iter::once(&self.root)
+            .chain(&self.allowed_paths)
+            .any(|dir| path.starts_with(dir));
+        if allowed { Ok(()) } else { Err(FileError::AccessDenied) }
+    }
+
+    /// Returns the path of the file a font was loaded from, or `None` if it
+    /// is embedded in the binary.
+    pub fn font_path(&self, font: &Font) -> Option<&Path> {
+        (0..self.fonts.len())
+            .find(|&index| {
+                self.book.info(index) == Some(font.info())
+                    && self.fonts[index].index() == font.index()
+            })
+            .and_then(|index| self.fonts[index].path())
+    }
+
+    /// The text of the main source.
+    pub fn main_text(&self) -> &str {
+        self.source.text()
+    }
+
+    /// The main source, without the code prepended to it.
+    pub fn main_source(&self) -> &Source {
+        &self.source
+    }
+
+    /// Returns the files that have been read so far, besides the main source
+    /// and the code prepended to it, e.g. to find the files a compilation
+    /// depended on.
+    pub fn loaded_files(&self) -> Vec<FileId> {
+        self.files.lock().unwrap().keys().copied().collect()
+    }
+
+    /// Returns the main source and the Typst sources that have been read so
+    /// far, e.g. to find where the text of a compiled document comes from.
+    pub fn sources(&self) -> Vec<Source> {
+        let loaded = self
+            .loaded_files()
+            .into_iter()
+            .filter(|id| id.vpath().as_rootless_path().extension().is_some_and(|extension| extension == "typ"))
+            .filter_map(|id| typst::World::source(self, id).ok());
+        std::iter::once(self.source.clone()).chain(loaded).collect()
+    }
+
+    /// Replaces the text of the main source, e.g. after the file changed.
+    /// Only the parts of its syntax tree that changed are parsed again, so
+    /// that the next compilation can reuse as much of the last one as
+    /// possible.
+    pub fn set_main_text(&mut self, text: &str) {
+        self.source.replace(text);
+    }
+
+    /// Forgets all files read from disk, so that the next compilation picks
+    /// up changes to them. Within one compilation, each file is read and
+    /// parsed only once. In-memory files given by [`Self::with_vfs`] are kept.
+    ///
+    /// The current time is taken again, unless it was pinned with
+    /// [`Self::with_now`].
+    pub fn reset(&mut self) {
+        self.time = self.pinned_time.unwrap_or_else(now_local);
+        if self.disk_access {
+            self.files.lock().unwrap().clear();
+        }
+    }
 
     /// Helper to handle file requests.
     ///
-    /// Requests will be either in packages or a local file.
-    fn file(&self, id: FileId) -> FileResult<FileEntry> {
+    /// Requests will be either in packages or a local file. Files are read
+    /// once and kept in memory, so `f` can also cache derived data (like the
+    /// parsed source) in the entry.
+    fn file<T>(&self, id: FileId, f: impl FnOnce(&mut FileEntry) -> FileResult<T>) -> FileResult<T> {
         let mut files = self.files.lock().map_err(|_| FileError::AccessDenied)?;
-        if let Some(entry) = files.get(&id) {
-            return Ok(entry.clone());
+        if let Some(entry) = files.get_mut(&id) {
+            return f(entry);
         }
         let path = if let Some(package) = id.package() {
             // Fetching file from package
             let package_dir = self.download_package(package)?;
             id.vpath().resolve(&package_dir)
-        } else {
+        } else if self.disk_access {
             // Fetching file from disk
-            id.vpath().resolve(&self.root)
+            let path = id.vpath().resolve(&self.root).ok_or(FileError::AccessDenied)?;
+            self.check_access(&path)?;
+            Some(path)
+        } else {
+            return Err(FileError::NotFound(id.vpath().as_rootless_path().into()));
         }
         .ok_or(FileError::AccessDenied)?;
 
-        let content = std::fs::read(&path).map_err(|error| FileError::from_io(error, &path))?;
-        Ok(files
-            .entry(id)
-            .or_insert(FileEntry::new(content, None))
-            .clone())
+        let content = read(&path)?;
+        f(files.entry(id).or_insert(FileEntry::new(content, None)))
     }
 
     /// Downloads the package and returns the system path of the unpacked package.
@@ -87,6 +384,18 @@ impl TypstWrapperWorld {
             return Ok(path);
         }
 
+        // Only packages of the preview namespace are hosted on the registry.
+        if package.namespace != "preview" {
+            return Err(PackageError::NotFound(package.clone()));
+        }
+
+        if self.offline {
+            return Err(PackageError::Other(Some(eco_format!(
+                "{package} is not in {} and downloads are disabled in offline mode",
+                self.cache_directory.display()
+            ))));
+        }
+
         eprintln!("downloading {package}");
         let url = format!(
             "https://packages.typst.org/{}/{}-{}.tar.gz",
@@ -94,13 +403,21 @@ impl TypstWrapperWorld {
         );
 
         let response = retry(|| {
-            let response = self.http.get(&url).call().map_err(|error| eco_format!("{error}"))?;
+            let response = match self.http.get(&url).call() {
+                Ok(response) => response,
+                Err(ureq::Error::Status(404, _)) => {
+                    return Err(PackageError::NotFound(package.clone()));
+                }
+                Err(error) => return Err(PackageError::NetworkFailed(Some(eco_format!("{error}")))),
+            };
             let status = response.status();
             if !http_successful(status) {
-                return Err(eco_format!("response returned unsuccessful status code {status}"));
+                return Err(PackageError::NetworkFailed(Some(eco_format!(
+                    "response returned unsuccessful status code {status}"
+                ))));
             }
             Ok(response)
-        }).map_err(|error| PackageError::NetworkFailed(Some(error)))?;
+        })?;
 
         let mut compressed_archive = Vec::new();
         response
@@ -152,19 +469,33 @@ impl FileEntry {
 impl typst::World for TypstWrapperWorld {
     fn library(&self) -> &LazyHash<Library> { &self.library }
     fn book(&self) -> &LazyHash<FontBook> { &self.book }
-    fn main(&self) -> FileId { self.source.id() }
+    fn main(&self) -> FileId { self.prelude.as_ref().map_or(self.source.id(), |(id, _)| *id) }
     fn source(&self, id: FileId) -> FileResult<Source> {
         if id == self.source.id() {
             Ok(self.source.clone())
+        } else if let Some(prelude) = self.prelude().filter(|prelude| prelude.id() == id) {
+            Ok(prelude)
         } else {
-            self.file(id)?.source(id)
+            self.file(id, |file| file.source(id))
         }
     }
-    fn file(&self, id: FileId) -> FileResult<Bytes> { self.file(id).map(|file| file.bytes.clone()) }
-    fn font(&self, id: usize) -> Option<Font> { self.fonts[id].get() }
+    fn file(&self, id: FileId) -> FileResult<Bytes> {
+        if id == self.source.id() {
+            // The main source may not exist on disk, e.g. when read from stdin.
+            Ok(Bytes::from_string(self.source.text().to_owned()))
+        } else if let Some(prelude) = self.prelude().filter(|prelude| prelude.id() == id) {
+            Ok(Bytes::from_string(prelude.text().to_owned()))
+        } else {
+            self.file(id, |file| Ok(file.bytes.clone()))
+        }
+    }
+    fn font(&self, id: usize) -> Option<Font> { self.fonts.get(id)?.get() }
     fn today(&self, offset: Option<i64>) -> Option<Datetime> {
-        let offset = offset.unwrap_or(0);
-        let offset = time::UtcOffset::from_hms(offset.try_into().ok()?, 0, 0).ok()?;
+        // Without an explicit offset, the date is taken in the local timezone.
+        let offset = match offset {
+            Some(hours) => time::UtcOffset::from_hms(hours.try_into().ok()?, 0, 0).ok()?,
+            None => self.time.offset(),
+        };
         let time = self.time.checked_to_offset(offset)?;
         Some(Datetime::Date(time.date()))
     }
@@ -172,6 +503,58 @@ impl typst::World for TypstWrapperWorld {
 
 // ---- Helper Functions ----
 
+/// Returns the directory downloaded packages are stored in.
+///
+/// This is `TYPST_PACKAGE_PATH` if set, the legacy `CACHE_DIRECTORY` otherwise,
+/// and falls back to Typst's default `~/.cache/typst/packages`.
+fn package_cache_directory() -> PathBuf {
+    std::env::var_os("TYPST_PACKAGE_PATH")
+        .or_else(|| std::env::var_os("CACHE_DIRECTORY"))
+        .map(PathBuf::from)
+        .or_else(|| dirs::cache_dir().map(|dir| dir.join("typst/packages")))
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// The current time in the local timezone, or in UTC if the local offset
+/// can't be determined.
+fn now_local() -> time::OffsetDateTime {
+    time::OffsetDateTime::now_local().unwrap_or_else(|_| time::OffsetDateTime::now_utc())
+}
+
+/// Checks that all font paths are existing directories, and returns a
+/// `NotFound` error naming the first one that isn't.
+///
+/// Nothing is read or loaded here. Without this check, a mistyped path would
+/// silently leave its fonts out.
+pub fn check_font_paths(font_paths: &[PathBuf]) -> io::Result<()> {
+    for path in font_paths {
+        if !path.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("font directory {} does not exist", path.display()),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Builds the standard library with the given `sys.inputs`.
+fn library_with_inputs(inputs: impl IntoIterator<Item = (String, String)>) -> LazyHash<Library> {
+    let inputs: Dict = inputs
+        .into_iter()
+        .map(|(key, value)| (key.into(), Value::Str(value.into())))
+        .collect();
+    LazyHash::new(Library::builder().with_inputs(inputs).build())
+}
+
+/// Reads a file from disk, mapping I/O failures to the matching `FileError`.
+fn read(path: &Path) -> FileResult<Vec<u8>> {
+    if path.is_dir() {
+        return Err(FileError::IsDirectory);
+    }
+    std::fs::read(path).map_err(|error| FileError::from_io(error, path))
+}
+
 fn retry<T, E>(mut f: impl FnMut() -> Result<T, E>) -> Result<T, E> {
     if let Ok(ok) = f() { Ok(ok) } else { f() }
 }
@@ -179,4 +562,184 @@ fn retry<T, E>(mut f: impl FnMut() -> Result<T, E>) -> Result<T, E> {
 fn http_successful(status: u16) -> bool {
     // 2XX
     status / 100 == 2
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use typst::World;
+
+    #[test]
+    fn worlds_of_one_builder_read_each_font_file_once() {
+        // A copy of an embedded font in a font directory, loaded from disk.
+        let dir = std::env::temp_dir().join(format!("box_extractor-fonts-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let embedded = WorldBuilder::new(&[]).build(String::new(), String::new());
+        let font_path = dir.join("font.otf");
+        std::fs::write(&font_path, embedded.font(0).unwrap().data().as_slice()).unwrap();
+
+        let builder = WorldBuilder::new(std::slice::from_ref(&dir));
+        let id = builder.fonts.iter().position(|slot| slot.path() == Some(font_path.as_path())).unwrap();
+        // The embedded font is left out in favour of its copy.
+        assert_eq!(builder.fonts.len(), embedded.fonts.len());
+        let first = builder.build(String::new(), "Hello".into());
+        assert!(first.font(id).is_some());
+
+        // The next world must not read the file again, so it still has the
+        // font once the file is gone.
+        std::fs::remove_dir_all(&dir).unwrap();
+        let second = builder.build(String::new(), "World".into());
+        assert!(Arc::ptr_eq(&first.fonts, &second.fonts));
+        assert!(second.font(id).is_some());
+    }
+
+    #[test]
+    fn fonts_of_font_paths_lay_out_with_their_metrics() {
+        // A copy of the embedded monospace font under a family name of the
+        // same length, which is only found in the font directory.
+        let embedded = WorldBuilder::new(&[]).build(String::new(), String::new());
+        let index = (0..embedded.fonts.len())
+            .find(|&index| embedded.book().info(index).unwrap().family == "DejaVu Sans Mono")
+            .unwrap();
+        let mut data = embedded.font(index).unwrap().data().to_vec();
+        // The name table has the family both in ASCII and in UTF-16.
+        let utf16 = |text: &str| text.encode_utf16().flat_map(u16::to_be_bytes).collect::<Vec<u8>>();
+        let (from, to) = ("DejaVu Sans Mono", "Custom Test Font");
+        for (from, to) in [(from.as_bytes().to_vec(), to.as_bytes().to_vec()), (utf16(from), utf16(to))] {
+            while let Some(start) = data.windows(from.len()).position(|window| window == from) {
+                data[start..start + from.len()].copy_from_slice(&to);
+            }
+        }
+        let dir = std::env::temp_dir().join(format!("box_extractor-custom-font-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("custom.ttf"), data).unwrap();
+
+        let widths = |font_paths: &[PathBuf]| {
+            let source = "#set text(font: \"Custom Test Font\")\niiii mmmm";
+            let world = WorldBuilder::new(font_paths).build(String::new(), source.into());
+            let document = crate::compile(&world).output.unwrap();
+            let boxes = crate::collect_word_boxes(&document, &crate::ExtractOptions::default());
+            (boxes[0].width, boxes[1].width)
+        };
+        // Without the font directory the text falls back to a proportional
+        // font, with it all the words of four letters are as wide.
+        let (thin, wide) = widths(&[]);
+        assert!(wide > 2.0 * thin, "{thin} {wide}");
+        let (thin, wide) = widths(std::slice::from_ref(&dir));
+        assert!((thin - wide).abs() < 1e-6, "{thin} {wide}");
+        assert!((thin - 4.0 * 0.602 * 11.0).abs() < 0.1, "{thin}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reset_takes_the_current_time_unless_it_is_pinned() {
+        let epoch = time::OffsetDateTime::UNIX_EPOCH;
+        let mut world = WorldBuilder::new(&[]).build(String::new(), String::new());
+        world.time = epoch;
+        world.reset();
+        assert!(world.time > epoch);
+
+        let mut pinned = WorldBuilder::new(&[]).build(String::new(), String::new()).with_now(epoch);
+        pinned.reset();
+        assert_eq!(pinned.time, epoch);
+    }
+
+    #[test]
+    fn prepended_code_includes_the_main_source_where_it_was_moved() {
+        let world = WorldBuilder::new(&[])
+            .build(String::new(), "Hello".into())
+            .with_prepended(["#set text(size: 20pt)".to_string()])
+            .with_main(VirtualPath::new("chapters/intro.typ"));
+        let document = crate::compile(&world).output.unwrap();
+        let boxes = crate::collect_word_boxes(&document, &crate::ExtractOptions::default());
+        assert_eq!(boxes[0].word, "Hello");
+        assert_eq!(boxes[0].font_size, Some(20.0));
+    }
+
+    #[test]
+    fn prepended_margins_move_the_words() {
+        let first_x = |margin: &str| {
+            let world = WorldBuilder::new(&[])
+                .build(String::new(), "Hello".into())
+                .with_prepended([format!("#set page(margin: (left: {margin}))")]);
+            let document = crate::compile(&world).output.unwrap();
+            crate::collect_word_boxes(&document, &crate::ExtractOptions::default())[0].x
+        };
+        let (narrow, wide) = (first_x("1cm"), first_x("3cm"));
+        assert!((narrow - 28.3465).abs() < 1e-3, "{narrow}");
+        assert!((wide - narrow - 2.0 * 28.3465).abs() < 1e-3, "{wide}");
+    }
+
+    /// A project directory `root` with a symlink `root/link` to a directory
+    /// `outside` beside it, which has a file `data.txt` in it.
+    #[cfg(unix)]
+    fn project_with_symlink(name: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("box_extractor-{name}-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        let (root, outside) = (dir.join("root"), dir.join("outside"));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(outside.join("data.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+        (root, outside)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_out_of_the_root_are_denied() {
+        let (root, _) = project_with_symlink("symlink");
+        let world = WorldBuilder::new(&[]).build(root.to_str().unwrap().to_owned(), String::new());
+        let id = FileId::new(None, VirtualPath::new("link/data.txt"));
+        assert_eq!(World::file(&world, id), Err(FileError::AccessDenied));
+        std::fs::remove_dir_all(root.parent().unwrap()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_into_allowed_paths_are_read() {
+        let (root, outside) = project_with_symlink("allowed");
+        let world = WorldBuilder::new(&[])
+            .build(root.to_str().unwrap().to_owned(), String::new())
+            .with_allowed_paths([outside]);
+        let id = FileId::new(None, VirtualPath::new("link/data.txt"));
+        assert_eq!(World::file(&world, id).unwrap().as_slice(), b"secret");
+        std::fs::remove_dir_all(root.parent().unwrap()).unwrap();
+    }
+
+    /// The font of the first text on the first page of a document.
+    fn first_font(document: &typst::layout::PagedDocument) -> Font {
+        document.pages[0]
+            .frame
+            .items()
+            .find_map(|(_, item)| match item {
+                typst::layout::FrameItem::Text(text) => Some(text.font.clone()),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn two_documents_compile_with_the_fonts_of_one_builder() {
+        // A copy of the default font in a font directory, which takes
+        // precedence over the embedded one.
+        let embedded = WorldBuilder::new(&[]).build(String::new(), "Hello".into());
+        let font = first_font(&crate::compile(&embedded).output.unwrap());
+        let dir = std::env::temp_dir().join(format!("box_extractor-shared-fonts-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let font_path = dir.join("font.otf");
+        std::fs::write(&font_path, font.data().as_slice()).unwrap();
+
+        let builder = WorldBuilder::new(std::slice::from_ref(&dir));
+        let first = builder.build(String::new(), "Hello".into());
+        let first_document = crate::compile(&first).output.unwrap();
+        assert_eq!(first.font_path(&first_font(&first_document)), Some(font_path.as_path()));
+
+        // The second document is laid out with the font the first one loaded,
+        // as the file is gone.
+        std::fs::remove_dir_all(&dir).unwrap();
+        let second = builder.build(String::new(), "World".into());
+        let second_document = crate::compile(&second).output.unwrap();
+        assert_eq!(first_font(&second_document), first_font(&first_document));
+        assert_eq!(second.font_path(&first_font(&second_document)), Some(font_path.as_path()));
+    }
+}
@@ -0,0 +1,321 @@
+//! Runs the command line on small documents written to a temporary directory.
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+/// A fresh directory for one test, with the given files in it.
+fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (path, content) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+    dir
+}
+
+/// Runs the command line in the directory.
+fn run(dir: &PathBuf, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_box_extractor")).current_dir(dir).args(args).output().unwrap()
+}
+
+#[test]
+fn batch_writes_the_boxes_of_each_document() {
+    let dir = project("batch", &[("a.typ", "Hello one"), ("b.typ", "Hello two")]);
+    let output = run(&dir, &["--format", "csv", "batch", "a.typ", "b.typ", "--out-dir", "boxes"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    for (name, word) in [("a.csv", "one"), ("b.csv", "two")] {
+        let csv = fs::read_to_string(dir.join("boxes").join(name)).unwrap();
+        let words: Vec<&str> = csv.lines().skip(1).map(|row| row.split(',').nth(1).unwrap()).collect();
+        assert_eq!(words, ["Hello", word]);
+    }
+}
+
+#[test]
+fn batch_goes_on_after_a_failing_document() {
+    let dir = project("batch-failing", &[("bad.typ", "#bad("), ("good.typ", "Hello")]);
+    let output = run(&dir, &["batch", "bad.typ", "good.typ", "--out-dir", "boxes"]);
+    assert!(!output.status.success());
+    assert!(!dir.join("boxes/bad.json").exists());
+    assert!(dir.join("boxes/good.json").exists());
+}
+
+#[test]
+fn code_takes_only_the_output_path() {
+    let dir = project("code", &[]);
+    let output = run(&dir, &["--code", "Hello", "boxes.json"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(dir.join("boxes.json").exists());
+
+    let output = run(&dir, &["--code", "Hello", "input.typ", "boxes.json"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("an input file can't be used with --code"));
+}
+
+#[test]
+fn unwritable_outputs_fail_before_anything_is_written() {
+    let dir = project("unwritable", &[("main.typ", "Hello"), ("taken/.keep", "")]);
+    let output = run(&dir, &["main.typ", "boxes.json", "--pdf-out", "missing/boxes.pdf"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("error: could not write missing/boxes.pdf: missing does not exist"), "{stderr}");
+    for written in ["boxes.json", "output.png", "output_boxes.png"] {
+        assert!(!dir.join(written).exists(), "{written}");
+    }
+
+    let output = run(&dir, &["main.typ", "taken"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("error: could not write taken: it is a directory"));
+    assert!(!dir.join("output.png").exists());
+}
+
+#[test]
+fn dash_reads_the_source_from_stdin() {
+    let dir = project("stdin", &[("name.typ", "#let name = [stdin]")]);
+    let mut child = Command::new(env!("CARGO_BIN_EXE_box_extractor"))
+        .current_dir(&dir)
+        .args(["-", "boxes.json"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Files are found relative to the current directory.
+    child.stdin.take().unwrap().write_all(b"#import \"name.typ\": name\nRead from #name").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&fs::read(dir.join("boxes.json")).unwrap()).unwrap();
+    let words: Vec<&str> =
+        json["pages"][0]["words"].as_array().unwrap().iter().map(|word| word["word"].as_str().unwrap()).collect();
+    assert_eq!(words, ["Read", "from", "stdin"]);
+}
+
+#[test]
+fn source_locations_name_the_included_file() {
+    let main = "Intro text\n#include \"chapters/one.typ\"\n";
+    let chapter = "= Chapter\n\n  Indented words\n";
+    let dir = project("include", &[("main.typ", main), ("chapters/one.typ", chapter)]);
+    let output = run(&dir, &["main.typ", "boxes.json", "--with-source-locations"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&fs::read(dir.join("boxes.json")).unwrap()).unwrap();
+    let locations: Vec<(&str, &str, u64, u64)> = json["pages"][0]["words"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|word| word["kind"] == "word")
+        .map(|word| {
+            let text = word["word"].as_str().unwrap();
+            (text, word["file"].as_str().unwrap(), word["line"].as_u64().unwrap(), word["column"].as_u64().unwrap())
+        })
+        .collect();
+    assert_eq!(
+        locations,
+        [
+            ("Intro", "/main.typ", 1, 1),
+            ("text", "/main.typ", 1, 7),
+            ("Chapter", "/chapters/one.typ", 1, 3),
+            ("Indented", "/chapters/one.typ", 3, 3),
+            ("words", "/chapters/one.typ", 3, 12),
+        ]
+    );
+}
+
+#[test]
+fn root_lets_a_chapter_import_from_the_parent_directory() {
+    let files = [
+        ("chapters/main.typ", "#import \"../template.typ\": title\n#title"),
+        ("template.typ", "#let title = [Shared title]"),
+        ("outside.typ", "Outside"),
+    ];
+    let dir = project("root", &files);
+    let output = run(&dir, &["--root", "chapters", "chapters/main.typ", "boxes.json"]);
+    assert!(!output.status.success(), "the template is outside of the root");
+
+    let output = run(&dir, &["--root", ".", "chapters/main.typ", "boxes.json"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&fs::read(dir.join("boxes.json")).unwrap()).unwrap();
+    let words: Vec<&str> =
+        json["pages"][0]["words"].as_array().unwrap().iter().map(|word| word["word"].as_str().unwrap()).collect();
+    assert_eq!(words, ["Shared", "title"]);
+
+    let output = run(&dir, &["--root", "chapters", "outside.typ", "outside.json"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("is not inside the project root"), "{stderr}");
+    assert!(!dir.join("outside.json").exists());
+}
+
+#[test]
+fn input_sets_sys_inputs() {
+    let dir = project("input", &[("main.typ", "#sys.inputs.at(\"foo\", default: \"unset\")")]);
+    let output = run(&dir, &["main.typ", "boxes.json", "--input", "foo=bar"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&fs::read(dir.join("boxes.json")).unwrap()).unwrap();
+    assert_eq!(json["pages"][0]["words"][0]["word"], "bar");
+}
+
+#[test]
+fn table_from_a_csv_file_has_a_box_per_cell() {
+    let csv = "Name,Price\nApple,1\nPear,2\nPlum,3\n";
+    let main = "#table(columns: 2, ..csv(\"data.csv\").flatten())";
+    let dir = project("csv", &[("main.typ", main), ("data.csv", csv)]);
+    let output = run(&dir, &["main.typ", "boxes.json"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&fs::read(dir.join("boxes.json")).unwrap()).unwrap();
+    let words: Vec<&str> =
+        json["pages"][0]["words"].as_array().unwrap().iter().map(|word| word["word"].as_str().unwrap()).collect();
+    // Two headers and three rows of two cells.
+    assert_eq!(words.len(), 2 + 3 * 2);
+    assert_eq!(words, ["Name", "Price", "Apple", "1", "Pear", "2", "Plum", "3"]);
+}
+
+#[test]
+fn missing_data_file_is_not_found() {
+    let dir = project("missing-data", &[("main.typ", "#json(\"nope.json\")")]);
+    let output = run(&dir, &["main.typ", "boxes.json"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("not found"), "{stderr}");
+    assert!(!dir.join("boxes.json").exists());
+}
+
+#[test]
+fn missing_glyphs_are_reported_once_per_font_family() {
+    let dir = project("missing-glyphs", &[("main.typ", "*Bold 𓀀* and regular 𓀀")]);
+    let output = run(&dir, &["main.typ", "boxes.json"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("some characters are not covered by any font").count(), 1, "{stderr}");
+}
+
+#[test]
+fn streamed_ndjson_matches_the_buffered_output() {
+    let dir = project("ndjson", &[("main.typ", "Hello world #pagebreak() Second page")]);
+    let ndjson = |args: &[&str]| {
+        let args = [&["main.typ", "boxes.ndjson", "--format", "ndjson", "--origin", "bottom-left"], args].concat();
+        let output = run(&dir, &args);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        fs::read_to_string(dir.join("boxes.ndjson")).unwrap()
+    };
+    let streamed = ndjson(&[]);
+    assert_eq!(streamed.lines().count(), 4);
+    // --print-hash needs the whole output, so it isn't streamed.
+    assert_eq!(streamed, ndjson(&["--print-hash"]));
+}
+
+#[test]
+fn per_page_reports_the_selected_pages() {
+    let dir = project("per-page", &[("main.typ", "One #pagebreak() Two #pagebreak() Three")]);
+    let output = run(&dir, &["main.typ", "boxes.json", "--per-page", "--pages", "1-2", "--svg-out", "boxes.svg"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Rendered 2 pages"));
+}
+
+#[test]
+fn pdf_out_exports_the_selected_pages() {
+    let dir = project("pdf-out", &[("main.typ", "One #pagebreak() Two #pagebreak() Three")]);
+    let output = run(&dir, &["main.typ", "boxes.json", "--pages", "1,3", "--pdf-out", "boxes.pdf"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let pdf = fs::read(dir.join("boxes.pdf")).unwrap();
+    assert!(pdf.starts_with(b"%PDF-"));
+    assert!(String::from_utf8_lossy(&pdf).contains("/Count 2"));
+}
+
+#[test]
+fn highlight_takes_ranges_of_any_length() {
+    let dir = project("highlight", &[("main.typ", "Hello world")]);
+    let output = run(&dir, &["main.typ", "boxes.json", "--highlight", "1,0-18446744073709551615"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn pages_beyond_the_document_fail_cleanly() {
+    let dir = project("pages-out-of-range", &[("main.typ", "One #pagebreak() Two #pagebreak() Three")]);
+    let output = run(&dir, &["main.typ", "boxes.json", "--pages", "99"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--pages selects none of the 3 page(s)"), "{stderr}");
+    assert!(!dir.join("boxes.json").exists());
+}
+
+#[test]
+fn cached_boxes_are_reused_only_for_the_same_options() {
+    let dir = project("cache", &[("main.typ", "Hello world")]);
+    let stdout = |args: &[&str]| {
+        let output = run(&dir, &[&["main.typ", "boxes.json", "--cache-dir", "cache"], args].concat());
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let reused = "Reused the cached word boxes";
+    assert!(!stdout(&[]).contains(reused));
+    assert!(stdout(&[]).contains(reused));
+    // Only options that change the boxes count.
+    assert!(stdout(&["--box-width", "3"]).contains(reused));
+    assert!(!stdout(&["--granularity", "line"]).contains(reused));
+
+    for age in ["inf", "1e300", "-1"] {
+        let output = run(&dir, &["main.typ", "boxes.json", "--cache-dir", "cache", "--cache-max-age", age]);
+        assert_eq!(output.status.code(), Some(2), "{age}");
+    }
+}
+
+/// The names of the fields of the boxes in the output that the schema of a
+/// box doesn't declare.
+fn undeclared_fields(boxes: &serde_json::Value, schema: &serde_json::Value, undeclared: &mut Vec<String>) {
+    for word_box in boxes.as_array().into_iter().flatten() {
+        for (field, value) in word_box.as_object().unwrap() {
+            if schema["$defs"]["box"]["properties"].get(field).is_none() {
+                undeclared.push(field.clone());
+            }
+            if field == "children" {
+                undeclared_fields(value, schema, undeclared);
+            }
+        }
+    }
+}
+
+#[test]
+fn output_with_all_fields_matches_the_schema() {
+    let source = r#"
+#set document(title: "Schema", author: "Someone", keywords: ("boxes",))
+#set text(lang: "en")
+#set heading(numbering: "1.")
+= Heading <intro>
+#text(fill: blue)[Hello] #link("https://typst.app")[world], see @intro.
+#rotate(30deg)[Turned] $x^2$
+#table(columns: 2, [a], [b])
+#figure(caption: [Caption])[Body]
+"#;
+    let dir = project("schema", &[("main.typ", source)]);
+    let schema = box_extractor::output::json_schema();
+    let validator = jsonschema::validator_for(&schema).unwrap();
+    let runs: &[&[&str]] = &[
+        &[
+            "--with-metadata", "--query", "heading", "--find", "hello", "--tables", "--include-color",
+            "--include-baseline", "--include-lang", "--include-source", "--with-source-locations",
+            "--include-whitespace", "--include-delimiters",
+        ],
+        &["--tree", "--coords", "pixels", "--origin", "bottom-left", "--per-page"],
+        &["--granularity", "sentence"],
+        &["--granularity", "glyph"],
+    ];
+    for args in runs {
+        let output = run(&dir, &[&["main.typ", "boxes.json"], *args].concat());
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let json: serde_json::Value = serde_json::from_slice(&fs::read(dir.join("boxes.json")).unwrap()).unwrap();
+        let errors: Vec<String> = validator.iter_errors(&json).map(|error| error.to_string()).collect();
+        assert!(errors.is_empty(), "{args:?}: {errors:#?}");
+
+        let mut undeclared = Vec::new();
+        for page in json["pages"].as_array().unwrap() {
+            for list in ["words", "labels", "matches", "cells"] {
+                undeclared_fields(&page[list], &schema, &mut undeclared);
+            }
+        }
+        assert!(undeclared.is_empty(), "{args:?}: {undeclared:?}");
+    }
+}
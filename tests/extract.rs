@@ -0,0 +1,506 @@
+//! Extracts the boxes of small documents through the library.
+
+use std::path::Path;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use box_extractor::metadata::{fonts_used, FontUsage};
+use box_extractor::tables::table_cells;
+use box_extractor::vfs::parse_vfs;
+use box_extractor::{
+    compile, extract_word_boxes, extract_word_boxes_with, BoxMode, ExtractError, ExtractOptions, Granularity, SplitMode,
+    WordBox, WorldBuilder,
+};
+use typst::layout::PagedDocument;
+use typst::syntax::VirtualPath;
+
+/// The boxes of the words of a document, with the default options.
+fn words(source: &str) -> Vec<WordBox> {
+    extract_word_boxes(source, Path::new("."), &ExtractOptions::default()).unwrap()
+}
+
+/// The text, page, row, column, row span and column span of a table cell.
+type Cell = (String, usize, usize, usize, usize, usize);
+
+/// The cells of the tables of a document.
+fn cells(source: &str) -> Vec<Cell> {
+    let world = WorldBuilder::new(&[]).build(String::new(), source.into());
+    let document = compile(&world).output.unwrap();
+    table_cells(&document, &world.sources())
+        .into_iter()
+        .map(|cell| {
+            let position = [cell.row, cell.col, cell.rowspan, cell.colspan].map(Option::unwrap);
+            (cell.word, cell.page, position[0], position[1], position[2], position[3])
+        })
+        .collect()
+}
+
+/// The text of each box.
+fn texts(boxes: &[WordBox]) -> Vec<&str> {
+    boxes.iter().map(|word_box| word_box.word.as_str()).collect()
+}
+
+#[test]
+fn sys_inputs_are_strings_from_the_builder() {
+    let source = r#"#if sys.inputs.foo == "yes" [Drafted] else [Final] #sys.inputs.at("bar", default: "none")"#;
+    let builder = WorldBuilder::new(&[]).with_inputs([("foo".to_string(), "yes".to_string())]);
+    let boxes = extract_word_boxes_with(&builder, source, Path::new("."), &ExtractOptions::default()).unwrap();
+    assert_eq!(texts(&boxes), ["Drafted", "none"]);
+    assert_eq!(texts(&words(r#"#sys.inputs.at("foo", default: "unset")"#)), ["unset"]);
+}
+
+#[test]
+fn missing_glyphs_are_counted_per_word_and_summed_per_line() {
+    let source = "Hello 𓀀𓀁 world";
+    let boxes = words(source);
+    let flagged: Vec<(&str, usize)> = boxes
+        .iter()
+        .filter(|word_box| word_box.missing_glyph_count > 0)
+        .map(|word_box| (word_box.word.as_str(), word_box.missing_glyph_count))
+        .collect();
+    assert_eq!(flagged, [("𓀀𓀁", 2)]);
+
+    let opts = ExtractOptions { granularity: Granularity::Line, ..ExtractOptions::default() };
+    let lines = extract_word_boxes(source, Path::new("."), &opts).unwrap();
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0].missing_glyph_count, 2);
+}
+
+#[test]
+fn blocks_are_the_tagged_elements_their_lines_are_in() {
+    let source = "#set page(width: 8cm)
+= A heading that wraps onto a second line
+#par[Spaced #h(2cm) out, a labelled paragraph wraps onto a second line.] <intro>
+
+An untagged paragraph.
+
+- An item";
+    let opts = ExtractOptions { granularity: Granularity::Block, ..ExtractOptions::default() };
+    let blocks = extract_word_boxes(source, Path::new("."), &opts).unwrap();
+    let kinds: Vec<(&str, usize)> =
+        blocks.iter().map(|block| (block.kind.as_str(), block.children.len())).collect();
+    // The gap splits the first line, but not the paragraph, nor does it make
+    // it a list item as it would in untagged text.
+    assert_eq!(kinds, [("heading", 2), ("par", 3), ("par", 1), ("item", 1)]);
+    assert_eq!(blocks[1].word, "Spaced out a labelled paragraph wraps onto a second line");
+}
+
+#[test]
+fn words_of_each_page_are_in_its_own_coordinates() {
+    let boxes = words("First #pagebreak() Second");
+    let pages: Vec<(&str, usize)> = boxes.iter().map(|word_box| (word_box.word.as_str(), word_box.page)).collect();
+    assert_eq!(pages, [("First", 0), ("Second", 1)]);
+    assert_eq!(boxes[0].y, boxes[1].y);
+}
+
+#[test]
+fn words_joined_across_lines_from_different_sizes_have_mixed_sizes() {
+    let source = "#text(size: 12pt)[exam-] \\ #text(size: 9pt)[ple] same- \\ size";
+    let opts = ExtractOptions { merge_hyphenation: true, ..ExtractOptions::default() };
+    let boxes = extract_word_boxes(source, Path::new("."), &opts).unwrap();
+    let sizes: Vec<(&str, Option<f64>, bool)> =
+        boxes.iter().map(|word_box| (word_box.word.as_str(), word_box.font_size, word_box.mixed_sizes)).collect();
+    assert_eq!(sizes, [("exam-ple", Some(12.0), true), ("same-size", Some(11.0), false)]);
+}
+
+#[test]
+fn words_of_a_line_in_different_sizes_have_the_same_baseline() {
+    let source = "Small #text(size: 20pt)[Large] #text(size: 6pt)[tiny]";
+    let mut heights = Vec::new();
+    for box_mode in [BoxMode::Ascent, BoxMode::CapHeight, BoxMode::XHeight] {
+        let opts = ExtractOptions { include_baseline: true, box_mode, ..ExtractOptions::default() };
+        let boxes = extract_word_boxes(source, Path::new("."), &opts).unwrap();
+        assert_eq!(texts(&boxes), ["Small", "Large", "tiny"]);
+        let baseline = boxes[0].baseline.unwrap();
+        for word_box in &boxes {
+            assert_eq!(word_box.baseline, Some(baseline), "{box_mode:?}");
+            // Only the top of the box depends on the mode.
+            let bottom = baseline - word_box.descender.unwrap();
+            assert!((word_box.y + word_box.height - bottom).abs() < 1e-9, "{box_mode:?}");
+        }
+        heights.push(boxes[1].height);
+    }
+    assert!(heights[0] > heights[1] && heights[1] > heights[2], "{heights:?}");
+}
+
+#[test]
+fn stacked_text_leaves_one_box_after_dedup() {
+    let source = "#place(top + left)[Stacked] #place(top + left)[Stacked] Below";
+    // Without the boxes of the groups of the placed text.
+    let word_texts = |boxes: Vec<WordBox>| -> Vec<String> {
+        boxes.into_iter().filter(|word_box| word_box.kind == "word").map(|word_box| word_box.word).collect()
+    };
+    assert_eq!(word_texts(words(source)), ["Stacked", "Stacked", "Below"]);
+    let opts = ExtractOptions { dedup_epsilon: Some(0.1), ..ExtractOptions::default() };
+    let boxes = extract_word_boxes(source, Path::new("."), &opts).unwrap();
+    assert_eq!(word_texts(boxes), ["Stacked", "Below"]);
+}
+
+#[test]
+fn figure_with_a_transformed_frame_is_a_figure() {
+    let boxes = words("#figure(rotate(10deg, scale(x: 120%)[Turned]))");
+    let groups: Vec<(&str, &str)> = boxes
+        .iter()
+        .filter(|word_box| word_box.kind != "word")
+        .map(|word_box| (word_box.kind.as_str(), word_box.word.as_str()))
+        .collect();
+    assert_eq!(groups, [("figure", "Turned")]);
+    let word = boxes.iter().find(|word_box| word_box.kind == "word").unwrap();
+    assert!(word.quad.is_some());
+}
+
+#[test]
+fn hebrew_words_tile_the_line_from_right_to_left() {
+    let boxes = words("#set text(lang: \"he\")\nשָׁלוֹם עוֹלָם יָפֶה");
+    assert_eq!(texts(&boxes), ["שָׁלוֹם", "עוֹלָם", "יָפֶה"]);
+    for pair in boxes.windows(2) {
+        // Each word is left of the one before it, without overlapping it.
+        assert!(pair[1].x + pair[1].width <= pair[0].x + 1e-6, "{:?}", texts(pair));
+        assert_eq!(pair[0].dir.as_deref(), Some("rtl"));
+    }
+}
+
+#[test]
+fn arabic_inside_an_english_sentence_is_in_logical_order() {
+    let boxes = words("The price is 50 ريال سعودي only today");
+    assert_eq!(texts(&boxes), ["The", "price", "is", "50", "ريال", "سعودي", "only", "today"]);
+    let dirs: Vec<Option<&str>> = boxes.iter().map(|word_box| word_box.dir.as_deref()).collect();
+    let (ltr, rtl) = (Some("ltr"), Some("rtl"));
+    assert_eq!(dirs, [ltr, ltr, ltr, ltr, rtl, rtl, ltr, ltr]);
+
+    // The English words go from left to right around the Arabic ones, which
+    // are read from right to left.
+    let x = |word: &str| boxes.iter().find(|word_box| word_box.word == word).unwrap().x;
+    assert!(x("The") < x("price") && x("price") < x("is") && x("is") < x("50"));
+    assert!(x("50") < x("سعودي") && x("سعودي") < x("ريال") && x("ريال") < x("only"));
+    assert!(x("only") < x("today"));
+}
+
+#[test]
+fn unicode_split_mode_keeps_contractions_and_numbers_whole() {
+    let opts = ExtractOptions { split_mode: SplitMode::Unicode, ..ExtractOptions::default() };
+    let boxes = extract_word_boxes("don't pay 3.14 to co-operate", Path::new("."), &opts).unwrap();
+    assert_eq!(texts(&boxes), ["don’t", "pay", "3.14", "to", "co", "operate"]);
+    // The hyphen between the halves is in neither of them.
+    assert!(boxes[4].x + boxes[4].width < boxes[5].x);
+}
+
+#[test]
+fn cjk_chars_mode_splits_chinese_per_character_and_english_per_word() {
+    let opts = ExtractOptions { split_mode: SplitMode::CjkChars, ..ExtractOptions::default() };
+    let boxes = extract_word_boxes("我们用Typst排版，very nice。", Path::new("."), &opts).unwrap();
+    assert_eq!(texts(&boxes), ["我", "们", "用", "Typst", "排", "版", "very", "nice"]);
+    for pair in boxes.windows(2) {
+        assert!(pair[0].x + pair[0].width <= pair[1].x + 1e-6, "{:?}", texts(pair));
+    }
+}
+
+#[test]
+fn unicode_punctuation_is_split_off_only_when_it_is_a_delimiter() {
+    let source = "“quoted” –dashed– …ellipsed…";
+    let split = |opts: ExtractOptions| {
+        let boxes = extract_word_boxes(source, Path::new("."), &opts).unwrap();
+        boxes.into_iter().map(|word_box| word_box.word).collect::<Vec<_>>()
+    };
+    assert_eq!(split(ExtractOptions::default()), ["“quoted”", "–dashed–", "…ellipsed…"]);
+    let unicode = ExtractOptions { unicode_delimiters: true, ..ExtractOptions::default() };
+    assert_eq!(split(unicode), ["quoted", "dashed", "ellipsed"]);
+    // Only the given characters are delimiters.
+    let some = ExtractOptions { delimiters: "“”…".to_string(), ..ExtractOptions::default() };
+    assert_eq!(split(some), ["quoted", "–dashed–", "ellipsed"]);
+}
+
+#[test]
+fn flipped_quads_keep_the_order_of_their_corners() {
+    let boxes = words("#rotate(30deg)[Turned]");
+    let quad = boxes[0].quad.unwrap();
+    let flipped = boxes[0].flipped(100.0).quad.unwrap();
+    assert_eq!(flipped.map(|(x, _)| x), quad.map(|(x, _)| x));
+    // Twice the signed area, positive for corners that go clockwise with y
+    // pointing down.
+    let area = |quad: [(f64, f64); 4]| {
+        (0..4).map(|i| quad[i].0 * quad[(i + 1) % 4].1 - quad[(i + 1) % 4].0 * quad[i].1).sum::<f64>()
+    };
+    assert!(area(quad) > 0.0 && area(flipped) < 0.0);
+}
+
+#[test]
+fn words_split_from_ligatures_sum_to_the_width_of_the_line() {
+    let source = "difficult offline traffic";
+    // The default font has the ligatures.
+    let glyphs = ExtractOptions { granularity: Granularity::Glyph, ..ExtractOptions::default() };
+    assert!(extract_word_boxes(source, Path::new("."), &glyphs).unwrap().len() < 23);
+    let line = ExtractOptions { granularity: Granularity::Line, ..ExtractOptions::default() };
+    let line_width = extract_word_boxes(source, Path::new("."), &line).unwrap()[0].width;
+    let all = ExtractOptions { include_whitespace: true, include_delimiters: true, ..ExtractOptions::default() };
+    let width = |opts: &ExtractOptions| -> f64 {
+        let boxes = extract_word_boxes(source, Path::new("."), opts).unwrap();
+        boxes.iter().map(|word_box| word_box.width).sum()
+    };
+    assert!((width(&all) - line_width).abs() < 1e-6);
+
+    // The `ffi` and `ffl` ligatures are split between the words and the
+    // delimiter in them.
+    let at_i_and_l = ExtractOptions { delimiters: "il".to_string(), ..all.clone() };
+    let boxes = extract_word_boxes(source, Path::new("."), &at_i_and_l).unwrap();
+    assert_eq!(&texts(&boxes)[..5], ["d", "i", "ff", "i", "cu"]);
+    assert!((width(&at_i_and_l) - line_width).abs() < 1e-6);
+}
+
+#[test]
+fn words_in_scaled_and_rotated_blocks_are_transformed() {
+    let plain = &words("Scaled")[0];
+    let scaled = &words("#scale(200%, reflow: true, block[Scaled])")[0];
+    assert!((scaled.width - 2.0 * plain.width).abs() < 1e-6);
+    assert!((scaled.height - 2.0 * plain.height).abs() < 1e-6);
+    assert_eq!(scaled.rotation, None);
+
+    let rotated = &words("#rotate(45deg, reflow: true, block[Turned])")[0];
+    assert!((rotated.rotation.unwrap() - 45.0).abs() < 1e-6);
+    // The box is the bounding box of the turned corners.
+    let quad = rotated.quad.unwrap();
+    let xs = quad.map(|(x, _)| x);
+    let ys = quad.map(|(_, y)| y);
+    let min = |values: [f64; 4]| values.into_iter().fold(f64::INFINITY, f64::min);
+    let max = |values: [f64; 4]| values.into_iter().fold(f64::NEG_INFINITY, f64::max);
+    assert!((rotated.x - min(xs)).abs() < 1e-6 && (rotated.y - min(ys)).abs() < 1e-6);
+    assert!((rotated.x + rotated.width - max(xs)).abs() < 1e-6);
+    assert!((rotated.y + rotated.height - max(ys)).abs() < 1e-6);
+    // Its top edge goes down to the right.
+    assert!(quad[1].0 > quad[0].0 && quad[1].1 > quad[0].1);
+}
+
+#[test]
+fn inline_and_block_equations_are_one_box_each() {
+    let source = "Let $a + b = c$ hold.\n\n$ x^2 - 1 = 0 $";
+    let boxes = words(source);
+    let kinds: Vec<(&str, &str)> =
+        boxes.iter().map(|word_box| (word_box.word.as_str(), word_box.kind.as_str())).collect();
+    assert_eq!(
+        kinds,
+        [("Let", "word"), ("𝑎 + 𝑏 = 𝑐", "equation"), ("hold", "word"), ("𝑥2 − 1 = 0", "equation")]
+    );
+    // The block equation is centered on a line of its own.
+    assert!(boxes[3].y > boxes[1].y + boxes[1].height && boxes[3].x > boxes[2].x);
+
+    let opts = ExtractOptions { split_math: true, ..ExtractOptions::default() };
+    let split = extract_word_boxes(source, Path::new("."), &opts).unwrap();
+    let equations: Vec<&WordBox> = split.iter().filter(|word_box| word_box.kind == "equation").collect();
+    assert_eq!(equations.len(), 2);
+    // Each symbol is a word of its own within its equation.
+    for word in ["𝑏", "2"] {
+        let symbol = split.iter().find(|word_box| word_box.word == word && word_box.kind == "word").unwrap();
+        assert!(equations.iter().any(|equation| {
+            equation.x <= symbol.x + 1e-6 && symbol.x + symbol.width <= equation.x + equation.width + 1e-6
+        }));
+    }
+}
+
+#[test]
+fn table_cells_have_their_spans() {
+    let source =
+        "#table(columns: 3, table.cell(colspan: 2)[Wide], [C], table.cell(rowspan: 2)[Tall], [E], [F], [H], [I])";
+    let cells = cells(source);
+    let cell = |word: &str, row, col, rowspan, colspan| (word.to_string(), 0, row, col, rowspan, colspan);
+    assert_eq!(
+        cells,
+        [
+            cell("Wide", 0, 0, 1, 2),
+            cell("C", 0, 2, 1, 1),
+            cell("Tall", 1, 0, 2, 1),
+            cell("E", 1, 1, 1, 1),
+            cell("F", 1, 2, 1, 1),
+            cell("H", 2, 1, 1, 1),
+            cell("I", 2, 2, 1, 1),
+        ]
+    );
+}
+
+#[test]
+fn rows_of_a_table_across_pages_are_counted_on() {
+    let rows: Vec<String> = (0..30).map(|row| format!("[Row{row}], [Cell{row}]")).collect();
+    let source = format!("#set page(height: 6cm)\n#table(columns: 2, {})", rows.join(", "));
+    let cells = cells(&source);
+    assert_eq!(cells.len(), 60);
+    assert!(cells.last().unwrap().1 > 0);
+    for (row, pair) in cells.chunks(2).enumerate() {
+        assert_eq!(pair[0].0, format!("Row{row}"));
+        assert_eq!((pair[0].2, pair[0].3), (row, 0));
+        assert_eq!((pair[1].2, pair[1].3), (row, 1));
+    }
+}
+
+#[test]
+fn cells_made_by_code_are_placed_by_their_layout() {
+    let cells = cells("#table(columns: 2, [A], [B], ..range(2).map(i => [Made #i]), [C], [D])");
+    let texts: Vec<(&str, usize, usize)> = cells.iter().map(|cell| (cell.0.as_str(), cell.2, cell.3)).collect();
+    assert_eq!(texts, [("A", 0, 0), ("B", 0, 1), ("Made 0", 1, 0), ("Made 1", 1, 1), ("C", 2, 0), ("D", 2, 1)]);
+}
+
+#[test]
+fn split_modes_treat_decimals_abbreviations_and_hyphens_differently() {
+    let split = |split_mode| {
+        let opts = ExtractOptions { split_mode, ..ExtractOptions::default() };
+        let boxes = extract_word_boxes("3.14 e.g. well-known", Path::new("."), &opts).unwrap();
+        boxes.into_iter().map(|word_box| word_box.word).collect::<Vec<_>>()
+    };
+    assert_eq!(split(SplitMode::Whitespace), ["3.14", "e.g.", "well-known"]);
+    assert_eq!(split(SplitMode::Punctuation), ["3", "14", "e", "g", "well", "known"]);
+    assert_eq!(split(SplitMode::Unicode), ["3.14", "e.g", "well", "known"]);
+}
+
+#[test]
+fn sentences_wrapping_mid_sentence_have_a_rect_per_line() {
+    let source = "#set page(width: 6cm)
+We met Dr. Smith, e.g. at noon, and talked about the weather for a long while. Then we left.";
+    let opts = ExtractOptions { granularity: Granularity::Sentence, ..ExtractOptions::default() };
+    let sentences = extract_word_boxes(source, Path::new("."), &opts).unwrap();
+    // Neither `Dr.` nor `e.g.` ends the first sentence.
+    assert_eq!(
+        texts(&sentences),
+        ["We met Dr. Smith, e.g. at noon, and talked about the weather for a long while.", "Then we left."]
+    );
+    let lines = ExtractOptions { granularity: Granularity::Line, ..ExtractOptions::default() };
+    let lines = extract_word_boxes(source, Path::new("."), &lines).unwrap();
+    assert_eq!(lines.len(), 4);
+    // The first sentence has a rectangle on each of the first three lines,
+    // and the second one, on the last line only, needs none.
+    assert_eq!(sentences[0].rects.len(), 3);
+    assert!(sentences[1].rects.is_empty());
+    for (rect, line) in sentences[0].rects.iter().zip(&lines) {
+        assert!((rect.y - line.y).abs() < 1e-6);
+        assert!((rect.x - line.x).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn images_push_the_paragraphs_below_them_down() {
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("image-shift");
+    std::fs::create_dir_all(root.join("figures")).unwrap();
+    let png = tiny_skia::Pixmap::new(40, 20).unwrap().encode_png().unwrap();
+    std::fs::write(root.join("figures/plot.png"), png).unwrap();
+
+    let first_y = |source: &str| {
+        let source = format!("#set block(spacing: 0pt)\n{source}");
+        extract_word_boxes(&source, &root, &ExtractOptions::default()).unwrap()[0].y
+    };
+    let without = first_y("Below");
+    let with = first_y("#image(\"figures/plot.png\", height: 2cm)\n\nBelow");
+    let height = 2.0 * 72.0 / 2.54;
+    assert!((with - without - height).abs() < 1e-3, "{without} {with}");
+}
+
+/// Compiles `main.typ` of a virtual file system given as JSON.
+fn compile_vfs(json: &serde_json::Value) -> Result<PagedDocument, ExtractError> {
+    let files = parse_vfs(&json.to_string()).unwrap();
+    let main = VirtualPath::new("main.typ");
+    let source = files.iter().find(|(path, _)| *path == main).unwrap().1.clone();
+    let world = WorldBuilder::new(&[])
+        .build(String::new(), String::from_utf8(source).unwrap())
+        .with_main(main)
+        .with_vfs(files);
+    compile(&world).output
+}
+
+#[test]
+fn virtual_file_systems_serve_includes_and_binary_files() {
+    let png = tiny_skia::Pixmap::new(40, 20).unwrap().encode_png().unwrap();
+    let document = compile_vfs(&serde_json::json!({
+        "main.typ": "#image(\"logo.png\", height: 1cm)\n#include \"chapters/one.typ\"",
+        "chapters/one.typ": "Chapter one",
+        "logo.png": { "base64": STANDARD.encode(png) },
+    }))
+    .unwrap();
+    let boxes = box_extractor::collect_word_boxes(&document, &ExtractOptions::default());
+    assert_eq!(texts(&boxes), ["Chapter", "one"]);
+    // The text is below the image.
+    assert!(boxes[0].y > 1.0 * 72.0 / 2.54, "{}", boxes[0].y);
+
+    // Files that are missing from the file system are not read from disk.
+    let error = compile_vfs(&serde_json::json!({ "main.typ": "#include \"chapters/two.typ\"" })).unwrap_err();
+    let ExtractError::Compile(diagnostics) = &error;
+    assert!(diagnostics.iter().any(|diagnostic| diagnostic.message.contains("not found")), "{error}");
+}
+
+/// The fonts the text of a document was set in.
+fn fonts(source: &str) -> Vec<FontUsage> {
+    let world = WorldBuilder::new(&[]).build(String::new(), source.into());
+    fonts_used(&compile(&world).output.unwrap(), &world)
+}
+
+#[test]
+fn fonts_used_lists_each_face_once_and_notes_missing_glyphs() {
+    let used = fonts(r#"Hello #text(font: "DejaVu Sans Mono")[code] world #text(font: "DejaVu Sans Mono")[more]"#);
+    let families: Vec<&str> = used.iter().map(|font| font.family.as_str()).collect();
+    assert_eq!(families, ["Libertinus Serif", "DejaVu Sans Mono"]);
+    assert!(used.iter().all(|font| font.coverage.is_none() && font.source == "embedded"));
+
+    // Without fallback, the glyph that the font lacks is its `.notdef`.
+    let used = fonts("#set text(fallback: false)\nHello 𓀀");
+    assert_eq!(used.len(), 1);
+    assert_eq!(used[0].family, "Libertinus Serif");
+    assert!(used[0].coverage.as_deref().unwrap().contains(".notdef"));
+}
+
+#[test]
+fn words_have_the_weight_and_style_of_their_font() {
+    let boxes = words("Plain *bold* _italic_ *_both_*");
+    let faces: Vec<(&str, Option<u16>, Option<&str>)> = boxes
+        .iter()
+        .map(|word_box| (word_box.word.as_str(), word_box.weight, word_box.style.as_deref()))
+        .collect();
+    assert_eq!(
+        faces,
+        [
+            ("Plain", Some(400), Some("normal")),
+            ("bold", Some(700), Some("normal")),
+            ("italic", Some(400), Some("italic")),
+            ("both", Some(700), Some("italic")),
+        ]
+    );
+}
+
+#[test]
+fn words_have_the_language_and_script_they_were_set_in() {
+    let source = "Hello there.\n\n#[#set text(lang: \"de\")\nGuten Tag.]\n\nBye now.";
+    let opts = ExtractOptions { include_lang: true, ..ExtractOptions::default() };
+    let boxes = extract_word_boxes(source, Path::new("."), &opts).unwrap();
+    let langs: Vec<(&str, Option<&str>)> =
+        boxes.iter().map(|word_box| (word_box.word.as_str(), word_box.lang.as_deref())).collect();
+    assert_eq!(
+        langs,
+        [
+            ("Hello", Some("en")),
+            ("there", Some("en")),
+            ("Guten", Some("de")),
+            ("Tag", Some("de")),
+            ("Bye", Some("en")),
+            ("now", Some("en")),
+        ]
+    );
+    assert!(boxes.iter().all(|word_box| word_box.script.as_deref() == Some("Latin")));
+}
+
+#[test]
+fn unicode_split_puts_latin_and_cjk_words_on_their_clusters() {
+    let source = "Hello 世界、こんにちは world";
+    let unicode = ExtractOptions { split_mode: SplitMode::Unicode, ..ExtractOptions::default() };
+    let boxes = extract_word_boxes(source, Path::new("."), &unicode).unwrap();
+    // Ideographs and kana are words of their own, and the comma is none.
+    assert_eq!(texts(&boxes), ["Hello", "世", "界", "こ", "ん", "に", "ち", "は", "world"]);
+
+    // Each word spans the clusters of its characters, from the first to the
+    // last one, which are found in order in the boxes of the characters.
+    let chars = ExtractOptions { granularity: Granularity::Char, ..ExtractOptions::default() };
+    let chars = extract_word_boxes(source, Path::new("."), &chars).unwrap();
+    let mut rest = chars.as_slice();
+    for word_box in &boxes {
+        let start = rest.iter().position(|char_box| word_box.word.starts_with(char_box.word.as_str())).unwrap();
+        let (word_chars, after) = rest[start..].split_at(word_box.word.chars().count());
+        assert_eq!(word_chars.iter().map(|char_box| char_box.word.as_str()).collect::<String>(), word_box.word);
+        let (first, last) = (&word_chars[0], &word_chars[word_chars.len() - 1]);
+        assert!((word_box.x - first.x).abs() < 1e-6, "{}", word_box.word);
+        assert!((word_box.x + word_box.width - last.x - last.width).abs() < 1e-6, "{}", word_box.word);
+        rest = after;
+    }
+}